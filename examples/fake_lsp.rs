@@ -0,0 +1,233 @@
+//! a tiny language server for trying out the editor's client without a real one.
+//! it keeps the documents it is sent up to date, and knows a few tricks:
+//!
+//! - `TODO` is reported as a warning and `FIXME` as an error
+//! - hover tells the word under the caret, the UTF-16 column the server got and the line it has
+//! - definition jumps to the first line with `fn <word>`, `let <word>` or `struct <word>`
+//! - completion suggests the words of the document
+//!
+//! build it with `cargo build --example fake_lsp` and point the editor at it, like
+//! `language_server = "target/debug/examples/fake_lsp"` in `settings.toml`
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, BufReader, Write},
+};
+
+use serde_json::{json, Value};
+
+fn main() {
+    let mut reader = BufReader::new(io::stdin().lock());
+    let mut documents: HashMap<String, String> = HashMap::new();
+    while let Some(message) = read_message(&mut reader) {
+        let method = message.get("method").and_then(Value::as_str).unwrap_or_default();
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+        let uri = params.pointer("/textDocument/uri").and_then(Value::as_str).unwrap_or_default().to_string();
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": 2,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "completionProvider": {},
+                },
+                "serverInfo": { "name": "fake_lsp" },
+            }),
+            "textDocument/didOpen" => {
+                let text = params.pointer("/textDocument/text").and_then(Value::as_str).unwrap_or_default();
+                documents.insert(uri.clone(), text.to_string());
+                publish_diagnostics(&uri, &documents[&uri]);
+                continue;
+            }
+            "textDocument/didChange" => {
+                let document = documents.entry(uri.clone()).or_default();
+                for change in params.get("contentChanges").and_then(Value::as_array).into_iter().flatten() {
+                    apply_change(document, change);
+                }
+                publish_diagnostics(&uri, document);
+                continue;
+            }
+            "textDocument/hover" => {
+                let document = documents.get(&uri).map_or("", String::as_str);
+                let (line, character) = position(&params);
+                // the line as the server has it, to check that changes arrive right
+                let text = document.lines().nth(line).unwrap_or_default();
+                match word_at(document, line, character) {
+                    Some(word) => json!({
+                        "contents": {
+                            "kind": "plaintext",
+                            "value": format!("`{word}` at line {}, UTF-16 column {character}: {text}", line + 1),
+                        },
+                    }),
+                    None => Value::Null,
+                }
+            }
+            "textDocument/definition" => {
+                let document = documents.get(&uri).map_or("", String::as_str);
+                let (line, character) = position(&params);
+                word_at(document, line, character)
+                    .and_then(|word| definition(document, &word))
+                    .map_or(Value::Null, |(line, character)| {
+                        json!({
+                            "uri": uri,
+                            "range": {
+                                "start": { "line": line, "character": character },
+                                "end": { "line": line, "character": character },
+                            },
+                        })
+                    })
+            }
+            "textDocument/completion" => {
+                let document = documents.get(&uri).map_or("", String::as_str);
+                let mut words: Vec<&str> = words(document).map(|(_, _, word)| word).collect();
+                words.sort_unstable();
+                words.dedup();
+                json!(words.iter().map(|word| json!({ "label": word })).collect::<Vec<_>>())
+            }
+            "shutdown" => Value::Null,
+            "exit" => return,
+            // other notifications need no answer
+            _ if message.get("id").is_none() => continue,
+            _ => {
+                let id = message["id"].clone();
+                send(&json!({ "jsonrpc": "2.0", "id": id, "error": { "code": -32601, "message": format!("{method} is not supported") } }));
+                continue;
+            }
+        };
+        send(&json!({ "jsonrpc": "2.0", "id": message["id"], "result": result }));
+    }
+}
+
+fn read_message(reader: &mut impl BufRead) -> Option<Value> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).ok()? == 0 {
+            return None;
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse().ok();
+        }
+    }
+    let mut body = vec![0; length?];
+    reader.read_exact(&mut body).ok()?;
+    serde_json::from_slice(&body).ok()
+}
+
+fn send(message: &Value) {
+    let body = message.to_string();
+    let mut stdout = io::stdout().lock();
+    let _ = write!(stdout, "Content-Length: {}\r\n\r\n{body}", body.len());
+    let _ = stdout.flush();
+}
+
+fn position(params: &Value) -> (usize, usize) {
+    let number = |key: &str| params.pointer(&format!("/position/{key}")).and_then(Value::as_u64).unwrap_or(0) as usize;
+    (number("line"), number("character"))
+}
+
+/// byte offset of a line and UTF-16 column in the document
+fn offset(document: &str, line: usize, character: usize) -> usize {
+    let mut start = 0;
+    for _ in 0..line {
+        match document[start..].find('\n') {
+            Some(index) => start += index + 1,
+            None => return document.len(),
+        }
+    }
+    let text = &document[start..];
+    let text = &text[..text.find('\n').unwrap_or(text.len())];
+    let mut units = 0;
+    for (index, c) in text.char_indices() {
+        if units >= character {
+            return start + index;
+        }
+        units += c.len_utf16();
+    }
+    start + text.len()
+}
+
+fn apply_change(document: &mut String, change: &Value) {
+    let text = change.get("text").and_then(Value::as_str).unwrap_or_default();
+    let Some(range) = change.get("range") else {
+        *document = text.to_string();
+        return;
+    };
+    let at = |key: &str| {
+        let number = |field: &str| range.pointer(&format!("/{key}/{field}")).and_then(Value::as_u64).unwrap_or(0) as usize;
+        offset(document, number("line"), number("character"))
+    };
+    let (start, end) = (at("start"), at("end"));
+    document.replace_range(start..end.max(start), text);
+}
+
+/// words of the document with their line and UTF-16 column
+fn words(document: &str) -> impl Iterator<Item = (usize, usize, &str)> {
+    document.lines().enumerate().flat_map(|(line, text)| {
+        let mut ret = Vec::new();
+        let mut start = None;
+        let mut units = 0;
+        let mut start_units = 0;
+        for (index, c) in text.char_indices().chain([(text.len(), ' ')]) {
+            let is_word = c.is_alphanumeric() || c == '_';
+            match (is_word, start) {
+                (true, None) => {
+                    start = Some(index);
+                    start_units = units;
+                }
+                (false, Some(from)) => {
+                    ret.push((line, start_units, &text[from..index]));
+                    start = None;
+                }
+                _ => (),
+            }
+            units += c.len_utf16();
+        }
+        ret
+    })
+}
+
+fn word_at(document: &str, line: usize, character: usize) -> Option<String> {
+    words(document)
+        .find(|(at, column, word)| *at == line && (*column..=column + word.encode_utf16().count()).contains(&character))
+        .map(|(_, _, word)| word.to_string())
+}
+
+fn definition(document: &str, name: &str) -> Option<(usize, usize)> {
+    let mut previous = "";
+    for (line, column, word) in words(document) {
+        if word == name && matches!(previous, "fn" | "let" | "struct") {
+            return Some((line, column));
+        }
+        previous = word;
+    }
+    None
+}
+
+fn publish_diagnostics(uri: &str, document: &str) {
+    let diagnostics: Vec<Value> = words(document)
+        .filter_map(|(line, column, word)| {
+            let (severity, message) = match word {
+                "TODO" => (2, "TODO left in the code"),
+                "FIXME" => (1, "FIXME left in the code"),
+                _ => return None,
+            };
+            let end = column + word.encode_utf16().count();
+            Some(json!({
+                "range": { "start": { "line": line, "character": column }, "end": { "line": line, "character": end } },
+                "severity": severity,
+                "source": "fake_lsp",
+                "message": message,
+            }))
+        })
+        .collect();
+    send(&json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    }));
+}
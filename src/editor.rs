@@ -3,18 +3,119 @@ mod view;
 mod buffer;
 mod command;
 mod line;
+mod commandbar;
+mod statusbar;
+mod args;
+mod excommand;
+mod highlight;
+mod modal;
+mod config;
+mod keymap;
+mod filetype;
+mod settings;
+mod syntax;
+mod theme;
+mod bracket;
+mod history;
+mod macros;
+mod swap;
+mod diff;
+mod scheduler;
+mod pager;
+mod comment;
+mod sort;
+mod shell;
+mod quickfix;
+mod lsp;
+mod diagnostic;
+mod finder;
 
+use args::Args;
 use command::Command;
-use crossterm::event::{read, Event};
-use view::View;
-use std::{io::Error as IoE, panic::{set_hook, take_hook}};
-use terminal::Terminal;
+use commandbar::CommandBar;
+use crossterm::event::{poll, read, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use excommand::{ExCommand, Substitution};
+use finder::Finder;
+use keymap::{Keymap, Resolution};
+use lsp::{Client, Position, Reply};
+use macros::Macros;
+use modal::Modal;
+use pager::Pager;
+use quickfix::Quickfix;
+use regex::Regex;
+use scheduler::{Scheduler, Task};
+use settings::{Settings, SettingsFile};
+use sort::Sort;
+use statusbar::StatusBar;
+use swap::Found;
+use view::{Location, Register, View};
+use std::{collections::HashMap, fs, io::Error as IoE, ops::Range, panic::{set_hook, take_hook}, path::Path, time::Duration};
+use terminal::{Size, Terminal};
+use theme::Theme;
+
+/// what the command bar is collecting user input for
+#[derive(Clone, Copy)]
+enum Prompt {
+    GotoLine,
+    CommandLine,
+    /// what to do with unsaved changes found in a swap file, answered with a single key
+    Recover,
+    /// what to do with a file changed on disk while the buffer has unsaved changes, answered with a single key
+    Reload,
+    Search {
+        backward: bool,
+    },
+    /// a file to open, picked in the finder
+    Find,
+}
+
+/// time without input after which the current file is checked for changes on disk, and again every time after
+const DISK_CHECK: Duration = Duration::from_secs(1);
+
+/// time without input after which unsaved changes are written to swap files
+const SWAP_FLUSH: Duration = Duration::from_secs(4);
+
+/// time between reads of the files being followed
+const FOLLOW_POLL: Duration = Duration::from_millis(250);
+
+const READ_ONLY: &str = "E21: Cannot make changes, buffer is read-only";
 
 pub struct Editor{
     // whether use entered ctrl+q
     quit: bool,
-    /// contents shown on the screen
-    view: View,
+    /// whether the terminal was set up, to be restored when done
+    terminal: bool,
+    /// one view for each opened file. there is always at least one
+    views: Vec<View>,
+    /// index of the view shown on the screen
+    current: usize,
+    /// the row above the command bar
+    status_bar: StatusBar,
+    /// the last row of the screen
+    command_bar: CommandBar,
+    /// set while the command bar is collecting user input
+    prompt: Option<Prompt>,
+    terminal_size: Size,
+    /// vi-like modal layer, if enabled
+    modal: Option<Modal>,
+    /// less-like layer for reading, if enabled. it goes before the modal one
+    pager: Option<Pager>,
+    /// pattern of the last search and whether it went backward
+    search: Option<(Regex, bool)>,
+    /// errors of the last build
+    quickfix: Quickfix,
+    /// the file picker, shown over the views while it is open
+    finder: Option<Finder>,
+    /// language servers by file type. `None` for the ones that failed, so that they are not
+    /// started again and again
+    language_servers: HashMap<String, Option<Client>>,
+    /// text taken by the last delete or yank, shared among views
+    register: Register,
+    keymap: Keymap,
+    settings: SettingsFile,
+    theme: Theme,
+    macros: Macros,
+    scheduler: Scheduler,
 }
 
 impl Editor {
@@ -24,34 +125,112 @@ impl Editor {
             let _ = Terminal::terminate();
             current_hook(panic_info);
         }));
-        if Terminal::size().unwrap().height < 2 {
+        if Terminal::size().unwrap().height < 3 {
             panic!("Too small terminal!");
         }
 
         Terminal::initialize()?;
-        let mut view = View::default();
-        let args: Vec<String> = std::env::args().collect();
-        if let Some(path) = args.get(1) {
-            view.load_file(path);
+        let mut editor = Self::new();
+        editor.terminal = true;
+        let (keymap, mut errors) = Keymap::load();
+        editor.keymap = keymap;
+        let (settings, settings_errors) = SettingsFile::load();
+        editor.settings = settings;
+        errors.extend(settings_errors);
+        let (macros, macro_errors) = Macros::load();
+        editor.macros = macros;
+        errors.extend(macro_errors);
+        if let Err(e) = editor.load_theme() {
+            errors.push(e);
+        }
+        if let Some(first) = errors.first() {
+            let more = match errors.len() {
+                1 => String::new(),
+                n => format!(" (and {} more)", n - 1),
+            };
+            editor.command_bar.set_message(&format!("{first}{more}"));
+        }
+        editor.resize(Terminal::size()?);
+        let args = Args::parse(std::env::args().skip(1));
+        if args.vi {
+            editor.settings.global.vi = true;
+        }
+        if args.read_only || args.pager {
+            editor.settings.global.read_only = true;
         }
-        Ok(Self {
+        if args.pager {
+            editor.settings.global.pager = true;
+        }
+        editor.sync_modal();
+        let settings = editor.settings.for_file(args.path.as_deref());
+        editor.view_mut().set_settings(settings);
+        if let Some(path) = &args.path {
+            match editor.view_mut().load_file(path) {
+                Ok(()) => editor.open_swap(),
+                Err(e) => editor.command_bar.set_message(&format!("Error when loading file: {e}")),
+            }
+        }
+        if let Some(location) = args.location {
+            editor.view_mut().goto(location);
+        }
+        if args.follow {
+            editor.toggle_follow();
+        }
+        Ok(editor)
+    }
+    /// an editor with one empty view and the built-in settings, not yet drawing on the terminal
+    fn new() -> Self {
+        Self {
             quit: false,
-            view,
-        })
+            terminal: false,
+            views: vec![View::default()],
+            current: 0,
+            status_bar: StatusBar::default(),
+            command_bar: CommandBar::default(),
+            prompt: None,
+            terminal_size: Size::default(),
+            modal: None,
+            pager: None,
+            search: None,
+            quickfix: Quickfix::default(),
+            finder: None,
+            language_servers: HashMap::new(),
+            register: Register::default(),
+            keymap: Keymap::default(),
+            settings: SettingsFile::default(),
+            theme: Theme::default(),
+            macros: Macros::default(),
+            scheduler: Scheduler::default(),
+        }
     }
     /// start functioning
     pub fn run(&mut self) {
         self.repl();
     }
-    /// read-eval-print-loop
+    /// read-eval-print-loop. waits for input only until a timer is due or a worker is done
     fn repl(&mut self) {
+        self.scheduler.schedule(DISK_CHECK, Task::CheckDisk);
         loop {
+            for task in self.scheduler.take_due() {
+                self.run_task(task);
+            }
+            self.sync_language_servers();
+            self.schedule_message_timeout();
             self.refresh_screen();
             if self.quit {
                 break;
             }
+            match poll(self.scheduler.timeout()) {
+                Ok(true) => (),
+                Ok(false) | Err(_) => continue,
+            }
             match read() {
-                Ok(event) => self.evaluate_event(event),
+                Ok(event) => {
+                    self.evaluate_event(event);
+                    self.scheduler.schedule(DISK_CHECK, Task::CheckDisk);
+                    self.scheduler.schedule(SWAP_FLUSH, Task::FlushSwap);
+                    self.schedule_autosave();
+                }
                 Err(e) => {
                     #[cfg(debug_assertions)]
                     {
@@ -61,38 +240,1359 @@ impl Editor {
             }
         }
     }
-    /// evaluate an event and distribute to corresponding method
+    fn run_task(&mut self, task: Task) {
+        match task {
+            Task::ClearMessage => self.command_bar.expire_message(self.message_timeout()),
+            Task::CheckDisk => {
+                self.check_disk();
+                self.scheduler.schedule(DISK_CHECK, Task::CheckDisk);
+            }
+            Task::FlushSwap => {
+                for view in &mut self.views {
+                    if let Err(e) = view.update_swap(true) {
+                        self.command_bar.set_message(&e);
+                    }
+                }
+            }
+            Task::Autosave => self.autosave(|settings| settings.autosave > 0),
+            Task::Follow => {
+                for view in &mut self.views {
+                    if let Err(e) = view.follow() {
+                        self.command_bar.set_message(&e);
+                    }
+                }
+                if self.views.iter().any(View::is_following) {
+                    self.scheduler.schedule(FOLLOW_POLL, Task::Follow);
+                }
+            }
+            Task::Filtered { view, version, range, command, output } => {
+                self.finish_filter(view, version, range, &command, output);
+            }
+            Task::Built { command, result } => self.finish_build(&command, result),
+            Task::Listed(files) => {
+                if let Some(finder) = &mut self.finder {
+                    finder.set_files(files);
+                }
+            }
+            Task::Language { server, message: Some(message) } => self.language_message(server, &message),
+            Task::Language { server, message: None } => {
+                let stopped = self.language_servers.iter_mut().find(|(_, client)| client.as_ref().is_some_and(|client| client.id == server));
+                if let Some((filetype, client)) = stopped {
+                    *client = None;
+                    self.command_bar.set_message(&format!("Language server for {filetype} stopped"));
+                }
+            }
+            Task::ShowDiff(lines) => {
+                self.views.push(View::scratch(&lines));
+                self.switch_view(self.views.len() - 1);
+                self.resize(self.terminal_size);
+                self.command_bar.set_message(":bp goes back, :e! there reloads the file");
+            }
+        }
+    }
+    /// save after the shortest autosave delay of the opened files
+    fn schedule_autosave(&mut self) {
+        let delay = self.views.iter().map(|view| view.settings().autosave).filter(|delay| *delay > 0).min();
+        if let Some(delay) = delay {
+            self.scheduler.schedule(Duration::from_secs(delay as u64), Task::Autosave);
+        }
+    }
+    /// save every buffer with unsaved changes whose settings ask for it. files changed on disk
+    /// are left alone, so that the changes made there are not lost
+    fn autosave(&mut self, wanted: impl Fn(&Settings) -> bool) {
+        let mut errors = Vec::new();
+        for view in &mut self.views {
+            if !view.is_dirty() || !wanted(view.settings()) {
+                continue;
+            }
+            let Some(name) = view.file_name().map(str::to_string) else {
+                continue;
+            };
+            if view.changed_on_disk() {
+                errors.push(format!("\"{name}\" changed on disk, not autosaved"));
+            } else if let Err(e) = view.save() {
+                errors.push(format!("Autosave of \"{name}\" failed: {e}"));
+            }
+        }
+        if let Some(first) = errors.first() {
+            let more = match errors.len() {
+                1 => String::new(),
+                n => format!(" (and {} more)", n - 1),
+            };
+            self.command_bar.set_message(&format!("{first}{more}"));
+        }
+    }
+    /// clear the message on the command bar once it has been shown long enough
+    fn schedule_message_timeout(&mut self) {
+        let timeout = self.message_timeout();
+        if let Some(shown_at) = self.command_bar.shown_at().filter(|_| !timeout.is_zero()) {
+            self.scheduler.schedule_at(shown_at + timeout, Task::ClearMessage);
+        }
+    }
+    fn message_timeout(&self) -> Duration {
+        Duration::from_secs(self.settings.global.message_timeout as u64)
+    }
+    /// evaluate an event as one change of the current view, so that it is undone as a whole
     fn evaluate_event(&mut self, event: Event) {
-        match Command::try_from(event) {
-            Ok(command) => {
-                if matches!(command, Command::Quit) {
-                    self.quit = true;
-                } else {
-                    self.view.command_handler(command);
+        let current = self.current;
+        self.view_mut().begin_change();
+        self.dispatch_event(event);
+        // the view may have been replaced, e.g. by `:e`, which starts a fresh history anyway
+        if let Some(view) = self.views.get_mut(current) {
+            view.end_change();
+            if let Err(e) = view.update_swap(false) {
+                self.command_bar.set_message(&e);
+            }
+        }
+    }
+    /// distribute an event to corresponding method
+    fn dispatch_event(&mut self, event: Event) {
+        match event {
+            Event::FocusLost => return self.autosave(|settings| settings.autosave_on_focus_lost),
+            // files may have been changed elsewhere meanwhile
+            Event::FocusGained => return self.check_disk(),
+            _ => (),
+        }
+        if self.prompt.is_some() {
+            if let Event::Key(key) = event {
+                self.evaluate_prompt_key(key);
+                return;
+            }
+        }
+        // Enter in the list of errors jumps to the one under the caret
+        if let Event::Key(KeyEvent { code: KeyCode::Enter, kind: KeyEventKind::Press, .. }) = event {
+            if self.quickfix.list_view == Some(self.current) {
+                let index = self.view().status().current_line;
+                if let Err(e) = self.jump_to_error(index) {
+                    self.command_bar.set_message(&e);
                 }
-            },
+                return;
+            }
+        }
+        if let Some(pager) = &self.pager {
+            for command in pager.handle(event, &mut self.keymap) {
+                self.handle_command(command);
+            }
+            return;
+        }
+        if let Some(modal) = &mut self.modal {
+            modal.set_recording(self.macros.recording().is_some());
+            for command in modal.handle(event, &mut self.keymap) {
+                self.handle_command(command);
+            }
+            return;
+        }
+        if let Event::Key(key) = event {
+            if let Resolution::Command(command) = self.keymap.resolve(key) {
+                self.handle_command(command);
+            }
+            return;
+        }
+        match Command::try_from(event) {
+            Ok(command) => self.handle_command(command),
             Err(_e) => {
                 // #[cfg(debug_assertions)]
                 // panic!("Unsupported command: {e}");
             }
         }
     }
+    /// editor-level commands are handled here, the rest goes to the current view
+    fn handle_command(&mut self, command: Command) {
+        if command.edits() && self.is_read_only() {
+            self.fail(READ_ONLY);
+            return;
+        }
+        self.macros.record(&command);
+        let view = &mut self.views[self.current];
+        if !command.keeps_cursors() {
+            view.single_cursor();
+        }
+        let taken = match command {
+            Command::Quit => {
+                if let Err(e) = self.quit(false) {
+                    self.fail(&e);
+                }
+                None
+            }
+            Command::GotoLine => {
+                self.start_prompt(Prompt::GotoLine);
+                None
+            }
+            Command::FindFile => {
+                self.finder = Some(Finder::default());
+                self.scheduler.spawn(|| Task::Listed(finder::list_files(".")));
+                self.start_prompt(Prompt::Find);
+                None
+            }
+            Command::Ex => {
+                self.start_prompt(Prompt::CommandLine);
+                None
+            }
+            Command::Resize(size) => {
+                self.resize(size);
+                None
+            }
+            Command::Execute(line) => {
+                if let Err(e) = self.execute_ex(&line) {
+                    self.fail(&e);
+                }
+                None
+            }
+            Command::Follow => {
+                self.toggle_follow();
+                None
+            }
+            Command::Build => {
+                self.build("");
+                None
+            }
+            Command::Hover | Command::GotoDefinition | Command::Complete => {
+                if let Err(e) = self.ask_language_server(&command) {
+                    self.fail(&e);
+                }
+                None
+            }
+            Command::NextError { backward } => {
+                let result = self.quickfix.neighbour(backward).and_then(|index| self.jump_to_error(index));
+                if let Err(e) = result {
+                    self.fail(&e);
+                }
+                None
+            }
+            Command::StartSearch { backward } => {
+                self.start_prompt(Prompt::Search { backward });
+                None
+            }
+            Command::Search { pattern, backward } => {
+                if let Err(e) = self.search(&pattern, backward) {
+                    self.fail(&e);
+                }
+                None
+            }
+            Command::SearchNext { reverse } => {
+                if let Err(e) = self.search_next(reverse) {
+                    self.fail(&e);
+                }
+                None
+            }
+            Command::Undo => {
+                if !view.undo() {
+                    self.command_bar.set_message("Already at oldest change");
+                }
+                None
+            }
+            Command::Redo => {
+                if !view.redo() {
+                    self.command_bar.set_message("Already at newest change");
+                }
+                None
+            }
+            Command::RecordMacro(register) => {
+                if self.macros.recording().is_some() {
+                    self.stop_recording();
+                } else if let Err(e) = self.macros.start_recording(register) {
+                    self.fail(&e);
+                }
+                None
+            }
+            Command::StopRecording => {
+                self.stop_recording();
+                None
+            }
+            Command::PlayMacro { register, count } => {
+                if let Err(e) = self.play_macro(register, count) {
+                    self.fail(&e);
+                }
+                None
+            }
+            Command::Operate { operator, direction, count } => view.operate(operator, direction, count),
+            Command::OperateLines { operator, count } => view.operate_lines(operator, count),
+            Command::OperateSelection(operator) => view.operate_selection(operator),
+            Command::Paste { before } => {
+                view.paste(&self.register, before);
+                None
+            }
+            Command::ToggleComment { block } => {
+                if let Err(e) = view.toggle_comment(block) {
+                    self.fail(&e);
+                }
+                None
+            }
+            _ => {
+                view.command_handler(command);
+                None
+            }
+        };
+        if let Some(register) = taken {
+            self.register = register;
+        }
+    }
+    /// whether the current buffer refuses changes. the pager never changes anything
+    fn is_read_only(&self) -> bool {
+        self.view().settings().read_only || self.pager.is_some()
+    }
+    fn check_modifiable(&self) -> Result<(), String> {
+        if self.is_read_only() {
+            return Err(READ_ONLY.to_string());
+        }
+        Ok(())
+    }
+    fn stop_recording(&mut self) {
+        if let Some(register) = self.macros.stop_recording() {
+            self.command_bar.set_message(&format!("Recorded macro @{register}"));
+        }
+    }
+    /// play the commands of a macro `count` times. being part of the event that started it,
+    /// they are undone as one change
+    fn play_macro(&mut self, register: char, count: usize) -> Result<(), String> {
+        let commands = self.macros.start_playing(register)?;
+        'playing: for _ in 0..count {
+            for command in &commands {
+                self.handle_command(command.clone());
+                if self.macros.failed() {
+                    break 'playing;
+                }
+            }
+        }
+        self.macros.end_playing();
+        Ok(())
+    }
+    /// show why a command failed. like in vi, a failing command stops the macros playing
+    fn fail(&mut self, message: &str) {
+        self.command_bar.set_message(message);
+        self.macros.fail();
+    }
+    /// the last two rows belong to the status bar and the command bar, the rest to the views
+    fn resize(&mut self, size: Size) {
+        self.terminal_size = size;
+        let Size { width, height } = size;
+        for view in &mut self.views {
+            view.resize(Size {
+                width,
+                height: height.saturating_sub(2),
+            });
+        }
+        self.status_bar.resize(height.saturating_sub(2), width);
+        self.command_bar.resize(height.saturating_sub(1), width);
+    }
+    fn view(&self) -> &View {
+        &self.views[self.current]
+    }
+    fn view_mut(&mut self) -> &mut View {
+        &mut self.views[self.current]
+    }
+
+    // region: prompting
+
+    fn start_prompt(&mut self, prompt: Prompt) {
+        let text = match prompt {
+            Prompt::GotoLine => "Go to line[:col]: ",
+            Prompt::CommandLine => ":",
+            Prompt::Search { backward: false } => "/",
+            Prompt::Search { backward: true } => "?",
+            Prompt::Find => "Find file: ",
+            Prompt::Recover | Prompt::Reload => "",
+        };
+        self.command_bar.start_prompt(text);
+        self.prompt = Some(prompt);
+    }
+    /// keys typed while prompting edit the command bar instead of the buffer
+    fn evaluate_prompt_key(&mut self, key: KeyEvent) {
+        let KeyEvent { code, modifiers, kind, .. } = key;
+        if kind != KeyEventKind::Press {
+            return;
+        }
+        match self.prompt {
+            Some(Prompt::Recover) => return self.answer_recovery(code),
+            Some(Prompt::Reload) => return self.answer_reload(code),
+            _ => (),
+        }
+        let control = modifiers.contains(KeyModifiers::CONTROL);
+        match code {
+            KeyCode::Esc => {
+                self.command_bar.end_prompt();
+                self.prompt = None;
+                self.close_finder();
+            }
+            KeyCode::Enter => self.submit_prompt(),
+            KeyCode::Backspace => self.command_bar.delete_backward(),
+            KeyCode::Char(c) if !control => self.command_bar.insert(c),
+            // Up and Down, or Ctrl+P and Ctrl+N like in a shell, pick among the files found
+            KeyCode::Up | KeyCode::Down | KeyCode::Char('p' | 'n') => {
+                if let Some(finder) = &mut self.finder {
+                    finder.move_selection(matches!(code, KeyCode::Up | KeyCode::Char('p')));
+                }
+            }
+            _ => (),
+        }
+        if let Some(finder) = &mut self.finder {
+            finder.set_query(self.command_bar.value());
+        }
+    }
+    /// stop showing the finder, the views under it are drawn again
+    fn close_finder(&mut self) {
+        if self.finder.take().is_some() {
+            self.resize(self.terminal_size);
+        }
+    }
+    fn submit_prompt(&mut self) {
+        let value = self.command_bar.end_prompt();
+        let Some(prompt) = self.prompt.take() else {
+            return;
+        };
+        // recorded as commands that do not prompt, so that macros can replay them
+        match prompt {
+            Prompt::GotoLine => match Location::parse(&value) {
+                Some(location) => self.handle_command(Command::Goto(location)),
+                None => self.command_bar.set_message(&format!("Invalid line: {value}")),
+            },
+            Prompt::CommandLine => self.handle_command(Command::Execute(value)),
+            Prompt::Search { backward } => self.handle_command(Command::Search { pattern: value, backward }),
+            Prompt::Find => {
+                let path = self.finder.as_ref().and_then(Finder::selected_path).map(str::to_string);
+                self.close_finder();
+                match path {
+                    Some(path) => self.handle_command(Command::Execute(format!("edit {path}"))),
+                    None => self.command_bar.set_message(&format!("No file matches \"{value}\"")),
+                }
+            }
+            Prompt::Recover | Prompt::Reload => (),
+        }
+    }
+
+    // region: searching
+
+    /// jump to the next match of a regex, or the previous one if `backward`.
+    /// an empty pattern searches for the last one again
+    fn search(&mut self, pattern: &str, backward: bool) -> Result<(), String> {
+        let regex = if pattern.is_empty() {
+            self.search.as_ref().map(|(regex, _)| regex.clone()).ok_or_else(|| "E35: No previous regular expression".to_string())?
+        } else {
+            Regex::new(pattern).map_err(|_| format!("E383: Invalid search string: {pattern}"))?
+        };
+        self.search = Some((regex.clone(), backward));
+        self.jump_to_match(&regex, backward)
+    }
+    /// `n` and `N`, search again in the same direction or the opposite one
+    fn search_next(&mut self, reverse: bool) -> Result<(), String> {
+        let (regex, backward) = self.search.clone().ok_or_else(|| "E35: No previous regular expression".to_string())?;
+        self.jump_to_match(&regex, backward != reverse)
+    }
+    /// the pager shows the line of the match at the top of the screen, like less
+    fn jump_to_match(&mut self, regex: &Regex, backward: bool) -> Result<(), String> {
+        let location = self
+            .view_mut()
+            .find(regex, backward)
+            .ok_or_else(|| format!("E486: Pattern not found: {}", regex.as_str()))?;
+        if self.pager.is_some() {
+            self.view_mut().show_at_top(location);
+        } else {
+            self.view_mut().goto(location);
+        }
+        Ok(())
+    }
+
+    // region: changes on disk
+
+    /// start or stop following the current file as it grows
+    fn toggle_follow(&mut self) {
+        let follow = !self.view().is_following();
+        match self.view_mut().set_follow(follow) {
+            Ok(()) if follow => {
+                self.command_bar.set_message("Following the file, scrolling up pauses");
+                self.scheduler.schedule(FOLLOW_POLL, Task::Follow);
+            }
+            Ok(()) => self.command_bar.set_message("Stopped following the file"),
+            Err(e) => self.command_bar.set_message(&e),
+        }
+    }
+    /// notice files changed on disk. buffers without unsaved changes are reloaded, otherwise the
+    /// user is asked once the file is the current one. files being followed are read as they change anyway
+    fn check_disk(&mut self) {
+        if self.prompt.is_some() {
+            return;
+        }
+        for (index, view) in self.views.iter_mut().enumerate() {
+            if view.is_following() || !view.changed_on_disk() {
+                continue;
+            }
+            let name = view.file_name().unwrap_or_default().to_string();
+            if view.is_dirty() {
+                if index == self.current {
+                    self.command_bar.start_prompt(&format!("\"{name}\" changed on disk. [r]eload/[k]eep/[d]iff: "));
+                    self.prompt = Some(Prompt::Reload);
+                }
+                continue;
+            }
+            match view.reload() {
+                Ok(()) => self.command_bar.set_message(&format!("\"{name}\" changed on disk, reloaded")),
+                Err(e) => self.command_bar.set_message(&e),
+            }
+        }
+    }
+    /// `r` reloads the file, dropping unsaved changes, `k` keeps the buffer and `d` shows
+    /// how the file differs from it in a new view
+    fn answer_reload(&mut self, code: KeyCode) {
+        let result = match code {
+            KeyCode::Char('r') => self.view_mut().reload().map(|()| "Reloaded, undo brings the changes back".to_string()),
+            KeyCode::Char('k') | KeyCode::Esc => {
+                self.view_mut().keep_buffer();
+                Ok("Buffer kept, saving overwrites the file".to_string())
+            }
+            KeyCode::Char('d') => self.view().disk_diff().map(|diff| {
+                self.view_mut().keep_buffer();
+                // comparing long files takes a while
+                self.scheduler.spawn(move || Task::ShowDiff(diff()));
+                "Comparing with the file on disk...".to_string()
+            }),
+            _ => return,
+        };
+        self.command_bar.end_prompt();
+        self.prompt = None;
+        match result {
+            Ok(message) => self.command_bar.set_message(&message),
+            Err(e) => self.command_bar.set_message(&e),
+        }
+    }
+
+    // region: swap files
+
+    /// look for a swap file left for the file just opened, warn about another instance editing it
+    /// or ask what to do with changes left behind
+    fn open_swap(&mut self) {
+        let name = self.view().file_name().unwrap_or_default().to_string();
+        match self.view_mut().open_swap() {
+            Ok(Found::Nothing) => (),
+            Ok(Found::Live(pid)) => self.command_bar.set_message(&format!(
+                "W: \"{name}\" is being edited by another instance (pid {pid}), changes are not journaled"
+            )),
+            Ok(Found::Stale(summary)) => {
+                self.command_bar.start_prompt(&format!(
+                    "Swap file: {summary}. [r]ecover/[d]elete/Esc: "
+                ));
+                self.prompt = Some(Prompt::Recover);
+            }
+            Err(e) => self.command_bar.set_message(&e),
+        }
+    }
+    /// `r` takes the changes from the swap file, `d` deletes it.
+    /// Esc leaves it for later and the file is not journaled meanwhile
+    fn answer_recovery(&mut self, code: KeyCode) {
+        let result = match code {
+            KeyCode::Char('r') => self.view_mut().recover_swap().map(|()| "Recovered unsaved changes"),
+            KeyCode::Char('d') => self.view_mut().start_swap().map(|()| "Swap file deleted"),
+            KeyCode::Esc => Ok("Swap file kept, changes are not journaled"),
+            _ => return,
+        };
+        self.command_bar.end_prompt();
+        self.prompt = None;
+        match result {
+            Ok(message) => self.command_bar.set_message(message),
+            Err(e) => self.command_bar.set_message(&e),
+        }
+    }
+
+    // region: ex commands
+
+    /// execute a command typed after `:`. errors are returned to be shown in the command bar
+    fn execute_ex(&mut self, text: &str) -> Result<(), String> {
+        let command = ExCommand::parse(text)?;
+        let status = self.view().status();
+        let range = command.line_range(status.current_line, status.total_lines, self.view().selection_lines())?;
+        if command.name.is_empty() && command.bang {
+            self.check_modifiable()?;
+            return self.filter(&command, range);
+        }
+        if command.name.is_empty() {
+            // a bare address jumps to that line
+            if command.has_range() {
+                self.view_mut().goto(Location {
+                    grapheme_index: 0,
+                    line_index: range.end - 1,
+                });
+            }
+            return Ok(());
+        }
+
+        if command.is("write", 1) {
+            self.write(&command)
+        } else if command.is("wq", 2) || command.is("xit", 1) || command.is("exit", 3) {
+            self.write(&command)?;
+            self.quit(true)
+        } else if command.is("quit", 1) {
+            self.quit(command.bang)
+        } else if command.is("edit", 1) {
+            self.edit(&command)
+        } else if command.is("set", 2) {
+            let messages = command
+                .args
+                .split_whitespace()
+                .map(|option| self.set_option(option))
+                .collect::<Result<Vec<_>, _>>()?;
+            let messages: Vec<String> = messages.into_iter().flatten().collect();
+            if !messages.is_empty() {
+                self.command_bar.set_message(&messages.join(" "));
+            }
+            Ok(())
+        } else if command.is("delete", 1) {
+            self.check_modifiable()?;
+            self.view_mut().delete_lines(range);
+            Ok(())
+        } else if command.is("join", 1) {
+            self.check_modifiable()?;
+            // a single line joins with the next one
+            let range = if range.len() > 1 { range } else { range.start..range.start + 2 };
+            self.view_mut().join_lines(range);
+            Ok(())
+        } else if command.is("substitute", 1) {
+            self.check_modifiable()?;
+            let substitution = Substitution::parse(&command.args)?;
+            match self.view_mut().substitute(range, &substitution) {
+                0 => Err(format!("E486: Pattern not found: {}", substitution.pattern)),
+                changed => {
+                    self.command_bar.set_message(&format!("{changed} lines changed"));
+                    Ok(())
+                }
+            }
+        } else if command.is("sort", 3) || command.is("uniq", 3) || command.is("reverse", 3) {
+            self.check_modifiable()?;
+            self.rewrite_lines(&command, range)
+        } else if command.is("bnext", 2) {
+            self.switch_view((self.current + 1) % self.views.len());
+            Ok(())
+        } else if command.is("bprevious", 2) {
+            self.switch_view((self.current + self.views.len() - 1) % self.views.len());
+            Ok(())
+        } else if command.is("ls", 2) || command.is("buffers", 7) {
+            self.list_views();
+            Ok(())
+        } else if command.is("follow", 3) {
+            self.toggle_follow();
+            Ok(())
+        } else if command.is("make", 3) {
+            self.build(&command.args);
+            Ok(())
+        } else if command.is("copen", 4) {
+            self.open_error_list()
+        } else if command.is("cnext", 2) || command.is("cprevious", 2) {
+            let index = self.quickfix.neighbour(command.is("cprevious", 2))?;
+            self.jump_to_error(index)
+        } else if command.is("cc", 2) {
+            // `:cc` alone jumps to the current error again
+            let index = match command.args.parse::<usize>() {
+                Ok(number) => number.saturating_sub(1),
+                Err(_) if command.args.is_empty() => self.quickfix.current.unwrap_or_default(),
+                Err(_) => return Err(format!("E474: Invalid argument: {}", command.args)),
+            };
+            self.jump_to_error(index)
+        } else if command.is("diagnostics", 4) {
+            self.list_diagnostics()
+        } else if command.is("lsp", 3) {
+            self.language_server_command(&command.args)
+        } else if command.is("macro", 3) {
+            self.macro_command(&command.args)
+        } else {
+            Err(format!("E492: Not an editor command: {}", command.name))
+        }
+    }
+    /// lines given to commands changing many lines at once: the range typed, or the selected lines,
+    /// or the whole buffer if nothing is selected
+    fn target_lines(&self, command: &ExCommand, range: Range<usize>) -> Range<usize> {
+        match self.view().selection_lines() {
+            _ if command.has_range() => range,
+            Some(lines) => lines,
+            None => 0..self.view().status().total_lines,
+        }
+    }
+    /// `:sort`, `:uniq` and `:reverse`
+    fn rewrite_lines(&mut self, command: &ExCommand, range: Range<usize>) -> Result<(), String> {
+        let range = self.target_lines(command, range);
+        let removed = if command.is("sort", 3) {
+            let sort = Sort::parse(&command.args, command.bang)?;
+            self.view_mut().rewrite_lines(range, |lines| sort.apply(lines))
+        } else if command.is("uniq", 3) {
+            let ignore_case = match command.args.trim() {
+                "" => false,
+                "i" => true,
+                args => return Err(format!("E474: Invalid argument: {args}")),
+            };
+            self.view_mut().rewrite_lines(range, |lines| sort::dedupe(lines, ignore_case))
+        } else {
+            self.view_mut().rewrite_lines(range, |lines| lines.reverse())
+        };
+        if removed > 0 {
+            self.command_bar.set_message(&format!("{removed} fewer lines"));
+        }
+        Ok(())
+    }
+    /// `:!{command}` pipes the lines through a shell command and puts its output in their place.
+    /// the command runs in the background, its output is dropped if the buffer changes meanwhile
+    fn filter(&mut self, command: &ExCommand, range: Range<usize>) -> Result<(), String> {
+        if command.args.is_empty() {
+            return Err("E471: Argument required".to_string());
+        }
+        let range = self.target_lines(command, range);
+        let (view, version) = (self.current, self.view().version());
+        let input = self.view().line_texts(range.clone());
+        let shell_command = command.args.clone();
+        self.scheduler.spawn(move || {
+            let output = shell::filter(&shell_command, input);
+            Task::Filtered { view, version, range, command: shell_command, output }
+        });
+        self.command_bar.set_message(&format!("Running \"{}\"...", command.args));
+        Ok(())
+    }
+    fn finish_filter(&mut self, view: usize, version: usize, range: Range<usize>, command: &str, output: Result<shell::Output, String>) {
+        let message = match output {
+            Err(e) => e,
+            Ok(_) if self.views[view].version() != version => {
+                format!("Buffer changed while \"{command}\" ran, its output was dropped")
+            }
+            Ok(output) => {
+                let (taken, given) = (range.len(), output.lines.len());
+                self.views[view].rewrite_lines(range, |lines| *lines = output.lines);
+                if output.stderr.is_empty() {
+                    format!("{taken} lines filtered, {given} lines now")
+                } else {
+                    output.stderr
+                }
+            }
+        };
+        self.command_bar.set_message(&message);
+    }
+    // region: quickfix
+
+    /// run the build command of the current file type in the background, with given arguments added
+    fn build(&mut self, args: &str) {
+        let command = format!("{} {args}", self.view().settings().make_command).trim_end().to_string();
+        self.command_bar.set_message(&format!("Running \"{command}\"..."));
+        self.scheduler.spawn(move || Task::Built { result: shell::run(&command), command });
+    }
+    /// collect the errors of a finished build, and list them if there are any
+    fn finish_build(&mut self, command: &str, result: Result<(bool, Vec<String>), String>) {
+        let (success, output) = match result {
+            Ok(result) => result,
+            Err(e) => return self.command_bar.set_message(&e),
+        };
+        self.quickfix.set_entries(Quickfix::parse(&output));
+        for index in 0..self.views.len() {
+            self.mark_build_errors(index);
+        }
+        let message = if !self.quickfix.entries().is_empty() {
+            let _ = self.open_error_list();
+            format!("{}. Enter jumps to one, F8 to the next", self.quickfix.summary())
+        } else if success {
+            format!("\"{command}\" succeeded")
+        } else {
+            // nothing to jump to, the last line may tell why
+            let last = output.iter().rev().find(|line| !line.trim().is_empty());
+            format!("\"{command}\" failed: {}", last.map_or("", |line| line.trim()))
+        };
+        self.command_bar.set_message(&message);
+    }
+    /// mark the problems the last build found in the file of given view
+    fn mark_build_errors(&mut self, index: usize) {
+        let Some(name) = self.views[index].file_name() else {
+            return;
+        };
+        let diagnostics = self
+            .quickfix
+            .entries()
+            .iter()
+            .filter(|entry| same_file(name, &entry.path))
+            .map(quickfix::Entry::to_diagnostic)
+            .collect();
+        self.views[index].set_diagnostics("make", diagnostics);
+    }
+    /// show the errors of the last build in a view of their own, `:copen`
+    fn open_error_list(&mut self) -> Result<(), String> {
+        if self.quickfix.entries().is_empty() {
+            return Err("E42: No Errors".to_string());
+        }
+        let lines: Vec<String> = self.quickfix.entries().iter().map(quickfix::Entry::describe).collect();
+        let mut view = View::scratch(&lines);
+        let mut settings = self.settings.for_file(None);
+        settings.read_only = true;
+        view.set_settings(settings);
+        let index = match self.quickfix.list_view {
+            Some(index) => {
+                self.views[index] = view;
+                index
+            }
+            None => {
+                self.views.push(view);
+                self.views.len() - 1
+            }
+        };
+        self.quickfix.list_view = Some(index);
+        self.switch_view(index);
+        self.resize(self.terminal_size);
+        let line_index = self.quickfix.current.unwrap_or_default();
+        self.view_mut().goto(Location { grapheme_index: 0, line_index });
+        Ok(())
+    }
+    /// open the file of the error at given index and put the caret where the error is
+    fn jump_to_error(&mut self, index: usize) -> Result<(), String> {
+        let total = self.quickfix.entries().len();
+        let entry = self.quickfix.select(index)?;
+        let (path, location) = (entry.path.clone(), Location {
+            line_index: entry.line.saturating_sub(1),
+            grapheme_index: entry.column.saturating_sub(1),
+        });
+        let message = format!("({}/{total}) {}", index + 1, entry.message);
+        self.open_path(&path)?;
+        self.view_mut().goto(location);
+        if let Some(list) = self.quickfix.list_view {
+            self.views[list].goto(Location { grapheme_index: 0, line_index: index });
+        }
+        self.command_bar.set_message(&message);
+        Ok(())
+    }
+
+    // region: language servers
+
+    /// open or update the documents of every view in the language servers of their file types,
+    /// starting the servers on first use
+    fn sync_language_servers(&mut self) {
+        let mut errors = Vec::new();
+        for view in &self.views {
+            let settings = view.settings();
+            let Some(path) = view.file_name() else {
+                continue;
+            };
+            if settings.language_server.is_empty() {
+                continue;
+            }
+            let filetype = &settings.filetype;
+            let server = self.language_servers.entry(filetype.clone()).or_insert_with(|| {
+                Client::start(&settings.language_server, &mut self.scheduler)
+                    .map_err(|e| errors.push(e))
+                    .ok()
+            });
+            let Some(client) = server else {
+                continue;
+            };
+            let uri = lsp::uri(path);
+            if let Err(e) = client.sync(&uri, filetype, view.version(), || view.line_texts(0..usize::MAX)) {
+                errors.push(e);
+                *server = None;
+            }
+        }
+        if let Some(e) = errors.pop() {
+            self.command_bar.set_message(&e);
+        }
+    }
+    fn notify_saved(&mut self) {
+        let Some(path) = self.view().file_name() else {
+            return;
+        };
+        let uri = lsp::uri(path);
+        let filetype = &self.views[self.current].settings().filetype;
+        if let Some(Some(client)) = self.language_servers.get_mut(filetype) {
+            let _ = client.saved(&uri);
+        }
+    }
+    /// send a hover, definition or completion request about the caret
+    fn ask_language_server(&mut self, command: &Command) -> Result<(), String> {
+        self.sync_language_servers();
+        let view = self.view();
+        let path = view.file_name().ok_or_else(|| "E32: No file name".to_string())?;
+        let filetype = view.settings().filetype.clone();
+        let location = view.location();
+        let line = view.line_texts(location.line_index..location.line_index + 1).concat();
+        let (uri, position) = (lsp::uri(path), Position::from_location(location, &line));
+        let client = self
+            .language_servers
+            .get_mut(&filetype)
+            .and_then(Option::as_mut)
+            .ok_or_else(|| format!("No language server for {filetype} (set language_server)"))?;
+        match command {
+            Command::Hover => client.hover(&uri, position),
+            Command::GotoDefinition => client.definition(&uri, position),
+            _ => client.completion(&uri, position),
+        }
+    }
+    fn language_message(&mut self, server: usize, message: &serde_json::Value) {
+        let client = self.language_servers.values_mut().flatten().find(|client| client.id == server);
+        let Some(client) = client else {
+            return;
+        };
+        let result = match client.handle(message) {
+            Ok(Some(reply)) => self.language_reply(reply),
+            Ok(None) => Ok(()),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            self.command_bar.set_message(&e);
+        }
+    }
+    fn language_reply(&mut self, reply: Reply) -> Result<(), String> {
+        match reply {
+            Reply::Diagnostics { uri, diagnostics } => {
+                if let Some(index) = self.view_of_uri(&uri) {
+                    let view = &mut self.views[index];
+                    let diagnostics = diagnostics
+                        .into_iter()
+                        .map(|diagnostic| diagnostic.locate(|line| view.line_texts(line..line + 1).concat()))
+                        .collect();
+                    view.set_diagnostics("lsp", diagnostics);
+                }
+            }
+            Reply::Hover(text) => {
+                // code fences and blank lines say little in one line
+                let text: Vec<&str> = text
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty() && !line.starts_with("```"))
+                    .collect();
+                let text = if text.is_empty() { "No information here".to_string() } else { text.join("  ") };
+                self.command_bar.set_message(&text);
+            }
+            Reply::Definition { uri, position } => {
+                let path = lsp::path(&uri).ok_or_else(|| format!("Cannot open {uri}"))?;
+                self.open_path(&path)?;
+                let line = self.view().line_texts(position.line..position.line + 1).concat();
+                self.view_mut().goto(position.to_location(&line));
+            }
+            Reply::Completion { uri, position, items } => self.complete(&uri, position, &items)?,
+            Reply::Message(text) => self.command_bar.set_message(&text),
+        }
+        Ok(())
+    }
+    /// index of the view showing the file of given uri
+    fn view_of_uri(&self, uri: &str) -> Option<usize> {
+        let path = lsp::path(uri)?;
+        self.views
+            .iter()
+            .position(|view| view.file_name().is_some_and(|name| same_file(name, &path)))
+    }
+    /// complete the word before the caret with the longest text the suggestions starting with it
+    /// share. the suggestions are listed when there are several
+    fn complete(&mut self, uri: &str, position: Position, items: &[String]) -> Result<(), String> {
+        // the answer is late if the caret moved meanwhile
+        let location = self.view().location();
+        let line = self.view().line_texts(location.line_index..location.line_index + 1).concat();
+        if self.view_of_uri(uri) != Some(self.current) || Position::from_location(location, &line) != position {
+            return Ok(());
+        }
+        self.check_modifiable()?;
+        let prefix = self.view().word_before_caret();
+        let mut candidates: Vec<&str> = Vec::new();
+        for item in items.iter().filter(|item| item.starts_with(&prefix) && item.len() > prefix.len()) {
+            if !candidates.contains(&item.as_str()) {
+                candidates.push(item);
+            }
+        }
+        let Some(first) = candidates.first() else {
+            return Err("No completions".to_string());
+        };
+        let common = candidates.iter().fold(*first, |common, candidate| {
+            let len = common
+                .char_indices()
+                .zip(candidate.chars())
+                .find(|((_, a), b)| a != b)
+                .map_or(common.len().min(candidate.len()), |((index, _), _)| index);
+            &common[..len]
+        });
+        if common.len() > prefix.len() {
+            self.view_mut().insert_text(&common[prefix.len()..]);
+        }
+        if candidates.len() > 1 {
+            self.command_bar.set_message(&candidates.join(" "));
+        }
+        Ok(())
+    }
+    /// `:diagnostics` lists the problems known in the current file, from the language server and the last build
+    fn list_diagnostics(&mut self) -> Result<(), String> {
+        let path = self.view().file_name().ok_or_else(|| "E32: No file name".to_string())?.to_string();
+        let entries = self
+            .view()
+            .diagnostics()
+            .iter()
+            .map(|diagnostic| quickfix::Entry {
+                path: path.clone(),
+                line: diagnostic.start.line_index + 1,
+                column: diagnostic.start.grapheme_index + 1,
+                severity: diagnostic.severity.name().to_string(),
+                code: diagnostic.code.clone(),
+                message: diagnostic.message.clone(),
+            })
+            .collect();
+        self.quickfix.set_entries(entries);
+        self.open_error_list()
+    }
+    /// `:lsp restart` starts the language servers again, `:lsp stop` stops the one of the
+    /// current file type until the next restart
+    fn language_server_command(&mut self, args: &str) -> Result<(), String> {
+        match args {
+            "restart" => {
+                self.language_servers.clear();
+                for view in &mut self.views {
+                    view.set_diagnostics("lsp", Vec::new());
+                }
+                self.sync_language_servers();
+            }
+            "stop" => {
+                let filetype = self.view().settings().filetype.clone();
+                self.language_servers.insert(filetype, None);
+                self.view_mut().set_diagnostics("lsp", Vec::new());
+            }
+            _ => return Err(format!("E474: Invalid argument: {args}")),
+        }
+        Ok(())
+    }
+
+    /// `:macro save [file]`, `:macro load [file]` and `:macro play {register} [count]`.
+    /// without a file, macros are kept in the config directory
+    fn macro_command(&mut self, args: &str) -> Result<(), String> {
+        let mut words = args.split_whitespace();
+        let action = words.next().unwrap_or_default();
+        let arg = words.next();
+        match action {
+            "save" => {
+                let path = self.macros.write(arg)?;
+                self.command_bar.set_message(&format!("Macros saved to \"{path}\""));
+            }
+            "load" => {
+                let count = self.macros.read(arg)?;
+                self.command_bar.set_message(&format!("{count} macros loaded"));
+            }
+            "play" => {
+                let register = arg.and_then(|arg| arg.chars().next()).unwrap_or('@');
+                let count = match words.next() {
+                    Some(count) => count.parse().map_err(|_| format!("E474: Invalid count: {count}"))?,
+                    None => 1,
+                };
+                self.play_macro(register, count)?;
+            }
+            _ => return Err(format!("E474: Invalid argument: {args}")),
+        }
+        Ok(())
+    }
+    /// `:w [file]`. refuses to overwrite a read-only file or one changed on disk since it was read,
+    /// unless forced
+    fn write(&mut self, command: &ExCommand) -> Result<(), String> {
+        let result = if command.args.is_empty() {
+            if self.view().file_name().is_none() {
+                return Err("E32: No file name".to_string());
+            }
+            if !command.bang && self.view().settings().read_only {
+                return Err("E45: 'read_only' option is set (add ! to override)".to_string());
+            }
+            if !command.bang && self.view_mut().changed_on_disk() {
+                return Err("WARNING: The file has been changed since reading it (add ! to override)".to_string());
+            }
+            self.view_mut().save()
+        } else {
+            let same_file = self.view().file_name() == Some(command.args.as_str());
+            if !same_file && !command.bang && Path::new(&command.args).exists() {
+                return Err("E13: File exists (add ! to override)".to_string());
+            }
+            self.view_mut().save_as(&command.args)
+        };
+        result.map_err(|e| format!("E212: Can't open file for writing: {e}"))?;
+        self.notify_saved();
+        let status = self.view().status();
+        let name = status.file_name.unwrap_or_default();
+        self.command_bar
+            .set_message(&format!("\"{name}\" {}L written", status.total_lines));
+        Ok(())
+    }
+    /// `:q`. refuses to leave unsaved changes behind unless forced
+    fn quit(&mut self, force: bool) -> Result<(), String> {
+        if !force {
+            if let Some(dirty) = self.views.iter().position(View::is_dirty) {
+                self.switch_view(dirty);
+                return Err("E37: No write since last change (add ! to override)".to_string());
+            }
+        }
+        self.quit = true;
+        Ok(())
+    }
+    /// `:e [file]`. switches to the file if it is already opened, otherwise opens it in a new view.
+    /// without a file, reloads the current one
+    fn edit(&mut self, command: &ExCommand) -> Result<(), String> {
+        if command.args.is_empty() {
+            let Some(path) = self.view().file_name().map(str::to_string) else {
+                return Err("E32: No file name".to_string());
+            };
+            if self.view().is_dirty() && !command.bang {
+                return Err("E37: No write since last change (add ! to override)".to_string());
+            }
+            return self
+                .view_mut()
+                .load_file(&path)
+                .map_err(|e| format!("Error when loading file: {e}"));
+        }
+
+        self.open_path(&command.args)
+    }
+    /// switch to the view of given file, opening it if no view has it
+    fn open_path(&mut self, path: &str) -> Result<(), String> {
+        let opened = self.views.iter().position(|view| view.file_name().is_some_and(|name| same_file(name, path)));
+        if let Some(index) = opened {
+            self.switch_view(index);
+            return Ok(());
+        }
+        let mut view = View::default();
+        view.set_settings(self.settings.for_file(Some(path)));
+        view.load_file(path)
+            .map_err(|e| format!("Error when loading file: {e}"))?;
+        // reuse the initial view if nothing was opened in it
+        let unused = self.view().file_name().is_none()
+            && !self.view().is_dirty()
+            && self.quickfix.list_view != Some(self.current);
+        if unused {
+            self.views[self.current] = view;
+        } else {
+            self.views.push(view);
+            self.current = self.views.len() - 1;
+        }
+        self.mark_build_errors(self.current);
+        self.resize(self.terminal_size);
+        self.open_swap();
+        Ok(())
+    }
+    /// `:set name=value`. changes the option for the current file, or for the whole editor
+    /// when it is one of the editor's like `theme`. returns the value when asked with `name?`
+    fn set_option(&mut self, option: &str) -> Result<Option<String>, String> {
+        if !Settings::is_global(option) {
+            let mut settings = self.view().settings().clone();
+            let message = settings.apply(option)?;
+            if message.is_none() {
+                self.view_mut().set_settings(settings);
+            }
+            return Ok(message);
+        }
+        let theme = self.settings.global.theme.clone();
+        let message = self.settings.global.apply(option)?;
+        if self.settings.global.theme != theme {
+            if let Err(e) = self.load_theme() {
+                self.settings.global.theme = theme;
+                return Err(e);
+            }
+        }
+        self.sync_modal();
+        Ok(message)
+    }
+    /// switch to the color scheme named in the settings
+    fn load_theme(&mut self) -> Result<(), String> {
+        self.theme = Theme::load(&self.settings.global.theme, Terminal::color_support())?;
+        self.view_mut().mark_redraw();
+        Ok(())
+    }
+    /// turn the vi and pager layers on or off to follow the `vi` and `pager` settings
+    fn sync_modal(&mut self) {
+        match (self.settings.global.vi, self.modal.is_some()) {
+            (true, false) => self.modal = Some(Modal::default()),
+            (false, true) => {
+                self.modal = None;
+                self.view_mut().command_handler(Command::ClearSelection);
+            }
+            _ => (),
+        }
+        self.pager = self.settings.global.pager.then(Pager::default);
+    }
+    fn switch_view(&mut self, index: usize) {
+        self.current = index;
+        self.view_mut().mark_redraw();
+        self.check_disk();
+    }
+    /// `:ls`, shows all opened files in the command bar
+    fn list_views(&mut self) {
+        let list: Vec<String> = self
+            .views
+            .iter()
+            .enumerate()
+            .map(|(index, view)| {
+                let current = if index == self.current { "%" } else { "" };
+                let modified = if view.is_dirty() { " +" } else { "" };
+                let name = view.file_name().unwrap_or("[No Name]");
+                format!("{}{current} \"{name}\"{modified}", index + 1)
+            })
+            .collect();
+        self.command_bar.set_message(&list.join(" | "));
+    }
+
     /// refresh the screen
     /// ignore any errors
     fn refresh_screen(&mut self) {
         let _ = Terminal::hide_cursor();
-        self.view.render();
-        let _ = Terminal::move_cursor(self.view.caret_position());
+        match &mut self.finder {
+            Some(finder) => {
+                let Size { width, height } = self.terminal_size;
+                finder.render(Size { width, height: height.saturating_sub(2) }, &self.settings, &self.theme);
+            }
+            None => self.views[self.current].render(&self.theme),
+        }
+        let mut mode = self.modal.as_ref().map(|modal| modal.mode_name().to_string());
+        if self.pager.is_some() {
+            mode = Some("PAGER".to_string());
+        }
+        if let Some(register) = self.macros.recording() {
+            let recording = format!("recording @{register}");
+            mode = Some(mode.map_or_else(|| recording.clone(), |mode| format!("{mode} {recording}")));
+        }
+        self.status_bar.render(&self.view().status(), mode.as_deref(), &self.theme);
+        self.command_bar.render(&self.theme);
+        let caret = if self.prompt.is_some() {
+            self.command_bar.caret_position()
+        } else {
+            self.view().caret_position()
+        };
+        let _ = Terminal::move_cursor(caret);
         let _ = Terminal::show_cursor();
         let _ = Terminal::execute();
     }
 }
 
 impl Drop for Editor {
+    /// swap files go away with the editor, unless it panicked or their buffer has unsaved
+    /// changes. then they get the latest changes to be recovered next time
     fn drop(&mut self) {
+        let panicking = std::thread::panicking();
+        for view in &mut self.views {
+            if panicking || view.is_dirty() {
+                let _ = view.update_swap(true);
+            } else {
+                view.close_swap();
+            }
+        }
+        if !self.terminal {
+            return;
+        }
         let _ = Terminal::terminate();
         if self.quit {
             let _ = Terminal::print("\x1b[32mThanks for using! \r\n");
         }
     }
-}
\ No newline at end of file
+}
+/// whether two paths lead to the same file, like `src/main.rs` and `./src/main.rs`
+fn same_file(a: &str, b: &str) -> bool {
+    a == b || matches!((fs::canonicalize(a), fs::canonicalize(b)), (Ok(a), Ok(b)) if a == b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use command::Direction;
+
+    /// an editor on given lines, not drawing anything
+    fn editor(texts: &[&str]) -> Editor {
+        let mut editor = Editor::new();
+        let lines: Vec<String> = texts.iter().map(|text| text.to_string()).collect();
+        editor.views[0] = View::scratch(&lines);
+        editor
+    }
+
+    fn texts(editor: &Editor) -> Vec<String> {
+        editor.view().line_texts(0..editor.view().status().total_lines)
+    }
+
+    #[test]
+    fn files_changed_on_disk_are_noticed_in_every_view() {
+        let dir = std::env::temp_dir();
+        let paths: Vec<String> = ["clean", "dirty", "current"]
+            .iter()
+            .map(|name| dir.join(format!("my-hecto-disk-{name}-{}.txt", std::process::id())).to_string_lossy().to_string())
+            .collect();
+        let mut editor = Editor::new();
+        editor.views.clear();
+        for path in &paths {
+            std::fs::write(path, "a\n").unwrap();
+            let mut view = View::default();
+            view.load_file(path).unwrap();
+            editor.views.push(view);
+        }
+        editor.current = 2;
+        editor.views[1].command_handler(Command::Insert('x'));
+        for path in &paths {
+            std::fs::write(path, "changed\n").unwrap();
+        }
+
+        editor.check_disk();
+        let lines = |editor: &Editor, index: usize| editor.views[index].line_texts(0..1);
+        assert_eq!(lines(&editor, 0), ["changed"]);
+        assert_eq!(lines(&editor, 1), ["xa"]);
+        assert_eq!(lines(&editor, 2), ["changed"]);
+        assert!(editor.prompt.is_none());
+
+        // the unsaved changes are asked about once their file is the current one
+        editor.switch_view(1);
+        assert!(matches!(editor.prompt, Some(Prompt::Reload)));
+        assert_eq!(lines(&editor, 1), ["xa"]);
+        for path in &paths {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    #[test]
+    fn a_macro_playing_itself_stops_at_the_first_failure() {
+        let mut editor = editor(&[""]);
+        editor.handle_command(Command::RecordMacro('a'));
+        editor.handle_command(Command::Insert('x'));
+        // plays what `a` held before, nothing
+        editor.handle_command(Command::PlayMacro { register: 'a', count: 2 });
+        editor.handle_command(Command::StopRecording);
+        editor.handle_command(Command::PlayMacro { register: 'a', count: 2 });
+        // the one typed while recording, then one for each level down to the one refused,
+        // rather than one for each branch
+        assert_eq!(texts(&editor), ["x".repeat(1 + macros::MAX_DEPTH)]);
+        assert!(!editor.macros.failed());
+        // playing works again afterwards
+        editor.handle_command(Command::Execute("s/x+//".to_string()));
+        editor.handle_command(Command::RecordMacro('b'));
+        editor.handle_command(Command::Insert('y'));
+        editor.handle_command(Command::StopRecording);
+        editor.handle_command(Command::PlayMacro { register: 'b', count: 3 });
+        assert_eq!(texts(&editor), ["yyyy"]);
+    }
+
+    #[test]
+    fn refused_edits_are_not_recorded() {
+        let mut editor = editor(&["a"]);
+        editor.handle_command(Command::Execute("set ro".to_string()));
+        editor.handle_command(Command::RecordMacro('r'));
+        editor.handle_command(Command::Insert('x'));
+        editor.handle_command(Command::Move(Direction::End));
+        editor.handle_command(Command::StopRecording);
+        editor.handle_command(Command::Execute("set noro".to_string()));
+        editor.handle_command(Command::Move(Direction::Home));
+        editor.handle_command(Command::PlayMacro { register: 'r', count: 1 });
+        assert_eq!(texts(&editor), ["a"]);
+        assert!(editor.view().caret_position().col == 1);
+    }
+
+    #[test]
+    fn a_failing_command_stops_the_macro_and_its_count() {
+        let mut editor = editor(&["a", "b"]);
+        editor.handle_command(Command::RecordMacro('q'));
+        editor.handle_command(Command::Insert('-'));
+        editor.handle_command(Command::Execute("+1".to_string()));
+        editor.handle_command(Command::StopRecording);
+        editor.handle_command(Command::Undo);
+        editor.handle_command(Command::Execute("1".to_string()));
+        editor.handle_command(Command::PlayMacro { register: 'q', count: 5 });
+        // the second playing types into the last line and can't go further down
+        assert_eq!(texts(&editor), ["-a", "-b"]);
+    }
+
+    #[test]
+    fn only_the_editor_options_are_set_for_files_opened_later() {
+        let mut editor = editor(&["a"]);
+        assert!(editor.set_option("wrap").unwrap().is_none());
+        assert!(editor.set_option("ts=8").unwrap().is_none());
+        assert!(editor.view().settings().wrap);
+        assert_eq!(editor.view().settings().tab_width, 8);
+        assert!(!editor.settings.global.wrap);
+        assert_eq!(editor.settings.global.tab_width, 4);
+
+        assert!(editor.set_option("message_timeout=9").unwrap().is_none());
+        assert_eq!(editor.settings.global.message_timeout, 9);
+        assert!(editor.set_option("vi!").unwrap().is_none());
+        assert!(editor.settings.global.vi && editor.modal.is_some());
+        assert_eq!(editor.set_option("vi?").unwrap().as_deref(), Some("vi=true"));
+        assert!(editor.set_option("novi").unwrap().is_none());
+        assert!(editor.modal.is_none());
+    }
+}
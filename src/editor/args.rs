@@ -0,0 +1,121 @@
+use std::path::Path;
+
+use super::view::Location;
+
+/// options collected from the command line
+#[derive(Default)]
+pub struct Args {
+    /// file to open
+    pub path: Option<String>,
+    /// where to place the caret after loading the file
+    pub location: Option<Location>,
+    /// start with vi-like modal editing
+    pub vi: bool,
+    /// refuse to change files
+    pub read_only: bool,
+    /// read files with less-like keys, without changing them
+    pub pager: bool,
+    /// follow the file as it grows, like `tail -f`
+    pub follow: bool,
+}
+
+impl Args {
+    /// supports `my-hecto path`, `my-hecto path:line[:col]` and `my-hecto +line path`,
+    /// plus `--vi` for modal editing, `-R` or `--readonly` for read-only mode, `--pager` to page through files
+    /// and `-f` or `--follow` to follow a growing file
+    pub fn parse(args: impl Iterator<Item = String>) -> Self {
+        let mut ret = Self::default();
+        for arg in args {
+            if arg == "--vi" {
+                ret.vi = true;
+            } else if arg == "-R" || arg == "--readonly" {
+                ret.read_only = true;
+            } else if arg == "--pager" {
+                ret.pager = true;
+            } else if arg == "-f" || arg == "--follow" {
+                ret.follow = true;
+            } else if let Some(line) = arg.strip_prefix('+') {
+                ret.location = Location::parse(line).or(ret.location);
+            } else if ret.path.is_none() {
+                let (path, location) = Self::split_location(&arg);
+                ret.path = Some(path);
+                ret.location = location.or(ret.location);
+            }
+        }
+        ret
+    }
+    /// split `path:line[:col]` into its parts.
+    /// a file whose name really ends with `:number` is left untouched
+    fn split_location(arg: &str) -> (String, Option<Location>) {
+        if Path::new(arg).exists() {
+            return (arg.to_string(), None);
+        }
+        let mut parts = arg.rsplitn(3, ':');
+        let last = parts.next().unwrap_or_default();
+        let middle = parts.next();
+        let first = parts.next();
+        let (path, position) = match (first, middle) {
+            (Some(path), Some(line)) if line.parse::<usize>().is_ok() => {
+                (path.to_string(), format!("{line}:{last}"))
+            }
+            (Some(first), Some(middle)) => (format!("{first}:{middle}"), last.to_string()),
+            (None, Some(path)) => (path.to_string(), last.to_string()),
+            _ => return (arg.to_string(), None),
+        };
+        match Location::parse(&position) {
+            Some(location) => (path, Some(location)),
+            None => (arg.to_string(), None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+
+    fn split(arg: &str) -> (String, Option<(usize, usize)>) {
+        let (path, location) = Args::split_location(arg);
+        (path, location.map(|location| (location.line_index, location.grapheme_index)))
+    }
+
+    #[test]
+    fn lines_and_columns_are_split_off() {
+        assert_eq!(split("src/main.rs"), ("src/main.rs".to_string(), None));
+        assert_eq!(split("no/such/file.rs:12"), ("no/such/file.rs".to_string(), Some((11, 0))));
+        assert_eq!(split("no/such/file.rs:12:5"), ("no/such/file.rs".to_string(), Some((11, 4))));
+        // only the last two parts can be numbers
+        assert_eq!(split("no:such:file.rs:3"), ("no:such:file.rs".to_string(), Some((2, 0))));
+        assert_eq!(split("no:such:3:4"), ("no:such".to_string(), Some((2, 3))));
+    }
+
+    #[test]
+    fn other_colons_are_part_of_the_path() {
+        assert_eq!(split("no/such:file.rs"), ("no/such:file.rs".to_string(), None));
+        assert_eq!(split("no/such/file.rs:"), ("no/such/file.rs:".to_string(), None));
+        assert_eq!(split("no/such/file.rs:x:1"), ("no/such/file.rs:x".to_string(), Some((0, 0))));
+        assert_eq!(split("no/such/file.rs:1:x"), ("no/such/file.rs:1:x".to_string(), None));
+    }
+
+    #[test]
+    fn existing_files_are_left_untouched() {
+        let path = env::temp_dir().join(format!("my-hecto-args-{}:12", std::process::id()));
+        fs::write(&path, "").unwrap();
+        let arg = path.to_string_lossy().to_string();
+        let split = split(&arg);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(split, (arg, None));
+    }
+
+    #[test]
+    fn options_and_plus_lines_are_read() {
+        let args = Args::parse(["--vi", "-R", "+7", "no/such/file.rs", "other.rs"].into_iter().map(str::to_string));
+        assert!(args.vi && args.read_only && !args.pager && !args.follow);
+        assert_eq!(args.path.as_deref(), Some("no/such/file.rs"));
+        assert!(args.location == Some(Location { grapheme_index: 0, line_index: 6 }));
+        // a location in the path wins over `+line`
+        let args = Args::parse(["+7", "no/such/file.rs:3:2"].into_iter().map(str::to_string));
+        assert!(args.location == Some(Location { grapheme_index: 1, line_index: 2 }));
+    }
+}
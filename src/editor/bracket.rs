@@ -0,0 +1,123 @@
+use std::ops::Range;
+
+use super::{highlight::Highlight, line::Line, syntax::{State, StateCache, Syntax}, view::Location};
+
+const PAIRS: [(&str, &str); 3] = [("(", ")"), ("[", "]"), ("{", "}")];
+
+pub fn is_bracket(grapheme: &str) -> bool {
+    PAIRS.iter().any(|(open, close)| grapheme == *open || grapheme == *close)
+}
+
+/// location of the bracket matching the one at given location, if there is a bracket there.
+/// brackets in strings and comments are skipped, unless the one to match is in there too
+pub fn find_match(lines: &[Line], syntax: &Syntax, states: &mut StateCache, at: Location) -> Option<Location> {
+    let grapheme = lines.get(at.line_index)?.grapheme_at(at.grapheme_index)?;
+    let (this, other, forward) = PAIRS.iter().find_map(|(open, close)| {
+        if grapheme == *open {
+            Some((*open, *close, true))
+        } else if grapheme == *close {
+            Some((*close, *open, false))
+        } else {
+            None
+        }
+    })?;
+
+    let mut state = states.state_at(syntax, lines, at.line_index);
+    let skip_syntax = !skipped_ranges(&lines[at.line_index], syntax, &mut state)
+        .iter()
+        .any(|range| range.contains(&at.grapheme_index));
+
+    let mut depth = 0_usize;
+    let mut state = states.state_at(syntax, lines, at.line_index);
+    let line_indexes: Box<dyn Iterator<Item = usize>> = if forward {
+        Box::new(at.line_index..lines.len())
+    } else {
+        Box::new((0..=at.line_index).rev())
+    };
+    for line_index in line_indexes {
+        let line = &lines[line_index];
+        if !forward {
+            state = states.state_at(syntax, lines, line_index);
+        }
+        let skipped = if skip_syntax {
+            skipped_ranges(line, syntax, &mut state)
+        } else {
+            Vec::new()
+        };
+        let len = line.grapheme_len();
+        let grapheme_indexes: Vec<usize> = match (forward, line_index == at.line_index) {
+            (true, true) => (at.grapheme_index..len).collect(),
+            (true, false) => (0..len).collect(),
+            (false, true) => (0..=at.grapheme_index).rev().collect(),
+            (false, false) => (0..len).rev().collect(),
+        };
+        for grapheme_index in grapheme_indexes {
+            if skipped.iter().any(|range| range.contains(&grapheme_index)) {
+                continue;
+            }
+            match line.grapheme_at(grapheme_index) {
+                Some(grapheme) if grapheme == this => depth += 1,
+                Some(grapheme) if grapheme == other => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(Location { grapheme_index, line_index });
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+    None
+}
+
+/// grapheme ranges of a line inside strings or comments
+fn skipped_ranges(line: &Line, syntax: &Syntax, state: &mut State) -> Vec<Range<usize>> {
+    syntax
+        .highlight_line(line, state)
+        .into_iter()
+        .filter(|(_, highlight)| matches!(highlight, Highlight::String | Highlight::Comment))
+        .map(|(range, _)| range)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(line_index: usize, grapheme_index: usize) -> Location {
+        Location { grapheme_index, line_index }
+    }
+
+    /// the match of the bracket at given location in rust source
+    fn find(texts: &[&str], location: Location) -> Option<Location> {
+        let lines: Vec<Line> = texts.iter().map(|text| Line::from(text)).collect();
+        find_match(&lines, &Syntax::for_filetype("rust"), &mut StateCache::default(), location)
+    }
+
+    #[test]
+    fn brackets_in_strings_are_skipped() {
+        assert!(find(&["f(a, \"(\", b)"], at(0, 1)) == Some(at(0, 11)));
+        assert!(find(&["f(a, \"(\", b)"], at(0, 11)) == Some(at(0, 1)));
+        assert!(find(&["let c = ')'; g(c)"], at(0, 14)) == Some(at(0, 16)));
+        // within a string, its own brackets count
+        assert!(find(&["let s = \"(x)\";"], at(0, 9)) == Some(at(0, 11)));
+    }
+
+    #[test]
+    fn brackets_in_comments_are_skipped() {
+        let lines = ["fn f() {", "    // }", "    g(\"}\");", "}"];
+        assert!(find(&lines, at(0, 7)) == Some(at(3, 0)));
+        assert!(find(&lines, at(3, 0)) == Some(at(0, 7)));
+        let lines = ["/* {", "*/ {", "}"];
+        assert!(find(&lines, at(1, 3)) == Some(at(2, 0)));
+        assert!(find(&lines, at(2, 0)) == Some(at(1, 3)));
+        assert!(find(&["(", "/* ) */"], at(0, 0)).is_none());
+    }
+
+    #[test]
+    fn only_brackets_have_a_match() {
+        assert!(find(&["f(x)"], at(0, 0)).is_none());
+        assert!(find(&["f(x)"], at(0, 9)).is_none());
+        assert!(find(&["f(x]"], at(0, 1)).is_none());
+    }
+}
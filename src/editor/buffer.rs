@@ -1,23 +1,259 @@
-use std::io::Error as IoE;
+use std::io::{Error as IoE, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::fs::{self, File};
+use std::hash::{DefaultHasher, Hasher};
+use std::ops::Range;
+use std::path::Path;
+use std::time::SystemTime;
 
-use super::{line::Line, view::Location};
+use super::{history::History, line::Line, settings::Settings, syntax::StateCache, view::Location};
+
+/// kinds of graphemes that make up words, used by word motions
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    /// whitespace and line ends
+    Blank,
+    Punctuation,
+    /// letters, digits and underscores
+    Word,
+}
+
+/// what the file looked like on disk when last read or written
+#[derive(Clone)]
+struct DiskState {
+    modified: Option<SystemTime>,
+    len: u64,
+    /// hash of the contents, fed more of them as the file grows
+    hasher: DefaultHasher,
+}
+
+impl DiskState {
+    fn read(path: &str) -> Option<Self> {
+        let contents = fs::read(path).ok()?;
+        Some(Self::of(path, &contents))
+    }
+    /// state of the file at given path, which holds given contents
+    fn of(path: &str, contents: &[u8]) -> Self {
+        let mut hasher = DefaultHasher::new();
+        hasher.write(contents);
+        Self {
+            modified: modified(path),
+            len: contents.len() as u64,
+            hasher,
+        }
+    }
+    /// state of a file that does not exist yet
+    fn empty() -> Self {
+        Self {
+            modified: None,
+            len: 0,
+            hasher: DefaultHasher::new(),
+        }
+    }
+    fn hash(&self) -> u64 {
+        self.hasher.finish()
+    }
+    /// take bytes appended to the file at given path into account
+    fn grow(&mut self, path: &str, appended: &[u8]) {
+        self.hasher.write(appended);
+        self.len += appended.len() as u64;
+        self.modified = modified(path);
+    }
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
 
 /// buffer that records contents for each line
 #[derive(Default)]
 pub struct Buffer {
     pub lines: Vec<Line>,
+    /// file the buffer was loaded from or will be saved to
+    pub file_name: Option<String>,
+    /// whether the buffer has changed since last load or save
+    pub dirty: bool,
+    history: History,
+    /// state of the history when the file was last read or written
+    saved: usize,
+    /// counts changes to the contents, to tell whether they changed since some point
+    pub version: usize,
+    /// the file as last read or written, to notice changes made by others
+    disk: Option<DiskState>,
+    /// syntax states at the start of lines, forgotten from where the lines change
+    pub syntax_states: StateCache,
+    /// line ranges replaced since the view last took them, with how many lines took their place
+    edits: Vec<(Range<usize>, usize)>,
 }
 
 #[allow(dead_code)]
 impl Buffer {
-    /// load file from given path to buffer
+    /// load file from given path to buffer.
+    /// an inexistent file results in an empty buffer that will be saved to that path
     pub fn load_file(&mut self, path: &str) -> Result<(), IoE> {
-        let contents = std::fs::read_to_string(path)?;
-        for line in contents.lines() {
-            self.lines.push(Line::from(line));
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        let old = self.total_lines();
+        self.lines = contents.lines().map(Line::from).collect();
+        self.edits.push((0..old, self.total_lines()));
+        self.disk = Path::new(path).exists().then(|| DiskState::of(path, contents.as_bytes()));
+        self.file_name = Some(path.to_string());
+        self.history = History::default();
+        self.mark_saved();
+        self.syntax_states.clear();
+        self.version += 1;
+        Ok(())
+    }
+    /// write the buffer to the file it was loaded from
+    pub fn save(&mut self, settings: &Settings) -> Result<(), IoE> {
+        let Some(path) = self.file_name.clone() else {
+            return Err(IoE::new(ErrorKind::NotFound, "No file name"));
+        };
+        self.save_as(&path, settings)
+    }
+    /// write the buffer to given path, which becomes the buffer's file name
+    pub fn save_as(&mut self, path: &str, settings: &Settings) -> Result<(), IoE> {
+        if settings.trim_trailing_whitespace {
+            self.trim_trailing_whitespace();
         }
+        let mut file = File::create(path)?;
+        for (index, line) in self.lines.iter().enumerate() {
+            if index + 1 < self.lines.len() || settings.final_newline {
+                writeln!(file, "{line}")?;
+            } else {
+                write!(file, "{line}")?;
+            }
+        }
+        self.file_name = Some(path.to_string());
+        self.mark_saved();
+        self.disk = DiskState::read(path);
         Ok(())
     }
+    /// the buffer is as the file is now, undoing or redoing back to here makes it clean again
+    fn mark_saved(&mut self) {
+        self.dirty = false;
+        self.saved = self.history.mark();
+    }
+    /// remove whitespace at the end of lines, as one edit spanning the lines that had some
+    fn trim_trailing_whitespace(&mut self) {
+        let texts: Vec<String> = self.lines.iter().map(ToString::to_string).collect();
+        let trimmed = |text: &String| text.trim_end().len() != text.len();
+        let (Some(first), Some(last)) = (texts.iter().position(trimmed), texts.iter().rposition(trimmed)) else {
+            return;
+        };
+        let lines: Vec<String> = texts[first..=last].iter().map(|text| text.trim_end().to_string()).collect();
+        self.replace_lines(first..last + 1, &lines);
+    }
+    /// whether the file changed on disk since it was last read or written.
+    /// a file touched without changing its contents has not
+    pub fn changed_on_disk(&mut self) -> bool {
+        let (Some(path), Some(disk)) = (self.file_name.as_deref(), self.disk.as_ref()) else {
+            return false;
+        };
+        let Ok(metadata) = fs::metadata(path) else {
+            return false;
+        };
+        if metadata.modified().ok() == disk.modified && metadata.len() == disk.len {
+            return false;
+        }
+        let Some(now) = DiskState::read(path) else {
+            return false;
+        };
+        if now.hash() == disk.hash() {
+            self.disk = Some(now);
+            return false;
+        }
+        true
+    }
+    /// take what is on disk as the file's state, without reading it into the buffer
+    pub fn accept_disk(&mut self) {
+        self.disk = self.file_name.as_deref().and_then(DiskState::read);
+    }
+    /// lines of the file as it is on disk now
+    pub fn read_disk(&self) -> Result<Vec<String>, IoE> {
+        let path = self.file_name.as_deref().ok_or_else(|| IoE::new(ErrorKind::NotFound, "No file name"))?;
+        Ok(fs::read_to_string(path)?.lines().map(str::to_string).collect())
+    }
+    /// read the file again, as one edit that can be undone
+    pub fn reload(&mut self) -> Result<(), IoE> {
+        let lines = self.read_disk()?;
+        let current: Vec<String> = self.lines.iter().map(Line::to_string).collect();
+        if lines != current {
+            self.replace_all(&lines);
+        }
+        self.mark_saved();
+        self.accept_disk();
+        Ok(())
+    }
+    /// read what was appended to the file since it was last read or written, like `tail -f`.
+    /// appended lines are no edit to undo. a file that got shorter, e.g. truncated or rotated,
+    /// is read again as a whole. returns whether the buffer changed
+    pub fn read_appended(&mut self) -> Result<bool, IoE> {
+        let Some(path) = self.file_name.clone() else {
+            return Ok(false);
+        };
+        let metadata = match fs::metadata(&path) {
+            Ok(metadata) => metadata,
+            // a file missing when the buffer was loaded may still appear
+            Err(e) if e.kind() == ErrorKind::NotFound && self.disk.is_none() => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        let disk = self.disk.get_or_insert_with(DiskState::empty);
+        if metadata.len() == disk.len && metadata.modified().ok() == disk.modified {
+            return Ok(false);
+        }
+        if metadata.len() < disk.len {
+            let version = self.version;
+            self.reload()?;
+            return Ok(self.version != version);
+        }
+        // the byte before the new ones tells whether they continue the last line
+        let before = disk.len.min(1);
+        let mut file = File::open(&path)?;
+        file.seek(SeekFrom::Start(disk.len - before))?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        let continues = before == 1 && bytes.first() != Some(&b'\n');
+        let appended = &bytes[before as usize..];
+        // a character the writer has not finished yet is left for next time,
+        // so is a `\r` that may be the start of a `\r\n`
+        let mut taken = match std::str::from_utf8(appended) {
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            _ => appended.len(),
+        };
+        if appended[..taken].ends_with(b"\r") {
+            taken -= 1;
+        }
+        disk.grow(&path, &appended[..taken]);
+        let text = String::from_utf8_lossy(&appended[..taken]);
+        if text.is_empty() {
+            return Ok(false);
+        }
+
+        let mut pieces: Vec<&str> = text.split('\n').map(|piece| piece.strip_suffix('\r').unwrap_or(piece)).collect();
+        // nothing follows the last line break yet
+        if text.ends_with('\n') {
+            pieces.pop();
+        }
+        let mut pieces = pieces.into_iter();
+        let end = self.total_lines();
+        let mut lines = Vec::new();
+        let start = match self.lines.last().filter(|_| continues) {
+            Some(last) => {
+                lines.push(Line::from(&format!("{last}{}", pieces.next().unwrap_or_default())));
+                end - 1
+            }
+            None => end,
+        };
+        lines.extend(pieces.map(Line::from));
+        self.syntax_states.invalidate_from(start);
+        self.edits.push((start..end, lines.len()));
+        self.lines.splice(start..end, lines);
+        self.version += 1;
+        Ok(true)
+    }
     pub fn is_empty(&self) -> bool {
         self.lines.is_empty()
     }
@@ -30,11 +266,331 @@ impl Buffer {
             return;
         }
         
+        self.record(line_index..line_index + 1, 1, true);
         if let Some(line) = self.lines.get_mut(line_index) {
             line.insert_at(grapheme_index, c);
         } else {
             self.lines.push(Line::from(&String::from(c)));
         }
+        self.dirty = true;
+    }
+    /// remove lines within given range
+    pub fn delete_lines(&mut self, range: Range<usize>) {
+        let end = range.end.min(self.total_lines());
+        if range.start >= end {
+            return;
+        }
+        self.record(range.start..end, 0, false);
+        self.lines.drain(range.start..end);
+        self.dirty = true;
+    }
+    /// insert text at given location, the text may contain line breaks.
+    /// returns the location right after the inserted text
+    pub fn insert_str(&mut self, location: Location, text: &str) -> Location {
+        let Location { grapheme_index, line_index } = location;
+        if line_index > self.total_lines() {
+            return location;
+        }
+        let breaks = text.matches('\n').count();
+        let replaced = if line_index == self.total_lines() { 0 } else { 1 };
+        self.record(line_index..line_index + replaced, breaks + 1, false);
+        if line_index == self.total_lines() {
+            self.lines.push(Line::from(""));
+        }
+
+        let line = &mut self.lines[line_index];
+        let tail = line.split_off(grapheme_index);
+        let mut pieces = text.split('\n');
+        line.append(&Line::from(pieces.next().unwrap_or_default()));
+        let mut end = Location {
+            grapheme_index: line.grapheme_len(),
+            line_index,
+        };
+        for piece in pieces {
+            end.line_index += 1;
+            self.lines.insert(end.line_index, Line::from(piece));
+            end.grapheme_index = self.lines[end.line_index].grapheme_len();
+        }
+        self.lines[end.line_index].append(&tail);
+        self.dirty = true;
+        end
+    }
+    /// original text between two locations, lines are joined by line breaks.
+    /// `to` is exclusive
+    pub fn text_range(&self, from: Location, to: Location) -> String {
+        let (from, to) = (self.clamp(from), self.clamp(to));
+        if from.line_index == to.line_index {
+            return self.lines.get(from.line_index).map_or(String::new(), |line| {
+                line.text_between(from.grapheme_index..to.grapheme_index)
+            });
+        }
+        let mut ret = String::new();
+        for line_index in from.line_index..=to.line_index {
+            let Some(line) = self.lines.get(line_index) else {
+                break;
+            };
+            let start = if line_index == from.line_index { from.grapheme_index } else { 0 };
+            let end = if line_index == to.line_index { to.grapheme_index } else { line.grapheme_len() };
+            ret.push_str(&line.text_between(start..end));
+            if line_index != to.line_index {
+                ret.push('\n');
+            }
+        }
+        ret
+    }
+    /// remove text between two locations, joining the lines around them. `to` is exclusive
+    pub fn delete_range(&mut self, from: Location, to: Location) {
+        let (from, to) = (self.clamp(from), self.clamp(to));
+        if from.line_index >= self.total_lines() {
+            return;
+        }
+        if from.line_index == to.line_index {
+            if from.grapheme_index < to.grapheme_index {
+                self.record(from.line_index..from.line_index + 1, 1, false);
+                self.lines[from.line_index].delete(from.grapheme_index..to.grapheme_index);
+                self.dirty = true;
+            }
+            return;
+        }
+        self.record(from.line_index..to.line_index + 1, 1, false);
+        let tail = self.lines[to.line_index].split_off(to.grapheme_index);
+        let line = &mut self.lines[from.line_index];
+        line.split_off(from.grapheme_index);
+        line.append(&tail);
+        self.lines.drain(from.line_index + 1..=to.line_index);
+        self.dirty = true;
+    }
+    /// the location after the last grapheme of the buffer
+    pub fn end_location(&self) -> Location {
+        let line_index = self.total_lines().saturating_sub(1);
+        Location {
+            grapheme_index: self.lines.get(line_index).map_or(0, Line::grapheme_len),
+            line_index,
+        }
+    }
+
+    // region: undo
+
+    /// start a change, edits until the matching `end_change` are undone together
+    pub fn begin_change(&mut self, caret: Location) {
+        self.history.begin(caret);
+    }
+    pub fn end_change(&mut self, caret: Location) {
+        self.history.end(caret);
+    }
+    /// revert the last change, returns where the caret goes
+    pub fn undo(&mut self, caret: Location) -> Option<Location> {
+        let location = self.history.undo(&mut self.lines, caret, &mut self.edits)?;
+        self.syntax_states.clear();
+        self.dirty = self.history.state() != self.saved;
+        self.version += 1;
+        Some(self.clamp(location))
+    }
+    /// apply the last undone change again, returns where the caret goes
+    pub fn redo(&mut self, caret: Location) -> Option<Location> {
+        let location = self.history.redo(&mut self.lines, caret, &mut self.edits)?;
+        self.syntax_states.clear();
+        self.dirty = self.history.state() != self.saved;
+        self.version += 1;
+        Some(self.clamp(location))
+    }
+
+    /// remember the lines in range before they are replaced by `len` lines
+    fn record(&mut self, range: Range<usize>, len: usize, typing: bool) {
+        self.syntax_states.invalidate_from(range.start);
+        self.edits.push((range.clone(), len));
+        self.history.record(&self.lines, range, len, typing);
+        self.version += 1;
+    }
+
+    /// the line ranges replaced since last asked, in the order they were, with how many lines
+    /// took the place of each
+    pub fn take_edits(&mut self) -> Vec<(Range<usize>, usize)> {
+        std::mem::take(&mut self.edits)
+    }
+
+    // region: word motions
+
+    /// start of the next word, like `w` in vi. empty lines count as words
+    pub fn next_word_start(&self, from: Location) -> Location {
+        let mut location = from;
+        let class = self.class_at(location);
+        if class != CharClass::Blank {
+            while self.class_at(location) == class {
+                match self.next_location(location) {
+                    Some(next) => location = next,
+                    None => return location,
+                }
+            }
+        }
+        while self.class_at(location) == CharClass::Blank && !self.is_empty_line(location, from) {
+            match self.next_location(location) {
+                Some(next) => location = next,
+                None => return location,
+            }
+        }
+        location
+    }
+    /// end of the current or next word, like `e` in vi
+    pub fn next_word_end(&self, from: Location) -> Location {
+        let Some(mut location) = self.next_location(from) else {
+            return from;
+        };
+        while self.class_at(location) == CharClass::Blank {
+            match self.next_location(location) {
+                Some(next) => location = next,
+                None => return location,
+            }
+        }
+        let class = self.class_at(location);
+        while let Some(next) = self.next_location(location) {
+            if self.class_at(next) != class {
+                break;
+            }
+            location = next;
+        }
+        location
+    }
+    /// start of the current or previous word, like `b` in vi
+    pub fn prev_word_start(&self, from: Location) -> Location {
+        let Some(mut location) = self.prev_location(from) else {
+            return from;
+        };
+        while self.class_at(location) == CharClass::Blank && !self.is_empty_line(location, from) {
+            match self.prev_location(location) {
+                Some(prev) => location = prev,
+                None => return location,
+            }
+        }
+        let class = self.class_at(location);
+        while let Some(prev) = self.prev_location(location) {
+            if self.class_at(prev) != class {
+                break;
+            }
+            location = prev;
+        }
+        location
+    }
+    fn class_at(&self, location: Location) -> CharClass {
+        let grapheme = self
+            .lines
+            .get(location.line_index)
+            .and_then(|line| line.grapheme_at(location.grapheme_index));
+        match grapheme {
+            None => CharClass::Blank,
+            Some(grapheme) if grapheme.trim().is_empty() => CharClass::Blank,
+            Some(grapheme) if grapheme.chars().all(|c| c.is_alphanumeric() || c == '_') => CharClass::Word,
+            Some(_) => CharClass::Punctuation,
+        }
+    }
+    /// whether the location is an empty line other than the one motion started from
+    fn is_empty_line(&self, location: Location, from: Location) -> bool {
+        location.line_index != from.line_index
+            && self.lines.get(location.line_index).is_some_and(|line| line.grapheme_len() == 0)
+    }
+    /// the location one grapheme later, where line ends count as a grapheme
+    fn next_location(&self, location: Location) -> Option<Location> {
+        let len = self.lines.get(location.line_index)?.grapheme_len();
+        if location.grapheme_index < len {
+            Some(Location { grapheme_index: location.grapheme_index + 1, ..location })
+        } else if location.line_index + 1 < self.total_lines() {
+            Some(Location { grapheme_index: 0, line_index: location.line_index + 1 })
+        } else {
+            None
+        }
+    }
+    /// the location one grapheme earlier, where line ends count as a grapheme
+    fn prev_location(&self, location: Location) -> Option<Location> {
+        if location.grapheme_index > 0 {
+            Some(Location { grapheme_index: location.grapheme_index - 1, ..location })
+        } else if location.line_index > 0 {
+            let line_index = location.line_index - 1;
+            Some(Location { grapheme_index: self.lines.get(line_index)?.grapheme_len(), line_index })
+        } else {
+            None
+        }
+    }
+    /// snap a location into the buffer's contents
+    pub fn clamp(&self, location: Location) -> Location {
+        if location.line_index >= self.total_lines() {
+            return self.end_location();
+        }
+        let len = self.lines[location.line_index].grapheme_len();
+        Location {
+            grapheme_index: location.grapheme_index.min(len),
+            line_index: location.line_index,
+        }
+    }
+    /// replace the line at given index with new contents
+    pub fn replace_line(&mut self, line_index: usize, contents: &str) {
+        if line_index < self.total_lines() {
+            self.record(line_index..line_index + 1, 1, false);
+            self.lines[line_index] = Line::from(contents);
+            self.dirty = true;
+        }
+    }
+    /// replace all lines, as one edit
+    pub fn replace_all(&mut self, lines: &[String]) {
+        self.replace_lines(0..self.total_lines(), lines);
+    }
+    /// put other lines in place of the ones in range, as one edit
+    pub fn replace_lines(&mut self, range: Range<usize>, lines: &[String]) {
+        let range = range.start.min(self.total_lines())..range.end.min(self.total_lines());
+        self.record(range.clone(), lines.len(), false);
+        self.lines.splice(range, lines.iter().map(|line| Line::from(line)));
+        self.dirty = true;
+    }
+    /// put a copy of the lines in range right after them
+    pub fn duplicate_lines(&mut self, range: Range<usize>) {
+        let end = range.end.min(self.total_lines());
+        if range.start >= end {
+            return;
+        }
+        self.record(end..end, end - range.start, false);
+        let copies: Vec<Line> = self.lines[range.start..end].iter().map(|line| Line::from(&line.to_string())).collect();
+        self.lines.splice(end..end, copies);
+        self.dirty = true;
+    }
+    /// swap the lines in range with the line above or below them.
+    /// returns false if they are at the top or bottom already
+    pub fn move_lines(&mut self, range: Range<usize>, up: bool) -> bool {
+        let end = range.end.min(self.total_lines());
+        if range.start >= end || (up && range.start == 0) || (!up && end == self.total_lines()) {
+            return false;
+        }
+        let affected = if up { range.start - 1..end } else { range.start..end + 1 };
+        self.record(affected.clone(), affected.len(), false);
+        if up {
+            self.lines[affected].rotate_left(1);
+        } else {
+            self.lines[affected].rotate_right(1);
+        }
+        self.dirty = true;
+        true
+    }
+    /// join the lines in range into one, separated by a single space with indentation removed.
+    /// returns where the last join happened, if any did
+    pub fn join_lines(&mut self, range: Range<usize>) -> Option<usize> {
+        let end = range.end.min(self.total_lines());
+        if range.start + 1 >= end {
+            return None;
+        }
+        let mut joined = self.lines[range.start].to_string();
+        let mut at = 0;
+        for line in &self.lines[range.start + 1..end] {
+            let next = line.to_string();
+            let next = next.trim_start();
+            joined.truncate(joined.trim_end().len());
+            at = Line::from(&joined).grapheme_len();
+            if !joined.is_empty() && !next.is_empty() {
+                joined.push(' ');
+            }
+            joined.push_str(next);
+        }
+        self.record(range.start..end, 1, false);
+        self.lines.splice(range.start..end, [Line::from(&joined)]);
+        self.dirty = true;
+        Some(at)
     }
 }
 
@@ -51,3 +607,190 @@ impl Buffer {
 //         println!("{a:?}, {b:?}, {c:?}");
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::syntax::{State, Syntax};
+
+    fn buffer(lines: &[&str]) -> Buffer {
+        Buffer {
+            lines: lines.iter().map(|line| Line::from(line)).collect(),
+            ..Buffer::default()
+        }
+    }
+
+    fn texts(buffer: &Buffer) -> Vec<String> {
+        buffer.lines.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn trimming_on_save_is_an_edit_that_can_be_undone() {
+        let path = std::env::temp_dir().join(format!("my-hecto-trim-{}.txt", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        let settings = Settings { trim_trailing_whitespace: true, ..Settings::default() };
+        let mut buffer = buffer(&["foo   ", "bar", "baz\t"]);
+        let version = buffer.version;
+
+        buffer.save_as(&path, &settings).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "foo\nbar\nbaz\n");
+        assert_eq!(texts(&buffer), ["foo", "bar", "baz"]);
+        assert!(buffer.version > version);
+        assert!(!buffer.dirty);
+
+        let caret = buffer.undo(Location { line_index: 0, grapheme_index: 3 });
+        assert!(caret.is_some());
+        assert_eq!(texts(&buffer), ["foo   ", "bar", "baz\t"]);
+        assert!(buffer.dirty);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn undoing_back_to_the_saved_lines_makes_the_buffer_clean() {
+        let path = std::env::temp_dir().join(format!("my-hecto-saved-{}.txt", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        let mut buffer = buffer(&["foo"]);
+        let type_at = |buffer: &mut Buffer, grapheme_index: usize, c: char| {
+            let location = Location { line_index: 0, grapheme_index };
+            buffer.begin_change(location);
+            buffer.insert_at(location, c);
+            buffer.end_change(Location { line_index: 0, grapheme_index: grapheme_index + 1 });
+        };
+
+        buffer.save_as(&path, &Settings::default()).unwrap();
+        type_at(&mut buffer, 3, 'a');
+        assert!(buffer.dirty);
+        buffer.undo(Location::default());
+        assert!(!buffer.dirty);
+        buffer.redo(Location::default());
+        assert!(buffer.dirty);
+
+        // typing on after a save is a step of its own, so that undoing it gets back to the file
+        buffer.save(&Settings::default()).unwrap();
+        type_at(&mut buffer, 4, 'b');
+        buffer.undo(Location::default());
+        assert_eq!(texts(&buffer), ["fooa"]);
+        assert!(!buffer.dirty);
+        buffer.undo(Location::default());
+        assert_eq!(texts(&buffer), ["foo"]);
+        assert!(buffer.dirty);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn saving_without_trailing_whitespace_records_nothing() {
+        let path = std::env::temp_dir().join(format!("my-hecto-clean-{}.txt", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        let settings = Settings { trim_trailing_whitespace: true, ..Settings::default() };
+        let mut buffer = buffer(&["foo", "bar"]);
+        let version = buffer.version;
+
+        buffer.save_as(&path, &settings).unwrap();
+        assert_eq!(buffer.version, version);
+        assert!(buffer.undo(Location::default()).is_none());
+        let _ = fs::remove_file(&path);
+    }
+
+    /// a buffer of a file in the temp directory holding given contents, followed like `tail -f`
+    fn followed(name: &str, contents: &str) -> (Buffer, String) {
+        let path = std::env::temp_dir().join(format!("my-hecto-{name}-{}.txt", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        fs::write(&path, contents).unwrap();
+        let mut buffer = Buffer::default();
+        buffer.load_file(&path).unwrap();
+        (buffer, path)
+    }
+
+    fn append(path: &str, bytes: &[u8]) {
+        fs::OpenOptions::new().append(true).open(path).unwrap().write_all(bytes).unwrap();
+    }
+
+    #[test]
+    fn appended_lines_are_read_without_history() {
+        let (mut buffer, path) = followed("append", "a\nb\n");
+        assert!(!buffer.read_appended().unwrap());
+        append(&path, b"c\nd\n");
+        assert!(buffer.read_appended().unwrap());
+        assert_eq!(texts(&buffer), ["a", "b", "c", "d"]);
+        assert!(!buffer.dirty);
+        assert!(buffer.undo(Location::default()).is_none());
+        assert!(!buffer.read_appended().unwrap());
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn an_unfinished_line_is_continued() {
+        let (mut buffer, path) = followed("continue", "a\nb");
+        append(&path, b"c");
+        assert!(buffer.read_appended().unwrap());
+        assert_eq!(texts(&buffer), ["a", "bc"]);
+        append(&path, b"d\ne");
+        assert!(buffer.read_appended().unwrap());
+        assert_eq!(texts(&buffer), ["a", "bcd", "e"]);
+        // the line break is read once it is complete
+        append(&path, b"\r");
+        assert!(!buffer.read_appended().unwrap());
+        append(&path, b"\nf\r\n");
+        assert!(buffer.read_appended().unwrap());
+        assert_eq!(texts(&buffer), ["a", "bcd", "e", "f"]);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn an_unfinished_character_waits_for_the_rest() {
+        let (mut buffer, path) = followed("utf8", "a\n");
+        append(&path, &"\u{e9}".as_bytes()[..1]);
+        assert!(!buffer.read_appended().unwrap());
+        assert_eq!(texts(&buffer), ["a"]);
+        append(&path, &"\u{e9}\n".as_bytes()[1..]);
+        assert!(buffer.read_appended().unwrap());
+        assert_eq!(texts(&buffer), ["a", "\u{e9}"]);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn a_truncated_file_is_read_again() {
+        let (mut buffer, path) = followed("truncate", "a\nb\nc\n");
+        fs::write(&path, "x\n").unwrap();
+        assert!(buffer.read_appended().unwrap());
+        assert_eq!(texts(&buffer), ["x"]);
+        append(&path, b"y\n");
+        assert!(buffer.read_appended().unwrap());
+        assert_eq!(texts(&buffer), ["x", "y"]);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn a_file_missing_at_first_is_followed_once_it_appears() {
+        let path = std::env::temp_dir().join(format!("my-hecto-missing-{}.txt", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        let _ = fs::remove_file(&path);
+        let mut buffer = Buffer::default();
+        buffer.load_file(&path).unwrap();
+        assert!(!buffer.read_appended().unwrap());
+        fs::write(&path, "a\nb").unwrap();
+        assert!(buffer.read_appended().unwrap());
+        assert_eq!(texts(&buffer), ["a", "b"]);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn clamp_keeps_locations_inside_the_text() {
+        let buffer = buffer(&["foo", "é"]);
+        let clamped = buffer.clamp(Location { line_index: 0, grapheme_index: 6 });
+        assert!(clamped == Location { line_index: 0, grapheme_index: 3 });
+        let clamped = buffer.clamp(Location { line_index: 5, grapheme_index: 0 });
+        assert!(clamped == Location { line_index: 1, grapheme_index: 1 });
+    }
+
+    #[test]
+    fn edits_forget_syntax_states_below_them() {
+        let syntax = Syntax::for_filetype("rust");
+        let mut buffer = buffer(&["a", "b", "c"]);
+        assert!(buffer.syntax_states.state_at(&syntax, &buffer.lines, 3) == State::Code);
+        buffer.insert_str(Location { grapheme_index: 0, line_index: 1 }, "/*");
+        assert!(buffer.syntax_states.state_at(&syntax, &buffer.lines, 2) == State::BlockComment);
+        buffer.undo(Location::default());
+        assert!(buffer.syntax_states.state_at(&syntax, &buffer.lines, 2) == State::Code);
+    }
+}
@@ -1,7 +1,8 @@
-use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::event::{Event, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
 
-use super::terminal::Size;
+use super::{terminal::{Position, Size}, view::Location};
 
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum Direction {
     Up,
     Down, 
@@ -11,42 +12,267 @@ pub enum Direction {
     PageDown, 
     Home, 
     End,
+    /// like Left, but never wraps to the previous line
+    StepLeft,
+    /// like Right, but never wraps to the next line
+    StepRight,
+    /// start of the next word
+    WordForward,
+    /// start of the current or previous word
+    WordBackward,
+    /// end of the current or next word
+    WordEnd,
+    FileStart,
+    FileEnd,
+    /// the bracket matching the one at the caret, or the first one after it in the line
+    MatchingBracket,
 }
 
+/// how much text an operator affects when combined with a motion in a direction
+#[derive(PartialEq, Eq)]
+pub enum MotionKind {
+    /// whole lines between start and end
+    Linewise,
+    /// up to and including the grapheme at the end
+    Inclusive,
+    /// up to but excluding the grapheme at the end
+    Exclusive,
+}
+
+impl Direction {
+    pub const fn kind(self) -> MotionKind {
+        match self {
+            Self::Up | Self::Down | Self::PageUp | Self::PageDown | Self::FileStart | Self::FileEnd => MotionKind::Linewise,
+            Self::End | Self::WordEnd | Self::MatchingBracket => MotionKind::Inclusive,
+            _ => MotionKind::Exclusive,
+        }
+    }
+}
+
+/// how text between two locations is selected
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum SelectionKind {
+    /// every grapheme from one location to the other
+    #[default]
+    Characters,
+    /// whole lines
+    Lines,
+    /// a rectangle of screen columns on every line in between
+    Block,
+}
+
+/// what to do with text covered by a motion or a selection
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Delete,
+    /// delete, then start inserting
+    Change,
+    /// copy into the register
+    Yank,
+}
+
+/// what the editor can do. keys are mapped to commands by the keymap
+#[derive(Clone)]
 pub enum Command {
     Move(Direction), 
     Resize(Size),
     Insert(char),
+    /// split the line at the caret
+    InsertNewline,
+    /// remove the grapheme before the caret
+    DeleteBackward,
+    /// remove the grapheme under the caret
+    Delete,
+    /// move the caret to given location
+    Goto(Location),
+    /// apply an operator from the caret to where moving `count` times in a direction leads
+    Operate {
+        operator: Operator,
+        direction: Direction,
+        count: usize,
+    },
+    /// apply an operator to `count` whole lines starting from the caret
+    OperateLines {
+        operator: Operator,
+        count: usize,
+    },
+    /// apply an operator to the selection, then clear it
+    OperateSelection(Operator),
+    /// start selecting text from the caret. changes the kind of a selection already going on
+    StartSelection(SelectionKind),
+    /// extend a block selection in a direction, starting one if there is none
+    SelectBlock(Direction),
+    /// put a caret on every line of a block selection, at its left edge or after its right edge
+    BlockInsert {
+        append: bool,
+    },
+    ClearSelection,
+    /// insert the register after or before the caret
+    Paste {
+        before: bool,
+    },
+    /// a left click at given screen position. `add` toggles an extra caret there instead
+    Click {
+        position: Position,
+        add: bool,
+    },
+    /// add a caret on the line above the first caret, or below the last one
+    AddCursor(Direction),
+    /// add a caret at the next occurrence of the selection, or of the word under the caret
+    AddNextOccurrence,
+    /// drop all carets but the main one
+    SingleCursor,
+    /// copy the lines of the caret or the selection below them
+    DuplicateLines,
+    /// swap the lines of the caret or the selection with the line above or below
+    MoveLines(Direction),
+    /// remove the lines of the caret or the selection
+    DeleteLines,
+    /// join the selected lines, or the caret's line with the next one
+    JoinLines,
+    /// comment out the lines of the caret or the selection, or uncomment them.
+    /// `block` puts one block comment around them instead of a line comment on each
+    ToggleComment {
+        block: bool,
+    },
+    /// revert the last change
+    Undo,
+    /// apply the last undone change again
+    Redo,
+    /// start recording commands into the macro register of given name, or stop if recording already
+    RecordMacro(char),
+    StopRecording,
+    /// play the macro in a register `count` times, undone as one change.
+    /// `@` names the register played last
+    PlayMacro {
+        register: char,
+        count: usize,
+    },
+    /// run an ex command, as if typed after `:`
+    Execute(String),
+    /// move the screen instead of the caret, which stays on the top line shown
+    Scroll(Direction),
+    /// jump to the next match of a regex, or the previous one if `backward`
+    Search {
+        pattern: String,
+        backward: bool,
+    },
+    /// repeat the last search, in the opposite direction if `reverse`
+    SearchNext {
+        reverse: bool,
+    },
+    /// ask user for a pattern to search for
+    StartSearch {
+        backward: bool,
+    },
+    /// start or stop following the file as it grows, like `tail -f`
+    Follow,
+    /// run the build command, collecting its errors in the quickfix list
+    Build,
+    /// jump to the next error of the quickfix list, or the previous one if `backward`
+    NextError {
+        backward: bool,
+    },
+    /// show what the language server knows about the thing at the caret
+    Hover,
+    /// jump to where the thing at the caret is defined, as the language server tells
+    GotoDefinition,
+    /// complete the word before the caret with what the language server suggests
+    Complete,
+    /// ask user for a line to jump to
+    GotoLine,
+    /// pick a file of the working directory to open, by typing parts of its path
+    FindFile,
+    /// ask user for an ex command, like `:w`
+    Ex,
     Quit,
 }
 
+impl Command {
+    /// whether extra carets stay after the command. the ones that edit or move apply to every caret,
+    /// the others only make sense for the main caret, so the extra ones are dropped first
+    pub const fn keeps_cursors(&self) -> bool {
+        matches!(
+            self,
+            Self::Move(_)
+                | Self::Resize(_)
+                | Self::Insert(_)
+                | Self::InsertNewline
+                | Self::DeleteBackward
+                | Self::Delete
+                | Self::Click { .. }
+                | Self::AddCursor(_)
+                | Self::AddNextOccurrence
+                | Self::BlockInsert { .. }
+                | Self::GotoLine
+                | Self::Ex
+                | Self::Quit
+        )
+    }
+    /// whether the command changes the text, which read-only buffers refuse
+    pub const fn edits(&self) -> bool {
+        match self {
+            Self::Insert(_)
+            | Self::InsertNewline
+            | Self::DeleteBackward
+            | Self::Delete
+            | Self::Paste { .. }
+            | Self::DuplicateLines
+            | Self::MoveLines(_)
+            | Self::DeleteLines
+            | Self::JoinLines
+            | Self::ToggleComment { .. }
+            | Self::Complete
+            // appending to a block pads short lines with spaces
+            | Self::BlockInsert { .. }
+            | Self::Undo
+            | Self::Redo => true,
+            Self::Operate { operator, .. } | Self::OperateLines { operator, .. } | Self::OperateSelection(operator) => {
+                !matches!(operator, Operator::Yank)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// events other than key presses, which go through the keymap
 impl TryFrom<Event> for Command {
     type Error = String;
 
     fn try_from(event: Event) -> Result<Self, Self::Error> {
         match event {
-            Event::Key(KeyEvent {
-                code, modifiers, ..
-            }) => {
-                match code {
-                    KeyCode::Char('q') if modifiers == KeyModifiers::CONTROL => Ok(Self::Quit),
-                    KeyCode::Char(c) => Ok(Self::Insert(c)),
-                    KeyCode::Up => Ok(Self::Move(Direction::Up)),
-                    KeyCode::Down => Ok(Self::Move(Direction::Down)),
-                    KeyCode::Left => Ok(Self::Move(Direction::Left)),
-                    KeyCode::Right => Ok(Self::Move(Direction::Right)),
-                    KeyCode::PageUp => Ok(Self::Move(Direction::PageUp)),
-                    KeyCode::PageDown => Ok(Self::Move(Direction::PageDown)),
-                    KeyCode::Home => Ok(Self::Move(Direction::Home)),
-                    KeyCode::End => Ok(Self::Move(Direction::End)),
-                    _ => Err("Unsupported key: {code:?}".to_string()),
-                }
-            },
             Event::Resize(width_u16, height_u16) => Ok(Self::Resize(Size {
                 width: width_u16 as usize,
                 height: height_u16 as usize,
             })),
+            Event::Mouse(MouseEvent { kind, column, row, modifiers }) => match kind {
+                MouseEventKind::Down(MouseButton::Left) => Ok(Self::Click {
+                    position: Position { col: column as usize, row: row as usize },
+                    add: modifiers.contains(KeyModifiers::CONTROL),
+                }),
+                MouseEventKind::ScrollUp => Ok(Self::Move(Direction::Up)),
+                MouseEventKind::ScrollDown => Ok(Self::Move(Direction::Down)),
+                _ => Err(format!("Unsupported mouse event: {kind:?}")),
+            },
             _ => Err("Unsupported event: {event:?}".to_string()),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_insert_counts_as_an_edit() {
+        assert!(Command::BlockInsert { append: true }.edits());
+        assert!(Command::BlockInsert { append: false }.edits());
+    }
+
+    #[test]
+    fn yanking_and_moving_do_not_edit() {
+        assert!(!Command::OperateSelection(Operator::Yank).edits());
+        assert!(!Command::Move(Direction::Down).edits());
+        assert!(Command::OperateSelection(Operator::Delete).edits());
+    }
+}
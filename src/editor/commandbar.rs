@@ -0,0 +1,113 @@
+use std::time::{Duration, Instant};
+
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use super::{terminal::{Position, Terminal}, theme::Theme};
+
+/// the last row of the screen.
+/// shows a message, or collects user input after a prompt
+#[derive(Default)]
+pub struct CommandBar {
+    /// text shown before user input, e.g. "Go to line: ". empty when not prompting
+    prompt: String,
+    /// text typed by the user
+    value: String,
+    /// message shown when not prompting
+    message: String,
+    /// when the message was set
+    shown_at: Option<Instant>,
+    row: usize,
+    width: usize,
+}
+
+impl CommandBar {
+    /// place the bar at given row of the screen
+    pub fn resize(&mut self, row: usize, width: usize) {
+        self.row = row;
+        self.width = width;
+    }
+    /// start collecting user input after given prompt
+    pub fn start_prompt(&mut self, prompt: &str) {
+        self.prompt = prompt.to_string();
+        self.value.clear();
+    }
+    /// stop prompting and return what user has typed
+    pub fn end_prompt(&mut self) -> String {
+        self.prompt.clear();
+        std::mem::take(&mut self.value)
+    }
+    pub fn set_message(&mut self, message: &str) {
+        self.message = message.to_string();
+        self.shown_at = (!message.is_empty()).then(Instant::now);
+    }
+    /// when the message on the bar was set, if there is one
+    pub const fn shown_at(&self) -> Option<Instant> {
+        self.shown_at
+    }
+    /// clear the message if it has been shown for given time
+    pub fn expire_message(&mut self, after: Duration) {
+        if self.shown_at.is_some_and(|at| at.elapsed() >= after) {
+            self.set_message("");
+        }
+    }
+    /// text typed so far
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+    pub fn insert(&mut self, c: char) {
+        self.value.push(c);
+    }
+    /// remove the last grapheme of user input
+    pub fn delete_backward(&mut self) {
+        if let Some((index, _)) = self.value.grapheme_indices(true).next_back() {
+            self.value.truncate(index);
+        }
+    }
+
+    // region: rendering
+
+    /// render the bar. messages are clipped on the right,
+    /// while only the tail of user input is shown if it is too long
+    pub fn render(&self, theme: &Theme) {
+        let text = if self.prompt.is_empty() {
+            self.message_head()
+        } else {
+            self.input_tail()
+        };
+        let ret = Terminal::print_highlighted_at(self.row, &[(text, None)], theme);
+        debug_assert!(ret.is_ok(), "Failed to render command bar!");
+    }
+    /// caret sits right after user input
+    pub fn caret_position(&self) -> Position {
+        let typed = self.prompt.width() + self.value.width();
+        Position {
+            col: typed.min(self.width.saturating_sub(1)),
+            row: self.row,
+        }
+    }
+    fn message_head(&self) -> String {
+        let mut width = 0;
+        self.message
+            .graphemes(true)
+            .take_while(|grapheme| {
+                width += grapheme.width();
+                width <= self.width
+            })
+            .collect()
+    }
+    fn input_tail(&self) -> String {
+        let text = format!("{}{}", self.prompt, self.value);
+        // keep one column for the caret
+        let mut width = 0;
+        let mut start = text.len();
+        for (index, grapheme) in text.grapheme_indices(true).rev() {
+            width += grapheme.width();
+            if width >= self.width {
+                break;
+            }
+            start = index;
+        }
+        text[start..].to_string()
+    }
+}
@@ -0,0 +1,263 @@
+use std::ops::Range;
+
+use super::{line::Line, syntax::Syntax};
+
+/// text put in place of some graphemes of a line
+pub struct Splice {
+    pub line_index: usize,
+    /// grapheme index where the change starts
+    pub at: usize,
+    /// graphemes taken out
+    pub removed: usize,
+    pub inserted: String,
+}
+
+impl Splice {
+    /// where a grapheme index of the line ends up after the change.
+    /// one inside the removed graphemes goes to where they were
+    pub fn shift(&self, grapheme_index: usize) -> usize {
+        let inserted = Line::from(&self.inserted).grapheme_len();
+        if grapheme_index < self.at {
+            grapheme_index
+        } else if grapheme_index >= self.at + self.removed {
+            grapheme_index - self.removed + inserted
+        } else {
+            self.at
+        }
+    }
+}
+
+/// comment out the lines in range with the file type's line comments, or take the comments out
+/// if every line has one. comments go at the smallest indentation among the lines.
+/// file types without line comments get a block comment on each line
+pub fn toggle_lines(lines: &[Line], range: Range<usize>, syntax: &Syntax) -> Result<Vec<Splice>, String> {
+    let (line_comment, block_comment) = syntax.comment_tokens();
+    let Some(token) = line_comment else {
+        let (open, close) = block_comment.ok_or_else(no_comments)?;
+        let mut ret = Vec::new();
+        let uncomment = non_blank(lines, range.clone()).all(|line| is_wrapped(&line.to_string(), open, close));
+        if !uncomment && non_blank(lines, range.clone()).any(|line| line.to_string().contains(close)) {
+            return Err(cannot_nest(close));
+        }
+        let indent = min_indent(lines, range.clone());
+        for line_index in range {
+            ret.extend(toggle_block(lines, line_index..line_index + 1, (open, close), indent, uncomment));
+        }
+        return Ok(ret);
+    };
+
+    let indent = min_indent(lines, range.clone());
+    let uncomment = non_blank(lines, range.clone()).all(|line| starts_with(line, token));
+    let ret = range
+        .filter(|line_index| lines.get(*line_index).is_some_and(|line| !is_blank(line)))
+        .map(|line_index| {
+            let line = &lines[line_index];
+            if uncomment {
+                let at = indentation(line);
+                let removed = token_len(token) + usize::from(line.grapheme_at(at + token_len(token)) == Some(" "));
+                Splice { line_index, at, removed, inserted: String::new() }
+            } else {
+                Splice { line_index, at: indent, removed: 0, inserted: format!("{token} ") }
+            }
+        })
+        .collect();
+    Ok(ret)
+}
+
+/// put one block comment around the lines in range, or take it out if they are in one already.
+/// file types without block comments get line comments instead
+pub fn toggle_block_comment(lines: &[Line], range: Range<usize>, syntax: &Syntax) -> Result<Vec<Splice>, String> {
+    let Some((open, close)) = syntax.comment_tokens().1 else {
+        return toggle_lines(lines, range, syntax);
+    };
+    let text = lines[range.start.min(lines.len())..range.end.min(lines.len())]
+        .iter()
+        .map(Line::to_string)
+        .collect::<Vec<_>>()
+        .join("\n");
+    let uncomment = is_wrapped(&text, open, close);
+    // a comment around a closer would end there
+    if !uncomment && text.contains(close) {
+        return match syntax.comment_tokens().0 {
+            Some(_) => toggle_lines(lines, range, syntax),
+            None => Err(cannot_nest(close)),
+        };
+    }
+    let indent = min_indent(lines, range.clone());
+    Ok(toggle_block(lines, range, (open, close), indent, uncomment))
+}
+
+/// add or remove a block comment from the first non-blank line in range to the last one.
+/// the opener goes at given indentation
+fn toggle_block(lines: &[Line], range: Range<usize>, (open, close): (&str, &str), indent: usize, uncomment: bool) -> Vec<Splice> {
+    let mut indexes = range.filter(|line_index| lines.get(*line_index).is_some_and(|line| !is_blank(line)));
+    let Some(first) = indexes.next() else {
+        return Vec::new();
+    };
+    let last = indexes.next_back().unwrap_or(first);
+    let (first_line, last_line) = (&lines[first], &lines[last]);
+    // the end of the last line without trailing blanks
+    let end = (0..last_line.grapheme_len())
+        .rev()
+        .find(|index| !last_line.grapheme_at(*index).is_some_and(|grapheme| grapheme.trim().is_empty()))
+        .map_or(0, |index| index + 1);
+    // the closer goes first, so that the opener's index stays right when both are on one line
+    if uncomment {
+        let at = end - token_len(close);
+        let space = at > 0 && last_line.grapheme_at(at - 1) == Some(" ");
+        let opener = indentation(first_line);
+        let after_open = usize::from(first_line.grapheme_at(opener + token_len(open)) == Some(" "));
+        // `/**/` alone on a line has no space to take out
+        let space = space && at > opener + token_len(open) + after_open;
+        vec![
+            Splice { line_index: last, at: at - usize::from(space), removed: token_len(close) + usize::from(space), inserted: String::new() },
+            Splice { line_index: first, at: opener, removed: token_len(open) + after_open, inserted: String::new() },
+        ]
+    } else {
+        vec![
+            Splice { line_index: last, at: end, removed: 0, inserted: format!(" {close}") },
+            Splice { line_index: first, at: indent, removed: 0, inserted: format!("{open} ") },
+        ]
+    }
+}
+
+fn no_comments() -> String {
+    "No comments known for this file type (set filetype)".to_string()
+}
+
+fn cannot_nest(close: &str) -> String {
+    format!("Cannot comment out text with \"{close}\" in a block comment")
+}
+
+fn non_blank(lines: &[Line], range: Range<usize>) -> impl Iterator<Item = &Line> {
+    lines[range.start.min(lines.len())..range.end.min(lines.len())]
+        .iter()
+        .filter(|line| !is_blank(line))
+}
+
+fn is_blank(line: &Line) -> bool {
+    line.to_string().trim().is_empty()
+}
+
+/// graphemes of whitespace the line starts with
+fn indentation(line: &Line) -> usize {
+    (0..line.grapheme_len())
+        .find(|index| !line.grapheme_at(*index).is_some_and(|grapheme| grapheme.trim().is_empty()))
+        .unwrap_or(line.grapheme_len())
+}
+
+/// smallest indentation among the non-blank lines in range
+fn min_indent(lines: &[Line], range: Range<usize>) -> usize {
+    non_blank(lines, range).map(indentation).min().unwrap_or(0)
+}
+
+fn starts_with(line: &Line, token: &str) -> bool {
+    line.to_string().trim_start().starts_with(token)
+}
+
+/// whether the text without surrounding blanks is one block comment: it starts with the opener,
+/// ends with the closer and isn't closed before. `/**/` is one, `/*/` and `/* a */ b /* c */` aren't
+fn is_wrapped(text: &str, open: &str, close: &str) -> bool {
+    text.trim()
+        .strip_prefix(open)
+        .and_then(|inside| inside.strip_suffix(close))
+        .is_some_and(|inside| !inside.contains(close))
+}
+
+fn token_len(token: &str) -> usize {
+    Line::from(token).grapheme_len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the lines after toggling comments on the ones in range, the way the view applies the splices
+    fn toggle(filetype: &str, texts: &[&str], range: Range<usize>, block: bool) -> Result<Vec<String>, String> {
+        let lines: Vec<Line> = texts.iter().map(|text| Line::from(text)).collect();
+        let syntax = Syntax::for_filetype(filetype);
+        let mut splices = match block {
+            true => toggle_block_comment(&lines, range, &syntax)?,
+            false => toggle_lines(&lines, range, &syntax)?,
+        };
+        splices.sort_by_key(|splice| std::cmp::Reverse((splice.line_index, splice.at)));
+        let mut texts: Vec<String> = texts.iter().map(|text| text.to_string()).collect();
+        for splice in splices {
+            let line = Line::from(&texts[splice.line_index]);
+            texts[splice.line_index] = format!(
+                "{}{}{}",
+                line.text_between(0..splice.at),
+                splice.inserted,
+                line.text_between(splice.at + splice.removed..line.grapheme_len())
+            );
+        }
+        Ok(texts)
+    }
+
+    #[test]
+    fn line_comments_go_at_the_smallest_indentation() {
+        let texts = ["fn f() {", "    let é = 1;", "", "  g();", "}"];
+        let commented = toggle("rust", &texts, 1..4, false).unwrap();
+        assert_eq!(commented, ["fn f() {", "  //   let é = 1;", "", "  // g();", "}"]);
+        let texts: Vec<&str> = commented.iter().map(String::as_str).collect();
+        assert_eq!(toggle("rust", &texts, 1..4, false).unwrap(), ["fn f() {", "    let é = 1;", "", "  g();", "}"]);
+        assert_eq!(toggle("python", &["#x", "y"], 0..1, false).unwrap(), ["x", "y"]);
+    }
+
+    #[test]
+    fn lines_are_uncommented_only_when_all_are_comments() {
+        let texts = ["// a", "b"];
+        assert_eq!(toggle("rust", &texts, 0..2, false).unwrap(), ["// // a", "// b"]);
+        assert!(toggle("text", &texts, 0..2, false).is_err_and(|e| e == no_comments()));
+    }
+
+    #[test]
+    fn file_types_without_line_comments_get_a_block_on_each_line() {
+        let texts = ["a { }", "  b { }"];
+        let commented = toggle("css", &texts, 0..2, false).unwrap();
+        assert_eq!(commented, ["/* a { } */", "/*   b { } */"]);
+        let texts: Vec<&str> = commented.iter().map(String::as_str).collect();
+        assert_eq!(toggle("css", &texts, 0..2, false).unwrap(), ["a { }", "  b { }"]);
+        assert!(toggle("html", &["<b> <!-- x --> </b>"], 0..1, false).is_err());
+    }
+
+    #[test]
+    fn block_comments_go_around_the_lines() {
+        let texts = ["  a();", "", "  b(); "];
+        let commented = toggle("rust", &texts, 0..3, true).unwrap();
+        assert_eq!(commented, ["  /* a();", "", "  b(); */ "]);
+        let texts: Vec<&str> = commented.iter().map(String::as_str).collect();
+        assert_eq!(toggle("rust", &texts, 0..3, true).unwrap(), ["  a();", "", "  b(); "]);
+        assert_eq!(toggle("html", &["<!--x-->"], 0..1, true).unwrap(), ["x"]);
+        // without block comments, lines get line comments
+        assert_eq!(toggle("python", &["a", "b"], 0..2, true).unwrap(), ["# a", "# b"]);
+    }
+
+    #[test]
+    fn overlapping_tokens_are_not_taken_for_a_comment() {
+        assert_eq!(toggle("rust", &["/**/"], 0..1, true).unwrap(), [""]);
+        assert_eq!(toggle("rust", &["  /* */"], 0..1, true).unwrap(), ["  "]);
+        assert_eq!(toggle("lua", &["--[[]]"], 0..1, true).unwrap(), [""]);
+        // `/*/` opens a comment and never closes it, wrapping it would close early: line comments instead
+        assert_eq!(toggle("rust", &["/*/"], 0..1, true).unwrap(), ["// /*/"]);
+        assert_eq!(toggle("c", &["*/"], 0..1, true).unwrap(), ["// */"]);
+        assert!(toggle("css", &["/*/"], 0..1, true).is_err_and(|e| e == cannot_nest("*/")));
+    }
+
+    #[test]
+    fn comments_inside_the_lines_are_left_alone() {
+        // two comments with code between them are not one comment
+        assert_eq!(toggle("rust", &["/* a */ b(); /* c */"], 0..1, true).unwrap(), ["// /* a */ b(); /* c */"]);
+        assert_eq!(toggle("rust", &["x(); /* note */"], 0..1, true).unwrap(), ["// x(); /* note */"]);
+        assert_eq!(toggle("rust", &["/* a", "*/ b", "c */"], 0..3, true).unwrap(), ["// /* a", "// */ b", "// c */"]);
+        // an opener inside a comment is fine
+        assert_eq!(toggle("rust", &["a(); /* b"], 0..1, true).unwrap(), ["/* a(); /* b */"]);
+        assert_eq!(toggle("rust", &["/* a(); /* b */"], 0..1, true).unwrap(), ["a(); /* b"]);
+    }
+
+    #[test]
+    fn splices_move_locations_after_them() {
+        let splice = Splice { line_index: 0, at: 2, removed: 3, inserted: "é".to_string() };
+        assert_eq!([0, 2, 3, 5, 9].map(|index| splice.shift(index)), [0, 2, 2, 3, 7]);
+    }
+}
@@ -0,0 +1,19 @@
+use std::{env, io::{Error as IoE, ErrorKind}, path::PathBuf};
+
+/// directory holding user config files, `$XDG_CONFIG_HOME/my-hecto` or `~/.config/my-hecto`
+pub fn config_dir() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join(env!("CARGO_PKG_NAME")))
+}
+
+/// read a file from the config directory. returns `None` if it does not exist
+pub fn read_config(file_name: &str) -> Option<Result<String, IoE>> {
+    let path = config_dir()?.join(file_name);
+    match std::fs::read_to_string(path) {
+        Err(e) if e.kind() == ErrorKind::NotFound => None,
+        result => Some(result),
+    }
+}
@@ -0,0 +1,69 @@
+use super::view::Location;
+
+/// how bad a problem is, the worst first
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+
+impl Severity {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+            Self::Information => "info",
+            Self::Hint => "hint",
+        }
+    }
+    /// the letter marking it in the sign column
+    pub const fn sign(self) -> char {
+        match self {
+            Self::Error => 'E',
+            Self::Warning => 'W',
+            Self::Information => 'I',
+            Self::Hint => 'H',
+        }
+    }
+}
+
+/// a problem in a buffer, from whatever found it: a language server, a build, a linter
+#[derive(Clone)]
+pub struct Diagnostic {
+    pub start: Location,
+    /// exclusive. the same as `start` when the tool gave a single spot
+    pub end: Location,
+    pub severity: Severity,
+    /// like `E0308`
+    pub code: Option<String>,
+    pub message: String,
+    /// what reported it, like `lsp` or `make`. a source's diagnostics are replaced together
+    pub source: String,
+}
+
+impl Diagnostic {
+    /// the graphemes of given line it covers, an empty range for a spot between graphemes
+    pub fn graphemes_in(&self, line_index: usize) -> Option<(usize, usize)> {
+        if line_index < self.start.line_index || line_index > self.end.line_index {
+            return None;
+        }
+        let first = if line_index == self.start.line_index { self.start.grapheme_index } else { 0 };
+        let last = if line_index == self.end.line_index { self.end.grapheme_index } else { usize::MAX };
+        Some((first, last.max(first)))
+    }
+    /// whether the caret at given location is on it
+    pub fn covers(&self, location: Location) -> bool {
+        self.graphemes_in(location.line_index)
+            .is_some_and(|(first, last)| (first..last.max(first + 1)).contains(&location.grapheme_index))
+    }
+    /// how it shows in a popup: severity, code and the first line of the message
+    pub fn describe(&self) -> String {
+        let message = self.message.lines().next().unwrap_or_default();
+        match &self.code {
+            Some(code) => format!("{}[{code}]: {message}", self.severity.name()),
+            None => format!("{}: {message}", self.severity.name()),
+        }
+    }
+}
@@ -0,0 +1,205 @@
+/// a line of the difference between two versions of a text
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Change<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// size of the table comparing the parts that differ. beyond it, the old part is taken as replaced
+/// by the new one as a whole
+const MAX_CELLS: usize = 4_000_000;
+
+/// lines of unchanged text shown around changes
+const CONTEXT: usize = 3;
+
+/// the changes turning `old` into `new`, line by line
+pub fn diff<'a>(old: &'a [String], new: &'a [String]) -> Vec<Change<'a>> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (old_middle, new_middle) = (&old[prefix..old.len() - suffix], &new[prefix..new.len() - suffix]);
+
+    let mut changes: Vec<Change> = old[..prefix].iter().map(|line| Change::Same(line)).collect();
+    if old_middle.len().saturating_mul(new_middle.len()) > MAX_CELLS {
+        changes.extend(old_middle.iter().map(|line| Change::Removed(line)));
+        changes.extend(new_middle.iter().map(|line| Change::Added(line)));
+    } else {
+        changes.extend(common_subsequence(old_middle, new_middle));
+    }
+    changes.extend(old[old.len() - suffix..].iter().map(|line| Change::Same(line)));
+    changes
+}
+
+/// how many lines are added and removed, and where the first change is
+pub fn summary(changes: &[Change]) -> String {
+    let added = changes.iter().filter(|change| matches!(change, Change::Added(_))).count();
+    let removed = changes.iter().filter(|change| matches!(change, Change::Removed(_))).count();
+    let first = changes
+        .iter()
+        .take_while(|change| matches!(change, Change::Same(_)))
+        .count();
+    format!("+{added} -{removed} lines from line {}", first + 1)
+}
+
+/// the changes in unified diff format, with a few lines of context around each
+pub fn unified(changes: &[Change], old_name: &str, new_name: &str) -> Vec<String> {
+    let mut ret = vec![format!("--- {old_name}"), format!("+++ {new_name}")];
+    let changed: Vec<usize> = (0..changes.len())
+        .filter(|index| !matches!(changes[*index], Change::Same(_)))
+        .collect();
+    let mut index = 0;
+    while index < changed.len() {
+        // changes close to each other share a hunk
+        let start = changed[index].saturating_sub(CONTEXT);
+        let mut end = changed[index] + 1;
+        while index + 1 < changed.len() && changed[index + 1] <= end + 2 * CONTEXT {
+            index += 1;
+            end = changed[index] + 1;
+        }
+        index += 1;
+        let end = (end + CONTEXT).min(changes.len());
+
+        let (mut old_line, mut new_line) = (0, 0);
+        for change in &changes[..start] {
+            match change {
+                Change::Same(_) => (old_line, new_line) = (old_line + 1, new_line + 1),
+                Change::Removed(_) => old_line += 1,
+                Change::Added(_) => new_line += 1,
+            }
+        }
+        let hunk = &changes[start..end];
+        let old_count = hunk.iter().filter(|change| !matches!(change, Change::Added(_))).count();
+        let new_count = hunk.iter().filter(|change| !matches!(change, Change::Removed(_))).count();
+        // an empty side names the line before it, like `diff -u`
+        let first = |line: usize, count: usize| if count == 0 { line } else { line + 1 };
+        ret.push(format!("@@ -{},{old_count} +{},{new_count} @@", first(old_line, old_count), first(new_line, new_count)));
+        ret.extend(hunk.iter().map(|change| match change {
+            Change::Same(line) => format!(" {line}"),
+            Change::Removed(line) => format!("-{line}"),
+            Change::Added(line) => format!("+{line}"),
+        }));
+    }
+    ret
+}
+
+/// changes keeping the longest common subsequence of lines
+fn common_subsequence<'a>(old: &'a [String], new: &'a [String]) -> Vec<Change<'a>> {
+    let width = new.len() + 1;
+    // lengths of the common subsequences of `old[i..]` and `new[j..]`
+    let mut lengths = vec![0_u32; (old.len() + 1) * width];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i * width + j] = if old[i] == new[j] {
+                lengths[(i + 1) * width + j + 1] + 1
+            } else {
+                lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+            };
+        }
+    }
+    let mut changes = Vec::with_capacity(old.len().max(new.len()));
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            changes.push(Change::Same(&old[i]));
+            (i, j) = (i + 1, j + 1);
+        } else if j == new.len() || (i < old.len() && lengths[(i + 1) * width + j] >= lengths[i * width + j + 1]) {
+            changes.push(Change::Removed(&old[i]));
+            i += 1;
+        } else {
+            changes.push(Change::Added(&new[j]));
+            j += 1;
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(texts: &[&str]) -> Vec<String> {
+        texts.iter().map(ToString::to_string).collect()
+    }
+
+    /// the changes as lines of a unified diff, without the hunk headers
+    fn changes(old: &[&str], new: &[&str]) -> Vec<String> {
+        let (old, new) = (lines(old), lines(new));
+        diff(&old, &new)
+            .iter()
+            .map(|change| match change {
+                Change::Same(line) => format!(" {line}"),
+                Change::Removed(line) => format!("-{line}"),
+                Change::Added(line) => format!("+{line}"),
+            })
+            .collect()
+    }
+
+    fn hunks(old: &[&str], new: &[&str]) -> Vec<String> {
+        let (old, new) = (lines(old), lines(new));
+        let mut ret = unified(&diff(&old, &new), "old", "new");
+        assert_eq!(ret.drain(..2).collect::<Vec<_>>(), ["--- old", "+++ new"]);
+        ret
+    }
+
+    #[test]
+    fn the_longest_common_lines_are_kept() {
+        assert_eq!(changes(&["x", "a", "b", "c", "y"], &["a", "z", "b", "c"]), ["-x", " a", "+z", " b", " c", "-y"]);
+        assert_eq!(changes(&[], &["a"]), ["+a"]);
+        assert_eq!(changes(&["a"], &[]), ["-a"]);
+        assert_eq!(changes(&["a", "b"], &["a", "b"]), [" a", " b"]);
+        let (old, new) = (lines(&["a", "b", "c"]), lines(&["b", "x", "y"]));
+        assert_eq!(summary(&diff(&old, &new)), "+2 -2 lines from line 1");
+    }
+
+    #[test]
+    fn hunks_of_inserted_lines() {
+        assert_eq!(hunks(&["a", "b"], &["a", "x", "b"]), ["@@ -1,2 +1,3 @@", " a", "+x", " b"]);
+        assert_eq!(hunks(&["a"], &["a", "b"]), ["@@ -1,1 +1,2 @@", " a", "+b"]);
+        assert_eq!(hunks(&[], &["a"]), ["@@ -0,0 +1,1 @@", "+a"]);
+    }
+
+    #[test]
+    fn hunks_of_deleted_lines() {
+        assert_eq!(hunks(&["a", "b", "c"], &["a", "c"]), ["@@ -1,3 +1,2 @@", " a", "-b", " c"]);
+        assert_eq!(hunks(&["a", "b"], &[]), ["@@ -1,2 +0,0 @@", "-a", "-b"]);
+    }
+
+    #[test]
+    fn hunks_at_the_start_and_end_of_the_file() {
+        let old = ["a", "b", "c", "d", "e", "f"];
+        assert_eq!(
+            hunks(&old, &["X", "b", "c", "d", "e", "f"]),
+            ["@@ -1,4 +1,4 @@", "-a", "+X", " b", " c", " d"]
+        );
+        assert_eq!(
+            hunks(&old, &["a", "b", "c", "d", "e", "Y"]),
+            ["@@ -3,4 +3,4 @@", " c", " d", " e", "-f", "+Y"]
+        );
+        assert!(hunks(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn changes_far_apart_get_hunks_of_their_own() {
+        let old: Vec<String> = (1..=20).map(|n| n.to_string()).collect();
+        let old: Vec<&str> = old.iter().map(String::as_str).collect();
+        let mut new = old.clone();
+        new[1] = "two";
+        new[18] = "nineteen";
+        assert_eq!(
+            hunks(&old, &new),
+            [
+                "@@ -1,5 +1,5 @@", " 1", "-2", "+two", " 3", " 4", " 5",
+                "@@ -16,5 +16,5 @@", " 16", " 17", " 18", "-19", "+nineteen", " 20",
+            ]
+        );
+        // close enough, they share one
+        new[18] = "19";
+        new[8] = "nine";
+        assert_eq!(hunks(&old, &new).iter().filter(|line| line.starts_with("@@")).count(), 1);
+    }
+}
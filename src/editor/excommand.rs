@@ -0,0 +1,340 @@
+use std::ops::Range;
+
+use regex::{Regex, RegexBuilder};
+
+/// where a line address starts counting from
+#[derive(Clone, Copy)]
+enum Anchor {
+    /// 1-based line number
+    Line(usize),
+    /// `.`, the line the caret is on
+    Current,
+    /// `$`, the last line
+    Last,
+    /// `'<`, the first selected line
+    SelectionStart,
+    /// `'>`, the last selected line
+    SelectionEnd,
+}
+
+/// a line address typed in the command line, like `12`, `.-1` or `$`
+#[derive(Clone, Copy)]
+struct Address {
+    anchor: Anchor,
+    offset: isize,
+}
+
+/// a parsed ex command, like `10,20d` or `%s/a/b/g`
+pub struct ExCommand {
+    /// zero, one or two addresses before the command name
+    addresses: Vec<Address>,
+    pub name: String,
+    /// whether the name is followed by `!`
+    pub bang: bool,
+    pub args: String,
+}
+
+impl ExCommand {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut rest = text.trim_start().trim_start_matches(':').trim_start();
+        let mut addresses = Vec::new();
+        if let Some(after) = rest.strip_prefix('%') {
+            addresses.push(Address { anchor: Anchor::Line(1), offset: 0 });
+            addresses.push(Address { anchor: Anchor::Last, offset: 0 });
+            rest = after;
+        } else if let Some((address, after)) = Address::parse(rest)? {
+            addresses.push(address);
+            rest = after;
+            if let Some(after) = rest.strip_prefix(',') {
+                let (address, after) = Address::parse(after)?
+                    .ok_or_else(|| "E14: Invalid address".to_string())?;
+                addresses.push(address);
+                rest = after;
+            }
+        }
+
+        let rest = rest.trim_start();
+        let name_len = rest
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rest.len());
+        // `s` may be directly followed by its pattern, like `s/a/b/`
+        let name_len = if rest.starts_with('s') && !rest[1..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+            1
+        } else {
+            name_len
+        };
+        let (name, rest) = rest.split_at(name_len);
+        let (bang, args) = match rest.strip_prefix('!') {
+            Some(args) => (true, args),
+            None => (false, rest),
+        };
+        Ok(Self {
+            addresses,
+            name: name.to_string(),
+            bang,
+            args: args.trim().to_string(),
+        })
+    }
+    /// whether the command name is given abbreviation of `full`, at least `min_len` long
+    pub fn is(&self, full: &str, min_len: usize) -> bool {
+        self.name.len() >= min_len && full.starts_with(&self.name)
+    }
+    pub fn has_range(&self) -> bool {
+        !self.addresses.is_empty()
+    }
+    /// resolve the addresses into 0-based line indexes, `selected` being the lines of the selection.
+    /// without any address, the range covers the current line
+    pub fn line_range(&self, current: usize, total: usize, selected: Option<Range<usize>>) -> Result<Range<usize>, String> {
+        let mut resolved = Vec::with_capacity(2);
+        for address in &self.addresses {
+            resolved.push(address.resolve(current, total, selected.clone())?);
+        }
+        let (start, end) = match resolved.as_slice() {
+            [] => (current, current),
+            [line] => (*line, *line),
+            [first, second, ..] => (*first.min(second), *first.max(second)),
+        };
+        Ok(start..end + 1)
+    }
+}
+
+impl Address {
+    /// parse an address at the start of given text, returns the remaining text
+    fn parse(text: &str) -> Result<Option<(Self, &str)>, String> {
+        let digits = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+        let (anchor, mut rest) = if digits > 0 {
+            let line = text[..digits]
+                .parse()
+                .map_err(|_| "E14: Invalid address".to_string())?;
+            (Anchor::Line(line), &text[digits..])
+        } else if let Some(rest) = text.strip_prefix('.') {
+            (Anchor::Current, rest)
+        } else if let Some(rest) = text.strip_prefix('$') {
+            (Anchor::Last, rest)
+        } else if let Some(rest) = text.strip_prefix("'<") {
+            (Anchor::SelectionStart, rest)
+        } else if let Some(rest) = text.strip_prefix("'>") {
+            (Anchor::SelectionEnd, rest)
+        } else if text.starts_with(['+', '-']) {
+            (Anchor::Current, text)
+        } else {
+            return Ok(None);
+        };
+
+        let mut offset: isize = 0;
+        while let Some(sign) = rest.chars().next().filter(|c| *c == '+' || *c == '-') {
+            let after = &rest[1..];
+            let digits = after.find(|c: char| !c.is_ascii_digit()).unwrap_or(after.len());
+            let amount: isize = if digits == 0 {
+                1
+            } else {
+                after[..digits]
+                    .parse()
+                    .map_err(|_| "E14: Invalid address".to_string())?
+            };
+            offset += if sign == '+' { amount } else { -amount };
+            rest = &after[digits..];
+        }
+        Ok(Some((Self { anchor, offset }, rest)))
+    }
+    /// 0-based line index this address points to
+    fn resolve(&self, current: usize, total: usize, selected: Option<Range<usize>>) -> Result<usize, String> {
+        let base = match (self.anchor, selected) {
+            (Anchor::Line(line), _) => line.saturating_sub(1),
+            (Anchor::Current, _) => current,
+            (Anchor::Last, _) => total.saturating_sub(1),
+            (Anchor::SelectionStart, Some(lines)) => lines.start,
+            (Anchor::SelectionEnd, Some(lines)) => lines.end.saturating_sub(1),
+            (Anchor::SelectionStart | Anchor::SelectionEnd, None) => return Err("E20: Mark not set".to_string()),
+        };
+        let line = base
+            .checked_add_signed(self.offset)
+            .ok_or_else(|| "E16: Invalid range".to_string())?;
+        if line >= total.max(1) {
+            return Err("E16: Invalid range".to_string());
+        }
+        Ok(line)
+    }
+}
+
+/// arguments of `:s/pattern/replacement/flags`
+pub struct Substitution {
+    pub pattern: Regex,
+    /// replacement in the syntax of the regex crate
+    pub replacement: String,
+    /// replace every match in a line rather than the first one
+    pub global: bool,
+}
+
+impl Substitution {
+    /// `&` and `\1`..`\9` in the replacement refer to the whole match and to capture groups
+    pub fn parse(args: &str) -> Result<Self, String> {
+        let mut chars = args.chars();
+        let delimiter = chars
+            .next()
+            .filter(|c| !c.is_alphanumeric() && *c != '\\' && *c != '"')
+            .ok_or_else(|| "E486: Pattern not found".to_string())?;
+        let mut parts = vec![String::new()];
+        let mut escaped = false;
+        for c in chars {
+            let current = parts.len() - 1;
+            if escaped {
+                // an escaped delimiter stands for itself, other escapes are kept. in the pattern
+                // it's escaped for the regex, as `.` or `+` would mean something else there
+                match current {
+                    0 if c == delimiter => parts[current].push_str(&regex::escape(&c.to_string())),
+                    // `\&` is what keeps `&` from standing for the match in the replacement
+                    _ if c == delimiter && c != '&' => parts[current].push(c),
+                    _ => {
+                        parts[current].push('\\');
+                        parts[current].push(c);
+                    }
+                }
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == delimiter && parts.len() < 3 {
+                parts.push(String::new());
+            } else {
+                parts[current].push(c);
+            }
+        }
+        if escaped {
+            let current = parts.len() - 1;
+            parts[current].push('\\');
+        }
+
+        let mut parts = parts.into_iter();
+        let pattern = parts.next().unwrap_or_default();
+        let replacement = parts.next().unwrap_or_default();
+        let flags = parts.next().unwrap_or_default();
+        if pattern.is_empty() {
+            return Err("E35: No previous regular expression".to_string());
+        }
+        if let Some(flag) = flags.chars().find(|c| !matches!(c, 'g' | 'i')) {
+            return Err(format!("E488: Trailing characters: {flag}"));
+        }
+        let pattern = RegexBuilder::new(&pattern)
+            .case_insensitive(flags.contains('i'))
+            .build()
+            .map_err(|e| format!("Invalid pattern: {e}"))?;
+        Ok(Self {
+            pattern,
+            replacement: Self::convert_replacement(&replacement),
+            global: flags.contains('g'),
+        })
+    }
+    /// convert vi style replacement to the syntax of the regex crate
+    fn convert_replacement(replacement: &str) -> String {
+        let mut ret = String::new();
+        let mut chars = replacement.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '&' => ret.push_str("${0}"),
+                '$' => ret.push_str("$$"),
+                '\\' => match chars.next() {
+                    Some(digit @ '0'..='9') => ret.push_str(&format!("${{{digit}}}")),
+                    Some('t') => ret.push('\t'),
+                    Some(other) => ret.push(other),
+                    None => ret.push('\\'),
+                },
+                _ => ret.push(c),
+            }
+        }
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(text: &str, current: usize, total: usize, selected: Option<Range<usize>>) -> Result<Range<usize>, String> {
+        ExCommand::parse(text)?.line_range(current, total, selected)
+    }
+
+    fn substitute(args: &str, text: &str) -> String {
+        let substitution = Substitution::parse(args).unwrap();
+        match substitution.global {
+            true => substitution.pattern.replace_all(text, substitution.replacement.as_str()).into_owned(),
+            false => substitution.pattern.replace(text, substitution.replacement.as_str()).into_owned(),
+        }
+    }
+
+    #[test]
+    fn names_bangs_and_arguments_are_split() {
+        let command = ExCommand::parse(" :w! out.txt ").unwrap();
+        assert!(command.name == "w" && command.bang && command.args == "out.txt");
+        assert!(command.is("write", 1) && !command.is("wq", 2));
+        let command = ExCommand::parse("%s/a/b/g").unwrap();
+        assert!(command.name == "s" && command.args == "/a/b/g" && command.has_range());
+        let command = ExCommand::parse("set wrap").unwrap();
+        assert!(command.name == "set" && command.args == "wrap" && !command.has_range());
+        let command = ExCommand::parse("10,20!sort").unwrap();
+        assert!(command.name.is_empty() && command.bang && command.args == "sort");
+    }
+
+    #[test]
+    fn addresses_resolve_to_line_ranges() {
+        assert_eq!(range("d", 4, 10, None), Ok(4..5));
+        assert_eq!(range("%s/a/b/", 4, 10, None), Ok(0..10));
+        assert_eq!(range(".,+3d", 4, 10, None), Ok(4..8));
+        assert_eq!(range("-2,.d", 4, 10, None), Ok(2..5));
+        assert_eq!(range("$", 4, 10, None), Ok(9..10));
+        assert_eq!(range("$-1", 4, 10, None), Ok(8..9));
+        assert_eq!(range("3+1+1", 0, 10, None), Ok(4..5));
+        // backwards ranges are turned around
+        assert_eq!(range("5,2d", 0, 10, None), Ok(1..5));
+        assert_eq!(range("'<,'>s/a/b/", 0, 10, Some(2..5)), Ok(2..5));
+        assert_eq!(range("'<,'>+1d", 0, 10, Some(2..5)), Ok(2..6));
+        assert_eq!(range("'>", 0, 10, Some(7..8)), Ok(7..8));
+    }
+
+    #[test]
+    fn bad_addresses_are_refused() {
+        assert_eq!(range(".,+20d", 4, 10, None), Err("E16: Invalid range".to_string()));
+        assert_eq!(range("-9", 4, 10, None), Err("E16: Invalid range".to_string()));
+        assert_eq!(range("3,d", 4, 10, None), Err("E14: Invalid address".to_string()));
+        assert_eq!(range("'<,'>d", 4, 10, None), Err("E20: Mark not set".to_string()));
+        assert_eq!(range("99999999999999999999999", 4, 10, None), Err("E14: Invalid address".to_string()));
+    }
+
+    #[test]
+    fn escaped_delimiters_are_taken_literally() {
+        assert_eq!(substitute(r".\..x.", "a.b"), "axb");
+        assert_eq!(substitute(r".\..x.", "ab"), "ab");
+        assert_eq!(substitute(r"+a\+b+c\+d+", "aab a+b"), "aab c+d");
+        assert_eq!(substitute(r"/a\/b/c\/d/", "a/b"), "c/d");
+        assert_eq!(substitute(r"|a\|b|x|", "b a|b"), "b x");
+        // other escapes keep their meaning in the regex
+        assert_eq!(substitute(r"/\d+/n/g", "1+23"), "n+n");
+        assert_eq!(substitute(r"/a\.b/x/", "a.b axb"), "x axb");
+    }
+
+    #[test]
+    fn escaped_delimiters_in_the_replacement_are_literal() {
+        assert_eq!(substitute(r"&b&[\&]&", "abc"), "a[&]c");
+        assert_eq!(substitute(r"$b$\$$", "abc"), "a$c");
+        assert_eq!(substitute(r"#b#\##", "abc"), "a#c");
+    }
+
+    #[test]
+    fn replacements_refer_to_the_match_and_groups() {
+        assert_eq!(substitute(r"/(\w+) (\w+)/\2 \1/", "hello world"), "world hello");
+        assert_eq!(substitute("/o/<&>/g", "foo"), "f<o><o>");
+        assert_eq!(substitute(r"/ /\t/", "a b"), "a\tb");
+        assert_eq!(substitute("/a/$1/", "a"), "$1");
+    }
+
+    #[test]
+    fn flags_are_checked() {
+        assert_eq!(substitute("/o/0/", "foo"), "f0o");
+        assert_eq!(substitute("/o/0/g", "foo"), "f00");
+        assert_eq!(substitute("/O/0/gi", "fOo"), "f00");
+        assert_eq!(substitute("/O/0/", "foo"), "foo");
+        assert!(Substitution::parse("/a/b/x").is_err_and(|e| e == "E488: Trailing characters: x"));
+        assert!(Substitution::parse("//b/").is_err_and(|e| e == "E35: No previous regular expression"));
+        assert!(Substitution::parse("xaxbx").is_err());
+        assert!(Substitution::parse("/(/b/").is_err_and(|e| e.starts_with("Invalid pattern")));
+    }
+}
@@ -0,0 +1,38 @@
+use std::path::Path;
+
+/// name of the file type of given path, judged by its extension or name.
+/// used to pick per-filetype settings and language rules
+pub fn detect(path: &str) -> &'static str {
+    let path = Path::new(path);
+    let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+    match name {
+        "Makefile" | "makefile" | "GNUmakefile" => return "make",
+        "Dockerfile" => return "dockerfile",
+        "Cargo.lock" => return "toml",
+        _ => (),
+    }
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    match extension.as_str() {
+        "rs" => "rust",
+        "toml" => "toml",
+        "sh" | "bash" | "zsh" => "sh",
+        "py" => "python",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hpp" | "hh" => "cpp",
+        "go" => "go",
+        "java" => "java",
+        "js" | "mjs" | "cjs" => "javascript",
+        "ts" => "typescript",
+        "json" => "json",
+        "lua" => "lua",
+        "md" | "markdown" => "markdown",
+        "yml" | "yaml" => "yaml",
+        "html" | "htm" => "html",
+        "css" => "css",
+        _ => "text",
+    }
+}
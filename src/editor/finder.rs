@@ -0,0 +1,358 @@
+use std::{cmp::Reverse, fs::File, io::Read};
+
+use ignore::WalkBuilder;
+use unicode_width::UnicodeWidthChar;
+
+use super::{highlight::Highlight, settings::SettingsFile, terminal::{Size, Terminal}, theme::Theme, view::View};
+
+/// most files listed, so that a huge tree does not take all the memory
+const MAX_FILES: usize = 100_000;
+
+/// most rows of the list of matches
+const MAX_LIST_ROWS: usize = 10;
+
+/// bytes of a file read for its preview
+const PREVIEW_BYTES: u64 = 64 * 1024;
+
+// scores of matched characters
+const MATCH: i64 = 16;
+const CONSECUTIVE: i64 = 24;
+/// the first character of a path component
+const AFTER_SLASH: i64 = 40;
+/// the first character of a word, like after `_` or a lowercase letter in camel case
+const WORD_START: i64 = 28;
+/// any character of the file name, past the last `/`
+const IN_FILE_NAME: i64 = 12;
+/// skipping characters costs this, and one more for each of them
+const GAP: i64 = 3;
+
+/// files under given directory as paths relative to it, sorted. hidden files and the ones
+/// `.gitignore` or `.ignore` files leave out are skipped
+pub fn list_files(root: &str) -> Vec<String> {
+    let mut files: Vec<String> = WalkBuilder::new(root)
+        // .gitignore counts also outside of git repositories
+        .require_git(false)
+        .build()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_some_and(|kind| kind.is_file()))
+        .filter_map(|entry| {
+            let path = entry.path().strip_prefix(root).ok()?;
+            Some(path.to_string_lossy().replace('\\', "/"))
+        })
+        .take(MAX_FILES)
+        .collect();
+    files.sort_unstable();
+    files
+}
+
+/// how well a path matches a query typed in the finder, and the indexes of the characters
+/// matching it. `None` unless the characters of the query appear in the path in order.
+/// case is ignored unless the query has capitals. matches at the start of path components and
+/// words, runs of consecutive characters and matches in the file name rank higher
+pub fn fuzzy_match(query: &str, path: &str) -> Option<(i64, Vec<usize>)> {
+    let ignore_case = !query.chars().any(char::is_uppercase);
+    let fold = |c: char| if ignore_case { c.to_lowercase().next().unwrap_or(c) } else { c };
+    let query: Vec<char> = query.chars().filter(|c| !c.is_whitespace()).map(fold).collect();
+    let original: Vec<char> = path.chars().collect();
+    let path: Vec<char> = original.iter().copied().map(fold).collect();
+
+    // cheap check first, most paths do not match at all
+    let mut rest = path.iter();
+    if !query.iter().all(|c| rest.any(|p| p == c)) {
+        return None;
+    }
+    if query.is_empty() {
+        return Some((0, Vec::new()));
+    }
+
+    let name_start = original.iter().rposition(|c| *c == '/').map_or(0, |index| index + 1);
+    let bonus = |index: usize| {
+        let previous = index.checked_sub(1).map(|index| original[index]);
+        let start = match previous {
+            None | Some('/') => AFTER_SLASH,
+            Some('_' | '-' | '.' | ' ') => WORD_START,
+            Some(previous) if previous.is_lowercase() && original[index].is_uppercase() => WORD_START,
+            _ => 0,
+        };
+        MATCH + start + if index >= name_start { IN_FILE_NAME } else { 0 }
+    };
+
+    // scores[i][j]: the best score of the first i + 1 characters of the query, the last one
+    // matching the path at j. from[i][j] is where the one before matched
+    const NONE: i64 = i64::MIN / 2;
+    let (rows, columns) = (query.len(), path.len());
+    let mut scores = vec![vec![NONE; columns]; rows];
+    let mut from = vec![vec![0; columns]; rows];
+    for (j, c) in path.iter().enumerate() {
+        if *c == query[0] {
+            scores[0][j] = bonus(j);
+        }
+    }
+    for i in 1..rows {
+        // the best of scores[i - 1][k] + k for k up to j - 2, to reach j over a gap
+        let mut best_gap = (NONE, 0);
+        for j in i..columns {
+            if j >= 2 && scores[i - 1][j - 2] > NONE {
+                let candidate = scores[i - 1][j - 2] + (j - 2) as i64;
+                if candidate >= best_gap.0 {
+                    best_gap = (candidate, j - 2);
+                }
+            }
+            if path[j] != query[i] {
+                continue;
+            }
+            let after_gap = best_gap.0 - j as i64 + 1 - GAP;
+            let consecutive = scores[i - 1][j - 1] + CONSECUTIVE;
+            let (score, previous) = if scores[i - 1][j - 1] > NONE && consecutive >= after_gap {
+                (consecutive, j - 1)
+            } else if best_gap.0 > NONE {
+                (after_gap, best_gap.1)
+            } else {
+                continue;
+            };
+            scores[i][j] = score + bonus(j);
+            from[i][j] = previous;
+        }
+    }
+
+    let (mut j, score) = scores[rows - 1]
+        .iter()
+        .copied()
+        .enumerate()
+        .max_by_key(|(j, score)| (*score, Reverse(*j)))?;
+    if score <= NONE {
+        return None;
+    }
+    let mut positions = vec![0; rows];
+    for i in (0..rows).rev() {
+        positions[i] = j;
+        j = from[i][j];
+    }
+    Some((score, positions))
+}
+
+/// a file picker: the files of the working directory, the ones matching what is typed ranked
+/// best first, and a preview of the selected one
+#[derive(Default)]
+pub struct Finder {
+    /// `None` while the files are being listed
+    files: Option<Vec<String>>,
+    query: String,
+    /// indexes of the matching files and of the characters matching the query, best first
+    matches: Vec<(usize, Vec<usize>)>,
+    /// index into `matches`
+    selected: usize,
+    /// the file previewed and a view of its start
+    preview: Option<(String, View)>,
+}
+
+impl Finder {
+    pub fn set_files(&mut self, files: Vec<String>) {
+        self.files = Some(files);
+        self.update_matches();
+    }
+    pub fn set_query(&mut self, query: &str) {
+        if query != self.query {
+            self.query = query.to_string();
+            self.update_matches();
+        }
+    }
+    fn update_matches(&mut self) {
+        let files = self.files.as_deref().unwrap_or_default();
+        let mut matches: Vec<(i64, usize, Vec<usize>)> = files
+            .iter()
+            .enumerate()
+            .filter_map(|(index, path)| fuzzy_match(&self.query, path).map(|(score, positions)| (score, index, positions)))
+            .collect();
+        // shorter paths first among equally good ones
+        matches.sort_by_key(|(score, index, _)| (Reverse(*score), files[*index].len(), *index));
+        self.matches = matches.into_iter().map(|(_, index, positions)| (index, positions)).collect();
+        self.selected = 0;
+    }
+    /// select the next match, or the previous one
+    pub fn move_selection(&mut self, backward: bool) {
+        let last = self.matches.len().saturating_sub(1);
+        self.selected = match backward {
+            true => self.selected.checked_sub(1).unwrap_or(last),
+            false if self.selected >= last => 0,
+            false => self.selected + 1,
+        };
+    }
+    pub fn selected_path(&self) -> Option<&str> {
+        let (index, _) = self.matches.get(self.selected)?;
+        self.files.as_ref().map(|files| files[*index].as_str())
+    }
+
+    // region: rendering
+
+    /// draw over the area of the views: the preview on top, a line telling how many files
+    /// match, and the list of matches under it
+    pub fn render(&mut self, size: Size, settings: &SettingsFile, theme: &Theme) {
+        let Size { height, width } = size;
+        let list_rows = (height / 2).clamp(1, MAX_LIST_ROWS).min(height);
+        let preview_rows = height.saturating_sub(list_rows + 1);
+        let mut row = 0;
+        if preview_rows > 0 {
+            self.render_preview(Size { height: preview_rows, width }, settings, theme);
+            row = preview_rows;
+        }
+        if row + list_rows < height {
+            let count = match &self.files {
+                None => "listing files...".to_string(),
+                Some(files) => format!("{}/{}", self.matches.len(), files.len()),
+            };
+            let line = format!("── {count} {}", "─".repeat(width));
+            let line: String = line.chars().take(width).collect();
+            let ret = Terminal::print_highlighted_at(row, &[(line, Some(Highlight::Gutter))], theme);
+            debug_assert!(ret.is_ok(), "Failed to render finder!");
+            row += 1;
+        }
+        // scrolled so that the selected match shows
+        let top = (self.selected + 1).saturating_sub(list_rows);
+        for index in top..top + list_rows {
+            let segments = self.list_row(index, width);
+            let ret = Terminal::print_highlighted_at(row, &segments, theme);
+            debug_assert!(ret.is_ok(), "Failed to render finder!");
+            row += 1;
+        }
+    }
+    /// a match in the list, its matching characters highlighted
+    fn list_row(&self, index: usize, width: usize) -> Vec<(String, Option<Highlight>)> {
+        let (Some((file, positions)), Some(files)) = (self.matches.get(index), &self.files) else {
+            return Vec::new();
+        };
+        let selected = index == self.selected;
+        let base = selected.then_some(Highlight::Selection);
+        let mut segments: Vec<(String, Option<Highlight>)> = vec![((if selected { "> " } else { "  " }).to_string(), base)];
+        let mut used = 2;
+        for (position, c) in files[*file].chars().enumerate() {
+            used += c.width().unwrap_or(0);
+            if used > width {
+                break;
+            }
+            let highlight = if positions.contains(&position) { Some(Highlight::Search) } else { base };
+            match segments.last_mut() {
+                Some((text, last)) if *last == highlight => text.push(c),
+                _ => segments.push((c.to_string(), highlight)),
+            }
+        }
+        if selected && used < width {
+            segments.push((" ".repeat(width - used), base));
+        }
+        segments
+    }
+    /// the start of the selected file, highlighted like it is when opened
+    fn render_preview(&mut self, size: Size, settings: &SettingsFile, theme: &Theme) {
+        let path = self.selected_path().unwrap_or_default().to_string();
+        if self.preview.as_ref().is_none_or(|(previewed, _)| *previewed != path) {
+            let lines = if path.is_empty() { vec![String::new()] } else { preview_lines(&path, size.height) };
+            let mut view = View::scratch(&lines);
+            let mut file_settings = settings.for_file((!path.is_empty()).then_some(path.as_str()));
+            file_settings.read_only = true;
+            view.set_settings(file_settings);
+            self.preview = Some((path, view));
+        }
+        if let Some((_, view)) = &mut self.preview {
+            view.resize(size);
+            view.render(theme);
+        }
+    }
+}
+
+/// the first lines of a file, or why there is nothing to show
+fn preview_lines(path: &str, rows: usize) -> Vec<String> {
+    let mut bytes = Vec::new();
+    if let Err(e) = File::open(path).and_then(|file| file.take(PREVIEW_BYTES).read_to_end(&mut bytes)) {
+        return vec![format!("Cannot read {path}: {e}")];
+    }
+    if bytes.contains(&0) {
+        return vec!["(binary file)".to_string()];
+    }
+    let lines: Vec<String> = String::from_utf8_lossy(&bytes).lines().take(rows).map(str::to_string).collect();
+    if lines.is_empty() { vec![String::new()] } else { lines }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn positions(query: &str, path: &str) -> Option<Vec<usize>> {
+        fuzzy_match(query, path).map(|(_, positions)| positions)
+    }
+
+    /// the paths matching the query, best first
+    fn ranked(query: &str, paths: &[&str]) -> Vec<String> {
+        let mut finder = Finder::default();
+        finder.set_files(paths.iter().map(|path| path.to_string()).collect());
+        finder.set_query(query);
+        finder.matches.iter().map(|(index, _)| paths[*index].to_string()).collect()
+    }
+
+    #[test]
+    fn characters_must_appear_in_order() {
+        assert_eq!(positions("fb", "foo/bar.rs"), Some(vec![0, 4]));
+        assert_eq!(positions("bf", "foo/bar.rs"), None);
+        assert_eq!(positions("x", "foo/bar.rs"), None);
+        assert_eq!(positions("foobarx", "foo/bar.rs"), None);
+        assert_eq!(fuzzy_match("", "foo"), Some((0, Vec::new())));
+        // blanks in the query are left out
+        assert_eq!(positions(" f b ", "foo/bar.rs"), Some(vec![0, 4]));
+    }
+
+    #[test]
+    fn capitals_make_the_case_count() {
+        assert!(fuzzy_match("readme", "README.md").is_some());
+        assert!(fuzzy_match("README", "readme.md").is_none());
+        assert_eq!(positions("RM", "src/README.md"), Some(vec![4, 8]));
+    }
+
+    #[test]
+    fn positions_count_characters() {
+        assert_eq!(positions("éb", "café/bar.rs"), Some(vec![3, 5]));
+        assert_eq!(positions("ß", "straße.txt"), Some(vec![4]));
+    }
+
+    #[test]
+    fn the_best_spots_are_picked() {
+        // the `m` of the file name rather than the one of `mod`
+        assert_eq!(positions("main", "mod/x/main.rs"), Some(vec![6, 7, 8, 9]));
+        // a run of characters rather than scattered ones
+        assert_eq!(positions("bar", "b_a_r/bar.rs"), Some(vec![6, 7, 8]));
+        // the start of a component rather than the middle of a word
+        assert_eq!(positions("v", "over/view.rs"), Some(vec![5]));
+        assert_eq!(positions("mv", "editor/modal_view.rs"), Some(vec![7, 13]));
+        assert_eq!(positions("tc", "editor/TextCursor.rs"), Some(vec![7, 11]));
+        assert_eq!(positions("TC", "editor/TextCursor.rs"), Some(vec![7, 11]));
+        assert_eq!(positions("TC", "editor/textcursor.rs"), None);
+    }
+
+    #[test]
+    fn better_matches_rank_first() {
+        // word starts in the file name beat a run in the middle of a directory's name
+        let paths = ["src/domain/lib.rs", "src/main.rs", "examples/my_app_in.rs", "main.rs"];
+        assert_eq!(ranked("main", &paths), ["main.rs", "src/main.rs", "examples/my_app_in.rs", "src/domain/lib.rs"]);
+        let paths = ["src/editor/view.rs", "src/editor/viewport/mod.rs", "README.md"];
+        assert_eq!(ranked("view", &paths), ["src/editor/view.rs", "src/editor/viewport/mod.rs"]);
+        assert_eq!(ranked("edvi", &paths), ["src/editor/view.rs", "src/editor/viewport/mod.rs"]);
+        assert_eq!(ranked("", &paths), ["README.md", "src/editor/view.rs", "src/editor/viewport/mod.rs"]);
+    }
+
+    #[test]
+    fn selection_wraps_around() {
+        let mut finder = Finder::default();
+        assert_eq!(finder.selected_path(), None);
+        finder.set_files(vec!["a.rs".to_string(), "b.rs".to_string(), "c.txt".to_string()]);
+        finder.set_query("rs");
+        assert_eq!(finder.selected_path(), Some("a.rs"));
+        finder.move_selection(true);
+        assert_eq!(finder.selected_path(), Some("b.rs"));
+        finder.move_selection(false);
+        assert_eq!(finder.selected_path(), Some("a.rs"));
+        finder.move_selection(false);
+        finder.set_query("c");
+        assert_eq!(finder.selected_path(), Some("c.txt"));
+        finder.set_query("zzz");
+        finder.move_selection(false);
+        assert_eq!(finder.selected_path(), None);
+    }
+}
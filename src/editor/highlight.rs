@@ -0,0 +1,53 @@
+use super::diagnostic::Severity;
+
+/// why a part of the screen is drawn differently from plain text
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Highlight {
+    /// text selected in visual mode
+    Selection,
+    /// the column marked by the ruler setting
+    Ruler,
+    /// the bracket at the caret and the one matching it
+    Bracket,
+    /// carets other than the main one, which is the terminal's cursor
+    Cursor,
+    /// matches of the last search
+    Search,
+    /// the sign column left of the text
+    Gutter,
+    /// the sign and the message of a problem
+    Diagnostic(Severity),
+    /// the text a problem is about
+    Underline(Severity),
+    Keyword,
+    Type,
+    String,
+    Comment,
+    Number,
+    Constant,
+}
+
+impl Highlight {
+    /// name of the theme element giving its style
+    pub const fn element(self) -> &'static str {
+        match self {
+            Self::Selection => "selection",
+            Self::Ruler => "ruler",
+            Self::Bracket => "bracket",
+            Self::Cursor => "cursor",
+            Self::Search => "search",
+            Self::Gutter => "gutter",
+            Self::Diagnostic(severity) => severity.name(),
+            Self::Underline(Severity::Error) => "error_underline",
+            Self::Underline(Severity::Warning) => "warning_underline",
+            Self::Underline(Severity::Information) => "info_underline",
+            Self::Underline(Severity::Hint) => "hint_underline",
+            Self::Keyword => "keyword",
+            Self::Type => "type",
+            Self::String => "string",
+            Self::Comment => "comment",
+            Self::Number => "number",
+            Self::Constant => "constant",
+        }
+    }
+}
@@ -0,0 +1,317 @@
+use std::ops::Range;
+
+use super::{line::Line, view::Location};
+
+/// lines `start..start + len` took the place of the lines in `old`
+struct Splice {
+    start: usize,
+    old: Vec<String>,
+    len: usize,
+}
+
+/// edits undone and redone together, with the caret before and after them
+struct Group {
+    splices: Vec<Splice>,
+    before: Location,
+    after: Location,
+    /// whether the group only typed graphemes into a line, so that typing right after it joins it
+    typing: bool,
+    /// tells the step apart from the others, once it is in the history
+    id: usize,
+}
+
+impl Group {
+    const fn new(caret: Location) -> Self {
+        Self {
+            splices: Vec::new(),
+            before: caret,
+            after: caret,
+            typing: true,
+            id: 0,
+        }
+    }
+    /// add a splice, folding it into the last one when it replaces exactly what that one put in
+    fn push(&mut self, splice: Splice) {
+        if let Some(last) = self.splices.last_mut() {
+            if last.start == splice.start && last.len == splice.old.len() {
+                last.len = splice.len;
+                return;
+            }
+        }
+        self.splices.push(splice);
+    }
+    /// apply the opposite of every splice, last first, adding the lines each replaced to `edits`.
+    /// returns the group that redoes them, its splices in the order they were just applied
+    fn revert(self, lines: &mut Vec<Line>, edits: &mut Vec<(Range<usize>, usize)>) -> Self {
+        let splices: Vec<Splice> = self
+            .splices
+            .into_iter()
+            .rev()
+            .map(|Splice { start, old, len }| {
+                let start = start.min(lines.len());
+                let end = (start + len).min(lines.len());
+                let removed = lines
+                    .splice(start..end, old.iter().map(|text| Line::from(text)))
+                    .map(|line| line.to_string())
+                    .collect();
+                edits.push((start..end, old.len()));
+                Splice { start, old: removed, len: old.len() }
+            })
+            .collect();
+        Self {
+            splices,
+            before: self.after,
+            after: self.before,
+            typing: false,
+            id: self.id,
+        }
+    }
+}
+
+/// changes made to a buffer, to be undone and redone
+#[derive(Default)]
+pub struct History {
+    undo: Vec<Group>,
+    redo: Vec<Group>,
+    /// group collecting edits while a change is going on
+    open: Option<Group>,
+    /// how many changes are going on, edits are grouped until the outermost one ends
+    depth: usize,
+    /// id of the last step pushed
+    last_id: usize,
+}
+
+impl History {
+    /// start a change. changes started while another goes on are part of it
+    pub fn begin(&mut self, caret: Location) {
+        self.depth += 1;
+        if self.open.is_none() {
+            self.open = Some(Group::new(caret));
+        }
+    }
+    /// finish a change. once the outermost one ends, its edits become one undo step
+    pub fn end(&mut self, caret: Location) {
+        self.depth = self.depth.saturating_sub(1);
+        if self.depth > 0 {
+            return;
+        }
+        let Some(mut group) = self.open.take() else {
+            return;
+        };
+        if group.splices.is_empty() {
+            return;
+        }
+        group.after = caret;
+        if let Some(last) = self.undo.last_mut() {
+            let same_line = last.splices.last().map(|splice| splice.start) == group.splices.first().map(|splice| splice.start);
+            if group.typing && last.typing && last.after == group.before && same_line {
+                for splice in group.splices {
+                    last.push(splice);
+                }
+                last.after = group.after;
+                return;
+            }
+        }
+        self.push(group);
+    }
+    /// remember the lines in range before they are replaced by `len` lines.
+    /// `typing` tells that graphemes are typed into a single line
+    pub fn record(&mut self, lines: &[Line], range: Range<usize>, len: usize, typing: bool) {
+        let old = lines[range.start..range.end.min(lines.len())]
+            .iter()
+            .map(Line::to_string)
+            .collect();
+        let splice = Splice { start: range.start, old, len };
+        self.redo.clear();
+        match self.open.as_mut() {
+            Some(group) => {
+                group.typing &= typing;
+                group.push(splice);
+            }
+            // edits made outside of a change are a step of their own
+            None => {
+                let caret = Location { grapheme_index: 0, line_index: range.start };
+                let mut group = Group::new(caret);
+                group.typing = false;
+                group.push(splice);
+                self.push(group);
+            }
+        }
+    }
+    /// revert the last step, returns where the caret was before it
+    pub fn undo(&mut self, lines: &mut Vec<Line>, caret: Location, edits: &mut Vec<(Range<usize>, usize)>) -> Option<Location> {
+        self.close_open(caret);
+        let group = self.undo.pop()?;
+        let caret = group.before;
+        self.redo.push(group.revert(lines, edits));
+        Some(caret)
+    }
+    /// apply the last undone step again, returns where the caret was after it
+    pub fn redo(&mut self, lines: &mut Vec<Line>, caret: Location, edits: &mut Vec<(Range<usize>, usize)>) -> Option<Location> {
+        self.close_open(caret);
+        let group = self.redo.pop()?;
+        let caret = group.before;
+        self.undo.push(group.revert(lines, edits));
+        Some(caret)
+    }
+    /// edits of a change still going on become a step of their own, so that undoing
+    /// in the middle of a change, e.g. from a macro, sees them
+    fn close_open(&mut self, caret: Location) {
+        let Some(group) = self.open.as_mut() else {
+            return;
+        };
+        if group.splices.is_empty() {
+            return;
+        }
+        let mut closed = std::mem::replace(group, Group::new(caret));
+        closed.after = caret;
+        closed.typing = false;
+        self.push(closed);
+    }
+    /// add a new step to undo
+    fn push(&mut self, mut group: Group) {
+        self.last_id += 1;
+        group.id = self.last_id;
+        self.undo.push(group);
+    }
+    /// tells where in the history the lines are: the same state means the same lines
+    pub fn state(&self) -> usize {
+        self.undo.last().map_or(0, |group| group.id)
+    }
+    /// the current state is to be told apart from what follows, e.g. because it was saved.
+    /// typing joins no step made so far from now on
+    pub fn mark(&mut self) -> usize {
+        if let Some(last) = self.undo.last_mut() {
+            last.typing = false;
+        }
+        self.state()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(line_index: usize, grapheme_index: usize) -> Location {
+        Location { grapheme_index, line_index }
+    }
+
+    /// lines and their history, edited like a buffer does
+    struct Text {
+        lines: Vec<Line>,
+        history: History,
+    }
+
+    impl Text {
+        fn new(texts: &[&str]) -> Self {
+            Self { lines: texts.iter().map(|text| Line::from(text)).collect(), history: History::default() }
+        }
+        fn texts(&self) -> Vec<String> {
+            self.lines.iter().map(Line::to_string).collect()
+        }
+        /// type a character at given location, as a change of its own
+        fn type_at(&mut self, location: Location, c: char) {
+            self.history.begin(location);
+            self.history.record(&self.lines, location.line_index..location.line_index + 1, 1, true);
+            self.lines[location.line_index].insert_at(location.grapheme_index, c);
+            self.history.end(at(location.line_index, location.grapheme_index + 1));
+        }
+        fn replace(&mut self, range: Range<usize>, texts: &[&str]) {
+            self.history.record(&self.lines, range.clone(), texts.len(), false);
+            self.lines.splice(range, texts.iter().map(|text| Line::from(text)));
+        }
+        fn undo(&mut self) -> Option<Location> {
+            self.history.undo(&mut self.lines, Location::default(), &mut Vec::new())
+        }
+        fn redo(&mut self) -> Option<Location> {
+            self.history.redo(&mut self.lines, Location::default(), &mut Vec::new())
+        }
+    }
+
+    #[test]
+    fn typing_in_a_row_is_undone_at_once() {
+        let mut text = Text::new(&["ab"]);
+        for (index, c) in "xyz".chars().enumerate() {
+            text.type_at(at(0, 1 + index), c);
+        }
+        assert_eq!(text.texts(), ["axyzb"]);
+        assert!(text.undo() == Some(at(0, 1)));
+        assert_eq!(text.texts(), ["ab"]);
+        assert!(text.undo().is_none());
+        assert!(text.redo() == Some(at(0, 4)));
+        assert_eq!(text.texts(), ["axyzb"]);
+        assert!(text.redo().is_none());
+    }
+
+    #[test]
+    fn typing_elsewhere_starts_another_step() {
+        let mut text = Text::new(&["ab", "cd"]);
+        text.type_at(at(0, 0), 'x');
+        // the caret moved away in between
+        text.type_at(at(0, 3), 'y');
+        text.type_at(at(1, 0), 'z');
+        assert_eq!(text.texts(), ["xaby", "zcd"]);
+        text.undo();
+        assert_eq!(text.texts(), ["xaby", "cd"]);
+        text.undo();
+        assert_eq!(text.texts(), ["xab", "cd"]);
+        text.undo();
+        assert_eq!(text.texts(), ["ab", "cd"]);
+    }
+
+    #[test]
+    fn a_change_is_one_step_however_nested() {
+        let mut text = Text::new(&["a", "b", "c"]);
+        text.history.begin(at(1, 0));
+        text.replace(1..2, &["B"]);
+        text.history.begin(at(1, 0));
+        text.replace(2..3, &[]);
+        text.replace(0..0, &["0", "1"]);
+        text.history.end(at(0, 0));
+        text.replace(3..3, &["end"]);
+        text.history.end(at(3, 0));
+        assert_eq!(text.texts(), ["0", "1", "a", "end", "B"]);
+        assert!(text.undo() == Some(at(1, 0)));
+        assert_eq!(text.texts(), ["a", "b", "c"]);
+        assert!(text.redo() == Some(at(3, 0)));
+        assert_eq!(text.texts(), ["0", "1", "a", "end", "B"]);
+        text.undo();
+        assert_eq!(text.texts(), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn edits_outside_a_change_are_steps_of_their_own() {
+        let mut text = Text::new(&["a", "b"]);
+        text.replace(1..2, &["B"]);
+        text.replace(0..2, &["joined"]);
+        text.undo();
+        assert_eq!(text.texts(), ["a", "B"]);
+        assert!(text.undo() == Some(at(1, 0)));
+        assert_eq!(text.texts(), ["a", "b"]);
+    }
+
+    #[test]
+    fn editing_after_undo_forgets_what_was_undone() {
+        let mut text = Text::new(&["a"]);
+        text.type_at(at(0, 1), 'b');
+        text.undo();
+        text.type_at(at(0, 0), 'c');
+        assert!(text.redo().is_none());
+        assert_eq!(text.texts(), ["ca"]);
+    }
+
+    #[test]
+    fn undoing_during_a_change_sees_its_edits() {
+        let mut text = Text::new(&["a"]);
+        text.history.begin(at(0, 0));
+        text.replace(0..1, &["b"]);
+        assert!(text.undo() == Some(at(0, 0)));
+        assert_eq!(text.texts(), ["a"]);
+        text.history.end(at(0, 0));
+        // nothing was left to make another step of
+        assert!(text.undo().is_none());
+        text.history.begin(at(0, 0));
+        text.history.end(at(0, 0));
+        assert!(text.undo().is_none());
+    }
+}
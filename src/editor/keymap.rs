@@ -0,0 +1,438 @@
+use std::collections::HashMap;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use toml::{Table, Value};
+
+use super::{command::{Command, Direction, Operator}, config::read_config};
+
+/// file in the config directory holding user keybindings
+const KEYS_FILE: &str = "keys.toml";
+
+/// a single key press with its modifiers, like Ctrl+K
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyChord {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+/// what a key press turned out to mean
+pub enum Resolution {
+    Command(Command),
+    /// the keys typed so far start a longer sequence, wait for more
+    Pending,
+    /// no binding for the keys
+    Unbound,
+}
+
+/// maps sequences of key chords to editor actions.
+/// bindings in `keys.toml` override the built-in ones, for example:
+///
+/// ```toml
+/// "ctrl+s" = "command_line"
+/// "ctrl+k ctrl+c" = "quit"
+/// "ctrl+e" = "none"
+/// ```
+pub struct Keymap {
+    bindings: HashMap<Vec<KeyChord>, Command>,
+    /// chords typed so far of an unfinished sequence
+    pending: Vec<KeyChord>,
+}
+
+impl Keymap {
+    /// the built-in keymap, extended by `keys.toml` if it exists.
+    /// returns problems found in the file along with the keymap
+    pub fn load() -> (Self, Vec<String>) {
+        let mut keymap = Self::default();
+        let errors = match read_config(KEYS_FILE) {
+            None => Vec::new(),
+            Some(Err(e)) => vec![format!("{KEYS_FILE}: {e}")],
+            Some(Ok(contents)) => keymap.apply(&contents),
+        };
+        (keymap, errors)
+    }
+    /// add bindings from the contents of a keys file, returns problems found in it
+    fn apply(&mut self, contents: &str) -> Vec<String> {
+        let table = match contents.parse::<Table>() {
+            Ok(table) => table,
+            Err(e) => return vec![format!("{KEYS_FILE}: {}", e.message())],
+        };
+        let mut errors = Vec::new();
+        let mut added = Vec::new();
+        for (keys, action) in table {
+            let sequence = match Self::parse_sequence(&keys) {
+                Ok(sequence) => sequence,
+                Err(e) => {
+                    errors.push(format!("{KEYS_FILE}: {e}"));
+                    continue;
+                }
+            };
+            let Value::String(action) = action else {
+                errors.push(format!("{KEYS_FILE}: action for \"{keys}\" must be a string"));
+                continue;
+            };
+            if action == "none" {
+                self.bindings.remove(&sequence);
+                continue;
+            }
+            match action_command(&action) {
+                Some(command) => added.push((keys, sequence, command)),
+                None => errors.push(format!("{KEYS_FILE}: unknown action \"{action}\" for \"{keys}\"")),
+            }
+        }
+        // a binding of the first keys of another one would hide it, so those are refused.
+        // unbinding happens first, to make room for sequences
+        for (keys, sequence, command) in added {
+            let conflict = self.bindings.keys().any(|bound| {
+                *bound != sequence && (bound.starts_with(&sequence) || sequence.starts_with(bound))
+            });
+            if conflict {
+                errors.push(format!(
+                    "{KEYS_FILE}: \"{keys}\" starts another binding or is started by one, set that one to \"none\" first"
+                ));
+            } else {
+                self.bindings.insert(sequence, command);
+            }
+        }
+        errors
+    }
+    /// look up a key press, taking previously typed chords of a sequence into account
+    pub fn resolve(&mut self, key: KeyEvent) -> Resolution {
+        if key.kind != KeyEventKind::Press {
+            return Resolution::Unbound;
+        }
+        self.pending.push(KeyChord::from(key));
+        if let Some(command) = self.bindings.get(&self.pending) {
+            self.pending.clear();
+            return Resolution::Command(command.clone());
+        }
+        if self.bindings.keys().any(|sequence| sequence.starts_with(&self.pending)) {
+            return Resolution::Pending;
+        }
+
+        let single = self.pending.len() == 1;
+        self.pending.clear();
+        match key.code {
+            // unbound characters are typed into the buffer
+            KeyCode::Char(c) if single && !key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => {
+                Resolution::Command(Command::Insert(c))
+            }
+            _ => Resolution::Unbound,
+        }
+    }
+    /// parse space separated chords, like "ctrl+k ctrl+c"
+    fn parse_sequence(keys: &str) -> Result<Vec<KeyChord>, String> {
+        let sequence = keys
+            .split_whitespace()
+            .map(KeyChord::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        if sequence.is_empty() {
+            return Err("empty key sequence".to_string());
+        }
+        Ok(sequence)
+    }
+    fn bind(&mut self, keys: &str, command: Command) {
+        if let Ok(sequence) = Self::parse_sequence(keys) {
+            self.bindings.insert(sequence, command);
+        }
+    }
+}
+
+impl Default for Keymap {
+    /// the built-in keymap
+    fn default() -> Self {
+        let mut keymap = Self {
+            bindings: HashMap::new(),
+            pending: Vec::new(),
+        };
+        for (keys, action) in [
+            ("ctrl+q", "quit"),
+            ("ctrl+g", "goto_line"),
+            ("ctrl+p", "find_file"),
+            ("ctrl+e", "command_line"),
+            ("up", "move_up"),
+            ("down", "move_down"),
+            ("left", "move_left"),
+            ("right", "move_right"),
+            ("pageup", "page_up"),
+            ("pagedown", "page_down"),
+            ("home", "line_start"),
+            ("end", "line_end"),
+            ("ctrl+left", "word_backward"),
+            ("ctrl+right", "word_forward"),
+            ("ctrl+home", "file_start"),
+            ("ctrl+end", "file_end"),
+            ("ctrl+b", "matching_bracket"),
+            ("ctrl+alt+up", "add_cursor_above"),
+            ("ctrl+alt+down", "add_cursor_below"),
+            ("ctrl+d", "add_next_occurrence"),
+            ("esc", "single_cursor"),
+            ("alt+shift+up", "select_block_up"),
+            ("alt+shift+down", "select_block_down"),
+            ("alt+shift+left", "select_block_left"),
+            ("alt+shift+right", "select_block_right"),
+            ("alt+up", "move_lines_up"),
+            ("alt+down", "move_lines_down"),
+            ("alt+d", "duplicate_lines"),
+            ("alt+shift+d", "delete_lines"),
+            ("alt+j", "join_lines"),
+            ("ctrl+/", "toggle_comment"),
+            // what terminals send for Ctrl+/
+            ("ctrl+7", "toggle_comment"),
+            ("ctrl+_", "toggle_comment"),
+            ("alt+/", "toggle_block_comment"),
+            ("f5", "build"),
+            ("f8", "next_error"),
+            ("shift+f8", "previous_error"),
+            ("alt+k", "hover"),
+            ("f12", "goto_definition"),
+            ("ctrl+space", "complete"),
+            ("ctrl+z", "undo"),
+            ("ctrl+y", "redo"),
+            ("ctrl+r", "redo"),
+            ("ctrl+alt+r", "record_macro"),
+            ("ctrl+alt+p", "play_macro"),
+            ("ctrl+c", "copy"),
+            ("ctrl+x", "cut"),
+            ("ctrl+v", "paste"),
+            ("enter", "insert_newline"),
+            ("tab", "insert_tab"),
+            ("backspace", "delete_backward"),
+            ("delete", "delete"),
+        ] {
+            if let Some(command) = action_command(action) {
+                keymap.bind(keys, command);
+            }
+        }
+        keymap
+    }
+}
+
+/// the command behind a named action
+fn action_command(action: &str) -> Option<Command> {
+    let command = match action {
+        "quit" => Command::Quit,
+        "goto_line" => Command::GotoLine,
+        "find_file" => Command::FindFile,
+        "command_line" => Command::Ex,
+        "move_up" => Command::Move(Direction::Up),
+        "move_down" => Command::Move(Direction::Down),
+        "move_left" => Command::Move(Direction::Left),
+        "move_right" => Command::Move(Direction::Right),
+        "page_up" => Command::Move(Direction::PageUp),
+        "page_down" => Command::Move(Direction::PageDown),
+        "line_start" => Command::Move(Direction::Home),
+        "line_end" => Command::Move(Direction::End),
+        "word_forward" => Command::Move(Direction::WordForward),
+        "word_backward" => Command::Move(Direction::WordBackward),
+        "word_end" => Command::Move(Direction::WordEnd),
+        "file_start" => Command::Move(Direction::FileStart),
+        "file_end" => Command::Move(Direction::FileEnd),
+        "matching_bracket" => Command::Move(Direction::MatchingBracket),
+        "add_cursor_above" => Command::AddCursor(Direction::Up),
+        "add_cursor_below" => Command::AddCursor(Direction::Down),
+        "add_next_occurrence" => Command::AddNextOccurrence,
+        "single_cursor" => Command::SingleCursor,
+        "select_block_up" => Command::SelectBlock(Direction::Up),
+        "select_block_down" => Command::SelectBlock(Direction::Down),
+        "select_block_left" => Command::SelectBlock(Direction::Left),
+        "select_block_right" => Command::SelectBlock(Direction::Right),
+        "move_lines_up" => Command::MoveLines(Direction::Up),
+        "move_lines_down" => Command::MoveLines(Direction::Down),
+        "duplicate_lines" => Command::DuplicateLines,
+        "delete_lines" => Command::DeleteLines,
+        "join_lines" => Command::JoinLines,
+        "toggle_comment" => Command::ToggleComment { block: false },
+        "toggle_block_comment" => Command::ToggleComment { block: true },
+        "build" => Command::Build,
+        "next_error" => Command::NextError { backward: false },
+        "previous_error" => Command::NextError { backward: true },
+        "hover" => Command::Hover,
+        "goto_definition" => Command::GotoDefinition,
+        "complete" => Command::Complete,
+        "copy" => Command::OperateSelection(Operator::Yank),
+        "cut" => Command::OperateSelection(Operator::Delete),
+        "insert_newline" => Command::InsertNewline,
+        "insert_tab" => Command::Insert('\t'),
+        "delete_backward" => Command::DeleteBackward,
+        "delete" => Command::Delete,
+        "paste" => Command::Paste { before: true },
+        "undo" => Command::Undo,
+        "redo" => Command::Redo,
+        // without vi keys, macros go to register `q`
+        "record_macro" => Command::RecordMacro('q'),
+        "play_macro" => Command::PlayMacro { register: 'q', count: 1 },
+        _ => return None,
+    };
+    Some(command)
+}
+
+impl KeyChord {
+    /// parse a chord like "ctrl+shift+k", "alt+up" or "f5"
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut parts: Vec<&str> = text.split('+').collect();
+        // a trailing "+" means the plus key itself, like "ctrl++"
+        let key = if text.ends_with("++") || text == "+" {
+            parts.truncate(parts.len().saturating_sub(2));
+            "+".to_string()
+        } else {
+            parts.pop().unwrap_or_default().to_string()
+        };
+        for modifier in parts {
+            modifiers |= match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" | "meta" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(format!("unknown modifier \"{modifier}\" in \"{text}\"")),
+            };
+        }
+        let code = match key.to_lowercase().as_str() {
+            "enter" | "return" => KeyCode::Enter,
+            "esc" | "escape" => KeyCode::Esc,
+            "tab" => KeyCode::Tab,
+            "backspace" => KeyCode::Backspace,
+            "delete" | "del" => KeyCode::Delete,
+            "insert" => KeyCode::Insert,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "space" => KeyCode::Char(' '),
+            name if name.starts_with('f') && name.len() > 1 => name[1..]
+                .parse()
+                .map(KeyCode::F)
+                .map_err(|_| format!("unknown key \"{key}\" in \"{text}\""))?,
+            _ => {
+                let mut chars = key.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => KeyCode::Char(c),
+                    _ => return Err(format!("unknown key \"{key}\" in \"{text}\"")),
+                }
+            }
+        };
+        Ok(Self::from(KeyEvent::new(code, modifiers)))
+    }
+}
+
+impl From<KeyEvent> for KeyChord {
+    /// shift is folded into the character for character keys,
+    /// so that "shift+k" and a typed "K" are the same chord
+    fn from(key: KeyEvent) -> Self {
+        let KeyEvent { code, mut modifiers, .. } = key;
+        let code = match code {
+            KeyCode::Char(c) if modifiers.contains(KeyModifiers::SHIFT) => {
+                modifiers.remove(KeyModifiers::SHIFT);
+                KeyCode::Char(c.to_ascii_uppercase())
+            }
+            _ => code,
+        };
+        Self { code, modifiers }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    fn ctrl(c: char) -> KeyEvent {
+        press(KeyCode::Char(c), KeyModifiers::CONTROL)
+    }
+
+    #[test]
+    fn parse_sequence_reads_space_separated_chords() {
+        let sequence = Keymap::parse_sequence("ctrl+k  ctrl+c").unwrap();
+        assert!(sequence == [KeyChord::from(ctrl('k')), KeyChord::from(ctrl('c'))]);
+        let plus = Keymap::parse_sequence("ctrl++").unwrap();
+        assert!(plus == [KeyChord::from(ctrl('+'))]);
+        let f5 = Keymap::parse_sequence("shift+f5").unwrap();
+        assert!(f5 == [KeyChord::from(press(KeyCode::F(5), KeyModifiers::SHIFT))]);
+        assert!(Keymap::parse_sequence("   ").is_err());
+    }
+
+    #[test]
+    fn shift_is_folded_into_characters() {
+        let shifted = Keymap::parse_sequence("shift+k").unwrap();
+        assert!(shifted == [KeyChord::from(press(KeyCode::Char('K'), KeyModifiers::NONE))]);
+    }
+
+    #[test]
+    fn bad_names_are_rejected() {
+        assert!(KeyChord::parse("hyper+k").is_err());
+        assert!(KeyChord::parse("ctrl+foo").is_err());
+        assert!(KeyChord::parse("fx").is_err());
+        let errors = Keymap::default().apply("\"ctrl+k\" = \"explode\"\n\"ctrl+t\" = 1\n\"ctrl+bogus\" = \"quit\"");
+        assert_eq!(errors.len(), 3);
+        assert!(errors[0].contains("bogus"));
+        assert!(errors.iter().any(|e| e.contains("unknown action \"explode\"")));
+        assert!(errors.iter().any(|e| e.contains("must be a string")));
+    }
+
+    #[test]
+    fn sequences_wait_for_their_last_chord() {
+        let mut keymap = Keymap::default();
+        assert!(keymap.apply("\"ctrl+k ctrl+c\" = \"quit\"\n\"ctrl+k ctrl+g\" = \"goto_line\"").is_empty());
+        assert!(matches!(keymap.resolve(ctrl('k')), Resolution::Pending));
+        assert!(matches!(keymap.resolve(ctrl('c')), Resolution::Command(Command::Quit)));
+        assert!(matches!(keymap.resolve(ctrl('k')), Resolution::Pending));
+        assert!(matches!(keymap.resolve(ctrl('g')), Resolution::Command(Command::GotoLine)));
+        // a chord ending no sequence is dropped along with the ones before it
+        assert!(matches!(keymap.resolve(ctrl('k')), Resolution::Pending));
+        assert!(matches!(keymap.resolve(ctrl('t')), Resolution::Unbound));
+        assert!(matches!(keymap.resolve(ctrl('q')), Resolution::Command(Command::Quit)));
+    }
+
+    #[test]
+    fn the_documented_example_fits_the_built_in_keymap() {
+        let mut keymap = Keymap::default();
+        assert!(keymap.apply("\"ctrl+s\" = \"command_line\"\n\"ctrl+k ctrl+c\" = \"quit\"\n\"ctrl+e\" = \"none\"").is_empty());
+        assert!(matches!(keymap.resolve(ctrl('k')), Resolution::Pending));
+        assert!(matches!(keymap.resolve(ctrl('c')), Resolution::Command(Command::Quit)));
+        let alt_shift_d = press(KeyCode::Char('D'), KeyModifiers::ALT | KeyModifiers::SHIFT);
+        assert!(matches!(keymap.resolve(alt_shift_d), Resolution::Command(Command::DeleteLines)));
+    }
+
+    #[test]
+    fn bindings_sharing_a_prefix_are_refused() {
+        let mut keymap = Keymap::default();
+        let errors = keymap.apply("\"ctrl+g ctrl+c\" = \"quit\"");
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("\"ctrl+g ctrl+c\""));
+        assert!(matches!(keymap.resolve(ctrl('g')), Resolution::Command(Command::GotoLine)));
+
+        let errors = keymap.apply("\"ctrl+k ctrl+c\" = \"quit\"\n\"ctrl+k\" = \"goto_line\"");
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(keymap.resolve(ctrl('k')), Resolution::Command(Command::GotoLine)));
+    }
+
+    #[test]
+    fn unbinding_makes_room_for_a_sequence() {
+        let mut keymap = Keymap::default();
+        assert!(keymap.apply("\"ctrl+g ctrl+c\" = \"quit\"\n\"ctrl+g\" = \"none\"").is_empty());
+        assert!(matches!(keymap.resolve(ctrl('g')), Resolution::Pending));
+        assert!(matches!(keymap.resolve(ctrl('c')), Resolution::Command(Command::Quit)));
+    }
+
+    #[test]
+    fn built_in_bindings_do_not_hide_each_other() {
+        let keymap = Keymap::default();
+        for sequence in keymap.bindings.keys() {
+            let hidden = keymap.bindings.keys().any(|other| other != sequence && other.starts_with(sequence));
+            assert!(!hidden);
+        }
+    }
+
+    #[test]
+    fn unbound_characters_are_typed() {
+        let mut keymap = Keymap::default();
+        assert!(matches!(keymap.resolve(press(KeyCode::Char('é'), KeyModifiers::NONE)), Resolution::Command(Command::Insert('é'))));
+        assert!(matches!(keymap.resolve(press(KeyCode::Char('é'), KeyModifiers::ALT)), Resolution::Unbound));
+    }
+}
@@ -1,8 +1,12 @@
+use std::cmp::min;
+use std::fmt::{self, Display};
 use std::ops::{Add, Range};
 
 use unicode_segmentation::UnicodeSegmentation;
 use unicode_width::UnicodeWidthStr;
 
+use super::{highlight::Highlight, settings::Settings};
+
 #[derive(Copy, Clone, Debug)]
 pub enum GraphemeWidth {
     Half, 
@@ -20,10 +24,45 @@ impl Add<usize> for GraphemeWidth {
     }
 }
 
+/// invisible graphemes are drawn as a visible character chosen in the settings
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Replacement {
+    Tab,
+    Whitespace,
+    Control,
+    ZeroWidth,
+}
+
+impl Replacement {
+    const fn glyph(self, settings: &Settings) -> char {
+        match self {
+            Self::Tab => settings.tab_char,
+            Self::Whitespace => settings.whitespace_char,
+            Self::Control => settings.control_char,
+            Self::ZeroWidth => settings.zero_width_char,
+        }
+    }
+}
+
 struct GraphemeFragment {
     grapheme: String, 
     grapheme_width: GraphemeWidth, 
-    replacement: Option<char>,
+    replacement: Option<Replacement>,
+}
+
+impl GraphemeFragment {
+    /// columns taken on the screen when drawn at given column.
+    /// a tab stretches to the next tab stop
+    fn width_at(&self, pos: usize, settings: &Settings) -> usize {
+        if self.replacement == Some(Replacement::Tab) {
+            let tab_width = settings.tab_width.max(1);
+            return tab_width - pos % tab_width;
+        }
+        match self.grapheme_width {
+            GraphemeWidth::Half => 1,
+            GraphemeWidth::Full => 2,
+        }
+    }
 }
 
 pub struct Line {
@@ -63,45 +102,70 @@ impl Line {
             .collect()
     }
     /// fill in GraphemeFragment's replacement field
-    fn replacement_character(grapheme_str: &str) -> Option<char> {
+    fn replacement_character(grapheme_str: &str) -> Option<Replacement> {
         let width = grapheme_str.width();
         match grapheme_str {
             " " => None,
-            "\t" => Some(' '),
-            _ if width > 0 && grapheme_str.trim().is_empty() => Some('␣'),
+            "\t" => Some(Replacement::Tab),
+            _ if width > 0 && grapheme_str.trim().is_empty() => Some(Replacement::Whitespace),
             _ if width == 0 => {
                 let mut chars = grapheme_str.chars();
                 if let Some(c) = chars.next() {
                     if c.is_control() && chars.next().is_none() {
-                        return Some('▯')
+                        return Some(Replacement::Control)
                     }
                 }
-                Some('·')
+                Some(Replacement::ZeroWidth)
             }
             _ => None,
         }
     }
-    /// get graphemes within given range
-    /// mind that one grapheme may takes 2 bits
-    pub fn get_graphems(&self, range: Range<usize>) -> String {
-        let mut ret = String::new();
+    /// get graphemes within given range of columns, split into segments by highlighting.
+    /// mind that one grapheme may takes 2 columns.
+    /// `highlights` are ranges of grapheme indexes, later ones take precedence
+    pub fn get_highlighted_graphems(
+        &self,
+        range: Range<usize>,
+        highlights: &[(Range<usize>, Highlight)],
+        settings: &Settings,
+    ) -> Vec<(String, Option<Highlight>)> {
+        let mut ret: Vec<(String, Option<Highlight>)> = Vec::new();
         if range.start >= range.end {
             return ret;
         }
         let mut pos = 0;
-        for fragment in &self.fragments {
+        for (index, fragment) in self.fragments.iter().enumerate() {
             if pos >= range.end {
                 break;
             }
-            let end = fragment.grapheme_width + pos;
+            let end = pos + fragment.width_at(pos, settings);
             if end > range.start {
-                if end > range.end || pos < range.start {
+                let mut text = String::new();
+                if fragment.replacement == Some(Replacement::Tab) {
+                    // a tab is as wide as its visible part
+                    let visible = min(end, range.end) - pos.max(range.start);
+                    if pos >= range.start {
+                        text.push(settings.tab_char);
+                        text.push_str(&" ".repeat(visible.saturating_sub(1)));
+                    } else {
+                        text.push_str(&" ".repeat(visible));
+                    }
+                } else if end > range.end || pos < range.start {
                     // Clip on the right or left
-                    ret.push('·');
-                } else if let Some(char) = fragment.replacement {
-                    ret.push(char);
+                    text.push('·');
+                } else if let Some(replacement) = fragment.replacement {
+                    text.push(replacement.glyph(settings));
                 } else {
-                    ret.push_str(&fragment.grapheme);
+                    text.push_str(&fragment.grapheme);
+                }
+                let highlight = highlights
+                    .iter()
+                    .rev()
+                    .find(|(range, _)| range.contains(&index))
+                    .map(|(_, highlight)| *highlight);
+                match ret.last_mut() {
+                    Some((segment, last)) if *last == highlight => segment.push_str(&text),
+                    _ => ret.push((text, highlight)),
                 }
             }
             pos = end;
@@ -113,18 +177,200 @@ impl Line {
         self.fragments.len()
     }
     /// returns how many spaces the graphemes before given index take on the screen
-    pub fn width_until(&self, grapheme_index: usize) -> usize {
+    pub fn width_until(&self, grapheme_index: usize, settings: &Settings) -> usize {
         self.fragments
             .iter()
             .take(grapheme_index)
-            .map(|fragment| match fragment.grapheme_width {
-                GraphemeWidth::Half => 1,
-                GraphemeWidth::Full => 2,
-            })
-            .sum()
+            .fold(0, |pos, fragment| pos + fragment.width_at(pos, settings))
+    }
+    /// index of the grapheme covering given column, if the line reaches that far
+    pub fn grapheme_at_column(&self, column: usize, settings: &Settings) -> Option<usize> {
+        let mut pos = 0;
+        for (index, fragment) in self.fragments.iter().enumerate() {
+            pos += fragment.width_at(pos, settings);
+            if pos > column {
+                return Some(index);
+            }
+        }
+        None
+    }
+    /// graphemes overlapping given screen columns, with how far the first one sticks out
+    /// on the left and the last one on the right
+    pub fn graphemes_in_columns(&self, columns: Range<usize>, settings: &Settings) -> (Range<usize>, usize, usize) {
+        let mut pos = 0;
+        let mut overlap: Option<(Range<usize>, usize, usize)> = None;
+        for (index, fragment) in self.fragments.iter().enumerate() {
+            if pos >= columns.end {
+                break;
+            }
+            let end = pos + fragment.width_at(pos, settings);
+            if end > columns.start {
+                let after = end.saturating_sub(columns.end);
+                overlap = Some(match overlap {
+                    Some((range, before, _)) => (range.start..index + 1, before, after),
+                    None => (index..index + 1, columns.start.saturating_sub(pos), after),
+                });
+            }
+            pos = end;
+        }
+        overlap.unwrap_or_else(|| {
+            let index = self.grapheme_starting_at(columns.start, settings);
+            (index..index, 0, 0)
+        })
+    }
+    /// text shown in given screen columns. tabs and graphemes sticking out of the columns
+    /// become spaces for the part inside
+    pub fn text_in_columns(&self, columns: Range<usize>, settings: &Settings) -> String {
+        let mut ret = String::new();
+        let mut pos = 0;
+        for fragment in &self.fragments {
+            if pos >= columns.end {
+                break;
+            }
+            let end = pos + fragment.width_at(pos, settings);
+            if end > columns.start {
+                let inside = min(end, columns.end) - pos.max(columns.start);
+                let whole = pos >= columns.start && end <= columns.end;
+                if whole && fragment.replacement != Some(Replacement::Tab) {
+                    ret.push_str(&fragment.grapheme);
+                } else {
+                    ret.push_str(&" ".repeat(inside));
+                }
+            }
+            pos = end;
+        }
+        ret
+    }
+    /// index of the first grapheme starting at or after given column
+    pub fn grapheme_starting_at(&self, column: usize, settings: &Settings) -> usize {
+        let mut pos = 0;
+        for (index, fragment) in self.fragments.iter().enumerate() {
+            if pos >= column {
+                return index;
+            }
+            pos += fragment.width_at(pos, settings);
+        }
+        self.grapheme_len()
+    }
+    /// indexes of graphemes starting each screen row when the line is wrapped at given width.
+    /// the first row always starts at 0
+    pub fn wrap_starts(&self, width: usize, settings: &Settings) -> Vec<usize> {
+        let mut ret = vec![0];
+        let mut pos = 0;
+        let mut row_start = 0;
+        for (index, fragment) in self.fragments.iter().enumerate() {
+            let end = pos + fragment.width_at(pos, settings);
+            // a grapheme that does not fit goes to the next row, unless it is alone in its row
+            if end - row_start > width && index > *ret.last().unwrap_or(&0) {
+                ret.push(index);
+                row_start = pos;
+            }
+            pos = end;
+        }
+        ret
     }
     pub fn insert_at(&mut self, grapheme_index: usize, c: char) {
         let new_fragment = Self::from(&c.to_string()).fragments;
         self.fragments.splice(grapheme_index..grapheme_index, new_fragment);
     }
-}
\ No newline at end of file
+    /// insert text before given grapheme. the line is parsed again,
+    /// since the text may combine with its neighbours into new graphemes
+    pub fn insert_str(&mut self, grapheme_index: usize, text: &str) {
+        let mut contents = self.to_string();
+        contents.insert_str(self.byte_index(grapheme_index), text);
+        *self = Self::from(&contents);
+    }
+    /// original text of graphemes within given range
+    pub fn text_between(&self, range: Range<usize>) -> String {
+        self.fragments
+            .iter()
+            .skip(range.start)
+            .take(range.end.saturating_sub(range.start))
+            .map(|fragment| fragment.grapheme.as_str())
+            .collect()
+    }
+    /// remove graphemes within given range
+    pub fn delete(&mut self, range: Range<usize>) {
+        let mut contents = self.to_string();
+        contents.replace_range(self.byte_index(range.start)..self.byte_index(range.end), "");
+        *self = Self::from(&contents);
+    }
+    /// split the line at given grapheme, returns the part after it
+    pub fn split_off(&mut self, grapheme_index: usize) -> Self {
+        let index = grapheme_index.min(self.grapheme_len());
+        Self {
+            fragments: self.fragments.split_off(index),
+        }
+    }
+    /// append another line to the end of this one
+    pub fn append(&mut self, other: &Self) {
+        self.insert_str(self.grapheme_len(), &other.to_string());
+    }
+    /// original text of the grapheme at given index
+    pub fn grapheme_at(&self, grapheme_index: usize) -> Option<&str> {
+        self.fragments
+            .get(grapheme_index)
+            .map(|fragment| fragment.grapheme.as_str())
+    }
+    /// index of the grapheme starting at or containing given byte offset of the original text
+    pub fn grapheme_index(&self, byte_index: usize) -> usize {
+        let mut bytes = 0;
+        self.fragments
+            .iter()
+            .position(|fragment| {
+                bytes += fragment.grapheme.len();
+                bytes > byte_index
+            })
+            .unwrap_or(self.grapheme_len())
+    }
+    /// byte offset of given grapheme in the original text
+    fn byte_index(&self, grapheme_index: usize) -> usize {
+        self.fragments
+            .iter()
+            .take(grapheme_index)
+            .map(|fragment| fragment.grapheme.len())
+            .sum()
+    }
+}
+
+impl Display for Line {
+    /// the original text of the line, without replacements
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        for fragment in &self.fragments {
+            write!(formatter, "{}", fragment.grapheme)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn graphemes_in_columns_tell_how_far_they_stick_out() {
+        let settings = Settings::default();
+        let columns = |text: &str, columns: Range<usize>| Line::from(text).graphemes_in_columns(columns, &settings);
+        assert_eq!(columns("abcdef", 1..3), (1..3, 0, 0));
+        // the tab covers columns 1 to 4
+        assert_eq!(columns("a\tb", 2..3), (1..2, 1, 1));
+        assert_eq!(columns("a\tb", 1..5), (1..3, 0, 0));
+        assert_eq!(columns("a\u{4e2d}b", 2..4), (1..3, 1, 0));
+        assert_eq!(columns("a\u{4e2d}b", 0..2), (0..2, 0, 1));
+        // a line too short for the columns
+        assert_eq!(columns("ab", 4..6), (2..2, 0, 0));
+    }
+
+    #[test]
+    fn text_in_columns_keeps_its_width() {
+        let settings = Settings::default();
+        let text = |text: &str, columns: Range<usize>| Line::from(text).text_in_columns(columns, &settings);
+        assert_eq!(text("abcdef", 1..3), "bc");
+        assert_eq!(text("a\tb", 0..5), "a   b");
+        assert_eq!(text("a\tb", 2..3), " ");
+        assert_eq!(text("a\u{4e2d}b", 1..3), "\u{4e2d}");
+        assert_eq!(text("a\u{4e2d}b", 2..4), " b");
+        assert_eq!(text("ab", 1..5), "b");
+        assert_eq!(text("ab", 4..6), "");
+    }
+}
@@ -0,0 +1,705 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    process::{Child, ChildStdin, ChildStdout, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde_json::{json, Value};
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::{diagnostic::{self, Severity}, scheduler::{Listener, Scheduler, Task}, shell, view::Location};
+
+/// how long a server gets to leave after being asked to
+const SHUTDOWN_WAIT: Duration = Duration::from_millis(200);
+
+/// id of the next server started
+static NEXT_SERVER: AtomicUsize = AtomicUsize::new(0);
+
+/// a place in a document the way the protocol counts: lines, and UTF-16 code units within them
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub character: usize,
+}
+
+impl Position {
+    /// the position of a grapheme location, given the text of its line
+    pub fn from_location(location: Location, line: &str) -> Self {
+        let character = line
+            .graphemes(true)
+            .take(location.grapheme_index)
+            .map(|grapheme| grapheme.encode_utf16().count())
+            .sum();
+        Self { line: location.line_index, character }
+    }
+    /// the grapheme location of the position, given the text of its line.
+    /// one inside a grapheme goes to its start, one past the end to the end of the line
+    pub fn to_location(self, line: &str) -> Location {
+        let mut units = 0;
+        let mut grapheme_index = 0;
+        for grapheme in line.graphemes(true) {
+            units += grapheme.encode_utf16().count();
+            if units > self.character {
+                break;
+            }
+            grapheme_index += 1;
+        }
+        Location { grapheme_index, line_index: self.line }
+    }
+    fn parse(value: &Value) -> Option<Self> {
+        let number = |key: &str| value.get(key)?.as_u64().and_then(|number| usize::try_from(number).ok());
+        Some(Self { line: number("line")?, character: number("character")? })
+    }
+    fn to_json(self) -> Value {
+        json!({ "line": self.line, "character": self.character })
+    }
+}
+
+/// a problem a language server found in a document
+pub struct Diagnostic {
+    pub start: Position,
+    pub end: Position,
+    pub severity: Severity,
+    /// like `E0308`
+    pub code: Option<String>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn parse(value: &Value) -> Option<Self> {
+        let range = value.get("range")?;
+        let severity = match value.get("severity").and_then(Value::as_u64) {
+            Some(2) => Severity::Warning,
+            Some(3) => Severity::Information,
+            Some(4) => Severity::Hint,
+            // servers leaving it out mean errors, mostly
+            _ => Severity::Error,
+        };
+        let code = value.get("code").and_then(|code| match code {
+            Value::String(code) => Some(code.clone()),
+            Value::Number(code) => Some(code.to_string()),
+            _ => None,
+        });
+        Some(Self {
+            start: Position::parse(range.get("start")?)?,
+            end: Position::parse(range.get("end")?)?,
+            severity,
+            code,
+            message: value.get("message")?.as_str()?.to_string(),
+        })
+    }
+    /// the diagnostic in grapheme locations, given the text of each line
+    pub fn locate(self, line: impl Fn(usize) -> String) -> diagnostic::Diagnostic {
+        diagnostic::Diagnostic {
+            start: self.start.to_location(&line(self.start.line)),
+            end: self.end.to_location(&line(self.end.line)),
+            severity: self.severity,
+            code: self.code,
+            message: self.message,
+            source: "lsp".to_string(),
+        }
+    }
+}
+
+/// what the editor should do about a message from a language server
+pub enum Reply {
+    /// problems found in the document of given uri, replacing the ones sent before
+    Diagnostics { uri: String, diagnostics: Vec<Diagnostic> },
+    /// what the thing at the caret is
+    Hover(String),
+    /// where the thing at the caret is defined
+    Definition { uri: String, position: Position },
+    /// words that may go at the position the completion was asked for
+    Completion { uri: String, position: Position, items: Vec<String> },
+    /// something to show in the command bar, like an error
+    Message(String),
+}
+
+/// what an answer will be for, by the request's id
+enum Request {
+    Initialize,
+    Hover,
+    Definition,
+    Completion { uri: String, position: Position },
+    Shutdown,
+}
+
+/// what a document looked like when it was last sent to the server
+struct Document {
+    /// the protocol's version, counting the changes sent
+    version: u64,
+    /// the buffer's version
+    buffer_version: usize,
+    lines: Vec<String>,
+}
+
+/// a language server running as a child process, talking JSON-RPC over its stdin and stdout
+pub struct Client {
+    /// tells the server's messages from the ones of servers started before
+    pub id: usize,
+    child: Child,
+    stdin: ChildStdin,
+    next_id: u64,
+    /// requests sent and not answered yet
+    pending: HashMap<u64, Request>,
+    /// messages held back until the server answers `initialize`
+    queued: Vec<Value>,
+    initialized: bool,
+    /// whether the server takes changes as ranges. the whole text is sent otherwise
+    incremental: bool,
+    /// opened documents by uri
+    documents: HashMap<String, Document>,
+}
+
+impl Client {
+    /// run the shell command of a language server. its messages come back as `Task::Language`
+    pub fn start(command: &str, scheduler: &mut Scheduler) -> Result<Self, String> {
+        let mut child = shell::shell(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("Cannot start language server \"{command}\": {e}"))?;
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            return Err(format!("Cannot talk to language server \"{command}\""));
+        };
+        let id = NEXT_SERVER.fetch_add(1, Ordering::Relaxed);
+        scheduler.listen(move |listener| read_messages(stdout, id, &listener));
+        let mut client = Self {
+            id,
+            child,
+            stdin,
+            next_id: 1,
+            pending: HashMap::new(),
+            queued: Vec::new(),
+            initialized: false,
+            incremental: false,
+            documents: HashMap::new(),
+        };
+        let root = env::current_dir().map(|dir| uri(&dir.to_string_lossy())).unwrap_or_default();
+        let params = json!({
+            "processId": std::process::id(),
+            "rootUri": root,
+            "workspaceFolders": [{ "uri": root, "name": "root" }],
+            "capabilities": {
+                "general": { "positionEncodings": ["utf-16"] },
+                "textDocument": {
+                    "synchronization": { "didSave": true },
+                    "hover": { "contentFormat": ["plaintext", "markdown"] },
+                    "definition": { "linkSupport": true },
+                    "completion": { "completionItem": { "snippetSupport": false } },
+                    "publishDiagnostics": { "relatedInformation": false },
+                },
+            },
+        });
+        client.request("initialize", params, Request::Initialize)?;
+        Ok(client)
+    }
+    /// tell the server about a document: opens it the first time, then sends what changed since.
+    /// `lines` is only asked for when the buffer changed
+    pub fn sync(&mut self, uri: &str, filetype: &str, buffer_version: usize, lines: impl FnOnce() -> Vec<String>) -> Result<(), String> {
+        let Some(document) = self.documents.get_mut(uri) else {
+            let lines = lines();
+            let params = json!({
+                "textDocument": { "uri": uri, "languageId": language_id(filetype), "version": 0, "text": text(&lines) },
+            });
+            self.documents.insert(uri.to_string(), Document { version: 0, buffer_version, lines });
+            return self.notify("textDocument/didOpen", params);
+        };
+        if document.buffer_version == buffer_version {
+            return Ok(());
+        }
+        let lines = lines();
+        let change = if self.incremental {
+            change(&document.lines, &lines)
+        } else {
+            Some(json!({ "text": text(&lines) }))
+        };
+        document.buffer_version = buffer_version;
+        let Some(change) = change else {
+            return Ok(());
+        };
+        document.version += 1;
+        document.lines = lines;
+        let params = json!({
+            "textDocument": { "uri": uri, "version": document.version },
+            "contentChanges": [change],
+        });
+        self.notify("textDocument/didChange", params)
+    }
+    pub fn saved(&mut self, uri: &str) -> Result<(), String> {
+        if !self.documents.contains_key(uri) {
+            return Ok(());
+        }
+        self.notify("textDocument/didSave", json!({ "textDocument": { "uri": uri } }))
+    }
+    pub fn hover(&mut self, uri: &str, position: Position) -> Result<(), String> {
+        self.request("textDocument/hover", Self::at(uri, position), Request::Hover)
+    }
+    pub fn definition(&mut self, uri: &str, position: Position) -> Result<(), String> {
+        self.request("textDocument/definition", Self::at(uri, position), Request::Definition)
+    }
+    pub fn completion(&mut self, uri: &str, position: Position) -> Result<(), String> {
+        let request = Request::Completion { uri: uri.to_string(), position };
+        self.request("textDocument/completion", Self::at(uri, position), request)
+    }
+    fn at(uri: &str, position: Position) -> Value {
+        json!({ "textDocument": { "uri": uri }, "position": position.to_json() })
+    }
+    /// make sense of a message from the server
+    pub fn handle(&mut self, message: &Value) -> Result<Option<Reply>, String> {
+        let id = message.get("id");
+        match (message.get("method").and_then(Value::as_str), id) {
+            // the server asks something, like for settings. we have nothing to tell
+            (Some(method), Some(id)) => {
+                let result = match method {
+                    "workspace/configuration" => {
+                        let items = message.pointer("/params/items").and_then(Value::as_array).map_or(0, Vec::len);
+                        Value::Array(vec![Value::Null; items])
+                    }
+                    _ => Value::Null,
+                };
+                self.send(json!({ "jsonrpc": "2.0", "id": id, "result": result }))?;
+                Ok(None)
+            }
+            (Some(method), None) => Ok(Self::notification(method, message.get("params").unwrap_or(&Value::Null))),
+            (None, Some(id)) => {
+                let Some(request) = id.as_u64().and_then(|id| self.pending.remove(&id)) else {
+                    return Ok(None);
+                };
+                if let Some(error) = message.get("error") {
+                    let text = error.get("message").and_then(Value::as_str).unwrap_or("unknown error");
+                    return Ok(Some(Reply::Message(format!("Language server: {text}"))));
+                }
+                self.response(request, message.get("result").unwrap_or(&Value::Null))
+            }
+            (None, None) => Ok(None),
+        }
+    }
+    fn notification(method: &str, params: &Value) -> Option<Reply> {
+        match method {
+            "textDocument/publishDiagnostics" => {
+                let uri = params.get("uri")?.as_str()?.to_string();
+                let diagnostics = params
+                    .get("diagnostics")?
+                    .as_array()?
+                    .iter()
+                    .filter_map(Diagnostic::parse)
+                    .collect();
+                Some(Reply::Diagnostics { uri, diagnostics })
+            }
+            // errors and warnings only, the rest is chatter
+            "window/showMessage" if params.get("type").and_then(Value::as_u64).is_some_and(|kind| kind <= 2) => {
+                let text = params.get("message")?.as_str()?;
+                Some(Reply::Message(format!("Language server: {text}")))
+            }
+            _ => None,
+        }
+    }
+    fn response(&mut self, request: Request, result: &Value) -> Result<Option<Reply>, String> {
+        let reply = match request {
+            Request::Initialize => {
+                let sync = result.pointer("/capabilities/textDocumentSync");
+                let kind = sync.and_then(|sync| sync.as_u64().or_else(|| sync.get("change")?.as_u64()));
+                self.incremental = kind == Some(2);
+                self.initialized = true;
+                self.send(json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} }))?;
+                for message in std::mem::take(&mut self.queued) {
+                    self.send(message)?;
+                }
+                None
+            }
+            Request::Hover => {
+                let text = hover_text(result.get("contents").unwrap_or(&Value::Null));
+                Some(Reply::Hover(text))
+            }
+            Request::Definition => {
+                // a location, a list of them, or a list of links
+                let first = result.as_array().map_or(Some(result), |locations| locations.first());
+                first.and_then(|location| {
+                    let uri = location.get("uri").or_else(|| location.get("targetUri"))?.as_str()?.to_string();
+                    let range = location.get("range").or_else(|| location.get("targetSelectionRange"))?;
+                    let position = Position::parse(range.get("start")?)?;
+                    Some(Reply::Definition { uri, position })
+                })
+            }
+            Request::Completion { uri, position } => {
+                let items = result.get("items").unwrap_or(result).as_array().map_or_else(Vec::new, |items| {
+                    items
+                        .iter()
+                        .filter_map(|item| {
+                            let text = item.pointer("/textEdit/newText").or_else(|| item.get("insertText")).or_else(|| item.get("label"));
+                            text?.as_str().map(str::to_string)
+                        })
+                        .collect()
+                });
+                Some(Reply::Completion { uri, position, items })
+            }
+            Request::Shutdown => None,
+        };
+        Ok(reply)
+    }
+    fn request(&mut self, method: &str, params: Value, request: Request) -> Result<(), String> {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.pending.insert(id, request);
+        let message = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        if self.initialized || method == "initialize" {
+            self.send(message)
+        } else {
+            self.queued.push(message);
+            Ok(())
+        }
+    }
+    fn notify(&mut self, method: &str, params: Value) -> Result<(), String> {
+        let message = json!({ "jsonrpc": "2.0", "method": method, "params": params });
+        if self.initialized {
+            self.send(message)
+        } else {
+            self.queued.push(message);
+            Ok(())
+        }
+    }
+    fn send(&mut self, message: Value) -> Result<(), String> {
+        let body = message.to_string();
+        write!(self.stdin, "Content-Length: {}\r\n\r\n{body}", body.len())
+            .and_then(|()| self.stdin.flush())
+            .map_err(|e| format!("Language server stopped: {e}"))
+    }
+}
+
+impl Drop for Client {
+    /// ask the server to leave, and make sure it does
+    fn drop(&mut self) {
+        if self.initialized {
+            let _ = self.request("shutdown", Value::Null, Request::Shutdown);
+            let _ = self.notify("exit", Value::Null);
+        }
+        let deadline = Instant::now() + SHUTDOWN_WAIT;
+        while !matches!(self.child.try_wait(), Ok(Some(_))) {
+            if Instant::now() >= deadline {
+                let _ = self.child.kill();
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.wait();
+    }
+}
+
+/// read messages from the server until it goes away, which is told with `None`
+fn read_messages(stdout: ChildStdout, server: usize, listener: &Listener) {
+    let mut reader = BufReader::new(stdout);
+    while let Some(message) = read_message(&mut reader) {
+        if !listener.send(Task::Language { server, message: Some(message) }) {
+            return;
+        }
+    }
+    listener.send(Task::Language { server, message: None });
+}
+
+/// one message, after its `Content-Length` header
+fn read_message(reader: &mut impl BufRead) -> Option<Value> {
+    loop {
+        let mut length = None;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header).ok()? == 0 {
+                return None;
+            }
+            let header = header.trim();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    length = value.trim().parse().ok();
+                }
+            }
+        }
+        let mut body = vec![0; length?];
+        reader.read_exact(&mut body).ok()?;
+        // a message that is not JSON is skipped rather than ending the conversation
+        if let Ok(message) = serde_json::from_slice(&body) {
+            return Some(message);
+        }
+    }
+}
+
+/// the text of hover contents: markup, a string, a code block, or a list of those
+fn hover_text(contents: &Value) -> String {
+    match contents {
+        Value::String(text) => text.clone(),
+        Value::Array(items) => items.iter().map(hover_text).filter(|text| !text.is_empty()).collect::<Vec<_>>().join("\n"),
+        Value::Object(object) => object.get("value").and_then(Value::as_str).unwrap_or_default().to_string(),
+        _ => String::new(),
+    }
+}
+
+/// the smallest change turning old lines into new ones. within a single line,
+/// only the characters that differ are sent
+fn change(old: &[String], new: &[String]) -> Option<Value> {
+    let prefix = old.iter().zip(new).take_while(|(old, new)| old == new).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+    let (old_end, new_end) = (old.len() - suffix, new.len() - suffix);
+    if old_end == prefix && new_end == prefix {
+        return None;
+    }
+    let (start, end, inserted) = if old_end - prefix == 1 && new_end - prefix == 1 {
+        let (before, after) = (old[prefix].as_str(), new[prefix].as_str());
+        let head: usize = before.chars().zip(after.chars()).take_while(|(a, b)| a == b).map(|(c, _)| c.len_utf8()).sum();
+        let tail: usize = before[head..]
+            .chars()
+            .rev()
+            .zip(after[head..].chars().rev())
+            .take_while(|(a, b)| a == b)
+            .map(|(c, _)| c.len_utf8())
+            .sum();
+        let units = |text: &str| text.encode_utf16().count();
+        (
+            Position { line: prefix, character: units(&before[..head]) },
+            Position { line: prefix, character: units(&before[..before.len() - tail]) },
+            after[head..after.len() - tail].to_string(),
+        )
+    } else {
+        (
+            Position { line: prefix, character: 0 },
+            Position { line: old_end, character: 0 },
+            text(&new[prefix..new_end]),
+        )
+    };
+    Some(json!({ "range": { "start": start.to_json(), "end": end.to_json() }, "text": inserted }))
+}
+
+/// the document as the server sees it, every line ending with a line break
+fn text(lines: &[String]) -> String {
+    lines.iter().map(|line| format!("{line}\n")).collect()
+}
+
+/// the protocol's name of a file type, where it differs from ours
+fn language_id(filetype: &str) -> &str {
+    match filetype {
+        "sh" => "shellscript",
+        "text" => "plaintext",
+        filetype => filetype,
+    }
+}
+
+/// `file://` uri of a path, made absolute
+pub fn uri(path: &str) -> String {
+    let absolute = fs::canonicalize(path)
+        .or_else(|_| env::current_dir().map(|dir| dir.join(path)))
+        .unwrap_or_else(|_| Path::new(path).to_path_buf());
+    let mut ret = "file://".to_string();
+    for byte in absolute.to_string_lossy().bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~".contains(&byte) {
+            ret.push(char::from(byte));
+        } else {
+            ret.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    ret
+}
+
+/// the path of a `file://` uri
+pub fn path(uri: &str) -> Option<String> {
+    let encoded = uri.strip_prefix("file://")?.as_bytes();
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut index = 0;
+    while index < encoded.len() {
+        let escaped = (encoded[index] == b'%')
+            .then(|| encoded.get(index + 1..index + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                bytes.push(byte);
+                index += 3;
+            }
+            None => {
+                bytes.push(encoded[index]);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::VecDeque, io::Cursor};
+
+    use super::*;
+
+    fn lines(texts: &[&str]) -> Vec<String> {
+        texts.iter().map(|text| text.to_string()).collect()
+    }
+
+    #[test]
+    fn positions_count_utf16_units_of_graphemes() {
+        // an emoji takes two units, `é̃` is one grapheme of three characters
+        let line = "a😀e\u{301}\u{303}b";
+        let at = |grapheme_index| Position::from_location(Location { grapheme_index, line_index: 7 }, line);
+        assert!(at(1) == Position { line: 7, character: 1 });
+        assert!(at(2) == Position { line: 7, character: 3 });
+        assert!(at(3) == Position { line: 7, character: 6 });
+        assert!(at(4) == Position { line: 7, character: 7 });
+        let to = |character| Position { line: 7, character }.to_location(line).grapheme_index;
+        assert_eq!((0..=7).map(to).collect::<Vec<_>>(), [0, 1, 1, 2, 2, 2, 3, 4]);
+        // inside a surrogate pair or a combining sequence goes to the start, past the end to the end
+        assert_eq!(to(99), 4);
+        assert!(Position { line: 7, character: 4 }.to_location(line) == Location { grapheme_index: 2, line_index: 7 });
+    }
+
+    #[test]
+    fn changes_within_a_line_send_only_what_differs() {
+        let typed = change(&lines(&["😀 let x = 1;"]), &lines(&["😀 let xy = 1;"])).unwrap();
+        assert_eq!(typed, json!({
+            "range": { "start": { "line": 0, "character": 8 }, "end": { "line": 0, "character": 8 } },
+            "text": "y",
+        }));
+        let erased = change(&lines(&["a", "é😀é", "c"]), &lines(&["a", "éé", "c"])).unwrap();
+        assert_eq!(erased["range"]["start"], json!({ "line": 1, "character": 1 }));
+        assert_eq!(erased["range"]["end"], json!({ "line": 1, "character": 3 }));
+        assert_eq!(erased["text"], "");
+        assert!(change(&lines(&["a", "b"]), &lines(&["a", "b"])).is_none());
+    }
+
+    #[test]
+    fn changes_of_whole_lines_replace_them() {
+        let inserted = change(&lines(&["a", "b", "c"]), &lines(&["a", "x", "y", "c"])).unwrap();
+        assert_eq!(inserted, json!({
+            "range": { "start": { "line": 1, "character": 0 }, "end": { "line": 2, "character": 0 } },
+            "text": "x\ny\n",
+        }));
+        let deleted = change(&lines(&["a", "b", "c"]), &lines(&["a", "c"])).unwrap();
+        assert_eq!(deleted["range"]["end"], json!({ "line": 2, "character": 0 }));
+        assert_eq!(deleted["text"], "");
+        let appended = change(&lines(&["a"]), &lines(&["a", "b"])).unwrap();
+        assert_eq!(appended["range"]["start"], json!({ "line": 1, "character": 0 }));
+        assert_eq!(appended["text"], "b\n");
+    }
+
+    #[test]
+    fn uris_round_trip_to_paths() {
+        let uri = uri("/nonexistent dir/ü#1.rs");
+        assert_eq!(uri, "file:///nonexistent%20dir/%C3%BC%231.rs");
+        assert_eq!(path(&uri).as_deref(), Some("/nonexistent dir/ü#1.rs"));
+        assert_eq!(path("file:///a%2"), Some("/a%2".to_string()));
+        assert_eq!(path("https://example.com/a"), None);
+    }
+
+    #[test]
+    fn messages_are_framed_by_their_length() {
+        let body = r#"{"id":1,"text":"é"}"#;
+        let input = format!(
+            "Content-Length: {}\r\nContent-Type: application/vscode-jsonrpc\r\n\r\n{body}\
+             content-length: 8\r\n\r\nnot json\
+             Content-Length: 2\r\n\r\n{{}}\
+             Content-Length: 10\r\n\r\n{{}}",
+            body.len(),
+        );
+        let mut reader = Cursor::new(input);
+        assert_eq!(read_message(&mut reader), Some(json!({ "id": 1, "text": "é" })));
+        // the body that isn't JSON is skipped
+        assert_eq!(read_message(&mut reader), Some(json!({})));
+        // cut short
+        assert_eq!(read_message(&mut reader), None);
+        assert_eq!(read_message(&mut Cursor::new("\r\n\r\n{}")), None);
+    }
+
+    /// a client talking to the example server, with the replies it got so far
+    struct Session {
+        client: Client,
+        scheduler: Scheduler,
+        replies: VecDeque<Reply>,
+    }
+
+    impl Session {
+        fn start() -> Self {
+            // built by `cargo test` along with the tests, next to the `deps` directory they run from
+            let exe = env::current_exe().unwrap();
+            let server = exe.parent().and_then(Path::parent).unwrap().join("examples").join("fake_lsp");
+            assert!(server.exists(), "{} is missing, build it with `cargo build --example fake_lsp`", server.display());
+            let mut scheduler = Scheduler::default();
+            let client = Client::start(&shell_quote(&server.to_string_lossy()), &mut scheduler).unwrap();
+            Self { client, scheduler, replies: VecDeque::new() }
+        }
+        fn reply(&mut self) -> Reply {
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while self.replies.is_empty() {
+                assert!(Instant::now() < deadline, "the server did not answer");
+                for task in self.scheduler.take_due() {
+                    if let Task::Language { message: Some(message), .. } = task {
+                        self.replies.extend(self.client.handle(&message).unwrap());
+                    }
+                }
+                thread::sleep(Duration::from_millis(5));
+            }
+            self.replies.pop_front().unwrap()
+        }
+        fn diagnostics(&mut self) -> Vec<Diagnostic> {
+            match self.reply() {
+                Reply::Diagnostics { diagnostics, .. } => diagnostics,
+                _ => panic!("expected diagnostics"),
+            }
+        }
+    }
+
+    fn shell_quote(path: &str) -> String {
+        format!("'{}'", path.replace('\'', r"'\''"))
+    }
+
+    #[test]
+    fn talks_to_a_language_server() {
+        let uri = "file:///test/main.rs";
+        let mut document = lines(&["fn main() {", "    let héllo = 1; // TODO", "    héllo", "}"]);
+        let mut session = Session::start();
+        // sent before the server answered `initialize`, so held back until it did
+        session.client.sync(uri, "rust", 1, || document.clone()).unwrap();
+        assert!(!session.client.initialized && session.client.queued.len() == 1);
+
+        let diagnostics = session.diagnostics();
+        assert!(session.client.initialized && session.client.incremental);
+        assert!(session.client.queued.is_empty());
+        assert_eq!(diagnostics.len(), 1);
+        let todo = diagnostics.into_iter().next().unwrap().locate(|line| document[line].clone());
+        assert!(todo.severity == Severity::Warning && todo.message == "TODO left in the code");
+        assert!(todo.start == Location { grapheme_index: 22, line_index: 1 });
+        assert!(todo.end == Location { grapheme_index: 26, line_index: 1 });
+
+        // sent as a change of the line, which the server has to apply right for the rest to work
+        document[2] = "    héllo + 1 // FIXME".to_string();
+        session.client.sync(uri, "rust", 2, || document.clone()).unwrap();
+        let mut severities: Vec<Severity> = session.diagnostics().iter().map(|diagnostic| diagnostic.severity).collect();
+        severities.sort();
+        assert!(severities == [Severity::Error, Severity::Warning]);
+        // the same version again sends nothing
+        session.client.sync(uri, "rust", 2, || unreachable!()).unwrap();
+
+        let on_hello = Position { line: 2, character: 6 };
+        session.client.hover(uri, on_hello).unwrap();
+        let Reply::Hover(text) = session.reply() else { panic!("expected hover") };
+        assert_eq!(text, "`héllo` at line 3, UTF-16 column 6:     héllo + 1 // FIXME");
+
+        session.client.definition(uri, on_hello).unwrap();
+        let Reply::Definition { uri: target, position } = session.reply() else { panic!("expected a definition") };
+        assert!(target == uri && position == Position { line: 1, character: 8 });
+
+        session.client.completion(uri, Position { line: 2, character: 5 }).unwrap();
+        let Reply::Completion { uri: target, position, items } = session.reply() else { panic!("expected completions") };
+        assert!(target == uri && position == Position { line: 2, character: 5 });
+        assert!(["fn", "héllo", "main", "FIXME"].iter().all(|word| items.iter().any(|item| item == word)));
+        assert!(session.client.pending.is_empty());
+    }
+}
@@ -0,0 +1,475 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use toml::{Table, Value};
+
+use super::{command::{Command, Direction, Operator, SelectionKind}, config::{config_dir, read_config}, view::Location};
+
+/// file in the config directory macros are loaded from at start and saved to by default
+const MACROS_FILE: &str = "macros.toml";
+
+/// how deep macros may play other macros, so that a macro playing itself comes to an end
+pub const MAX_DEPTH: usize = 20;
+
+const DIRECTIONS: [(&str, Direction); 16] = [
+    ("up", Direction::Up),
+    ("down", Direction::Down),
+    ("left", Direction::Left),
+    ("right", Direction::Right),
+    ("page_up", Direction::PageUp),
+    ("page_down", Direction::PageDown),
+    ("line_start", Direction::Home),
+    ("line_end", Direction::End),
+    ("step_left", Direction::StepLeft),
+    ("step_right", Direction::StepRight),
+    ("word_forward", Direction::WordForward),
+    ("word_backward", Direction::WordBackward),
+    ("word_end", Direction::WordEnd),
+    ("file_start", Direction::FileStart),
+    ("file_end", Direction::FileEnd),
+    ("matching_bracket", Direction::MatchingBracket),
+];
+
+const OPERATORS: [(&str, Operator); 3] = [
+    ("delete", Operator::Delete),
+    ("change", Operator::Change),
+    ("yank", Operator::Yank),
+];
+
+const SELECTION_KINDS: [(&str, SelectionKind); 3] = [
+    ("characters", SelectionKind::Characters),
+    ("lines", SelectionKind::Lines),
+    ("block", SelectionKind::Block),
+];
+
+/// commands recorded into registers named by a lowercase letter or a digit, to be played again.
+/// macros are saved as a list of steps for each register, for example:
+///
+/// ```toml
+/// a = ["move:line_end", "insert:;", "move:down"]
+/// b = ["ex:s/foo/bar/g", "play:a:2"]
+/// ```
+#[derive(Default)]
+pub struct Macros {
+    registers: BTreeMap<char, Vec<Command>>,
+    /// register being recorded into, with the commands so far
+    recording: Option<(char, Vec<Command>)>,
+    /// register played last, for `@@`
+    last_played: Option<char>,
+    /// how many macros are playing, one inside the other
+    depth: usize,
+    /// set once a command fails while playing, so that every macro playing stops
+    failed: bool,
+}
+
+impl Macros {
+    /// macros saved in the config directory. returns problems found in the file along with them
+    pub fn load() -> (Self, Vec<String>) {
+        let mut macros = Self::default();
+        let errors = match read_config(MACROS_FILE) {
+            None => Vec::new(),
+            Some(Err(e)) => vec![format!("{MACROS_FILE}: {e}")],
+            Some(Ok(contents)) => match macros.parse(&contents) {
+                Ok(_) => Vec::new(),
+                Err(e) => vec![format!("{MACROS_FILE}: {e}")],
+            },
+        };
+        (macros, errors)
+    }
+    /// add macros from a file, the one in the config directory if no path is given.
+    /// returns how many there were
+    pub fn read(&mut self, path: Option<&str>) -> Result<usize, String> {
+        let path = Self::path(path)?;
+        let contents = std::fs::read_to_string(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+        self.parse(&contents).map_err(|e| format!("{}: {e}", path.display()))
+    }
+    /// save all macros to a file, the one in the config directory if no path is given.
+    /// returns where they went
+    pub fn write(&self, path: Option<&str>) -> Result<String, String> {
+        let path = Self::path(path)?;
+        let mut table = Table::new();
+        for (register, commands) in &self.registers {
+            let steps = steps(commands).into_iter().map(Value::String).collect();
+            table.insert(register.to_string(), Value::Array(steps));
+        }
+        let contents = toml::to_string(&table).map_err(|e| e.to_string())?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+        }
+        std::fs::write(&path, contents).map_err(|e| format!("{}: {e}", path.display()))?;
+        Ok(path.display().to_string())
+    }
+    fn path(path: Option<&str>) -> Result<PathBuf, String> {
+        match path {
+            Some(path) => Ok(PathBuf::from(path)),
+            None => config_dir()
+                .map(|dir| dir.join(MACROS_FILE))
+                .ok_or_else(|| "No config directory".to_string()),
+        }
+    }
+    fn parse(&mut self, contents: &str) -> Result<usize, String> {
+        let table = contents.parse::<Table>().map_err(|e| e.message().to_string())?;
+        let mut macros = Vec::new();
+        for (name, value) in table {
+            let register = Self::register(&name)?;
+            let Value::Array(values) = value else {
+                return Err(format!("macro \"{name}\" must be a list of steps"));
+            };
+            let mut commands = Vec::new();
+            for value in values {
+                let Value::String(step) = value else {
+                    return Err(format!("steps of macro \"{name}\" must be strings"));
+                };
+                commands.extend(parse_step(&step).map_err(|e| format!("macro \"{name}\": {e}"))?);
+            }
+            macros.push((register, commands));
+        }
+        let count = macros.len();
+        self.registers.extend(macros);
+        Ok(count)
+    }
+    /// the register named by given text, a lowercase letter or a digit
+    fn register(name: &str) -> Result<char, String> {
+        let mut chars = name.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) if c.is_ascii_lowercase() || c.is_ascii_digit() => Ok(c),
+            _ => Err(format!("E354: Invalid register name: '{name}'")),
+        }
+    }
+
+    // region: recording
+
+    pub fn start_recording(&mut self, register: char) -> Result<(), String> {
+        let register = Self::register(&register.to_string())?;
+        self.recording = Some((register, Vec::new()));
+        Ok(())
+    }
+    /// stop recording and keep what was recorded, returns the register it went to
+    pub fn stop_recording(&mut self) -> Option<char> {
+        let (register, commands) = self.recording.take()?;
+        self.registers.insert(register, commands);
+        Some(register)
+    }
+    /// register being recorded into
+    pub fn recording(&self) -> Option<char> {
+        self.recording.as_ref().map(|(register, _)| *register)
+    }
+    /// add a command to the macro being recorded. commands of a playing macro are left out,
+    /// the command playing it is recorded instead
+    pub fn record(&mut self, command: &Command) {
+        if self.depth > 0 || step(command).is_none() {
+            return;
+        }
+        if let Some((_, commands)) = self.recording.as_mut() {
+            commands.push(command.clone());
+        }
+    }
+
+    // region: playing
+
+    /// commands of the macro to play, `end_playing` must follow once they are done
+    pub fn start_playing(&mut self, register: char) -> Result<Vec<Command>, String> {
+        let register = match register {
+            '@' => self.last_played.ok_or_else(|| "E748: No previously used register".to_string())?,
+            _ => Self::register(&register.to_string())?,
+        };
+        if self.depth >= MAX_DEPTH {
+            return Err("E169: Command too recursive".to_string());
+        }
+        let commands = self.registers.get(&register).cloned().unwrap_or_default();
+        self.last_played = Some(register);
+        self.depth += 1;
+        Ok(commands)
+    }
+    pub fn end_playing(&mut self) {
+        self.depth = self.depth.saturating_sub(1);
+        if self.depth == 0 {
+            self.failed = false;
+        }
+    }
+    /// a command failed. the macros playing stop, the outer ones too
+    pub fn fail(&mut self) {
+        self.failed = self.depth > 0;
+    }
+    /// whether the macros playing are to stop
+    pub const fn failed(&self) -> bool {
+        self.failed
+    }
+}
+
+// region: steps
+
+/// the text of each step of a macro. typed characters in a row make one step
+fn steps(commands: &[Command]) -> Vec<String> {
+    let mut ret: Vec<String> = Vec::new();
+    let mut typing = false;
+    for command in commands {
+        if let Command::Insert(c) = command {
+            if typing {
+                if let Some(last) = ret.last_mut() {
+                    last.push(*c);
+                    continue;
+                }
+            }
+        }
+        if let Some(step) = step(command) {
+            typing = matches!(command, Command::Insert(_));
+            ret.push(step);
+        }
+    }
+    ret
+}
+
+/// the text of a command as a macro step, `None` for commands that are not recorded
+fn step(command: &Command) -> Option<String> {
+    let step = match command {
+        Command::Move(direction) => format!("move:{}", direction_name(*direction)),
+        Command::Insert(c) => format!("insert:{c}"),
+        Command::InsertNewline => "newline".to_string(),
+        Command::DeleteBackward => "delete_backward".to_string(),
+        Command::Delete => "delete".to_string(),
+        Command::Goto(Location { grapheme_index, line_index }) => {
+            format!("goto:{}:{}", line_index + 1, grapheme_index + 1)
+        }
+        Command::Operate { operator, direction, count } => {
+            format!("operate:{}:{}:{count}", operator_name(*operator), direction_name(*direction))
+        }
+        Command::OperateLines { operator, count } => format!("operate_lines:{}:{count}", operator_name(*operator)),
+        Command::OperateSelection(operator) => format!("operate_selection:{}", operator_name(*operator)),
+        Command::StartSelection(kind) => {
+            let name = SELECTION_KINDS.iter().find(|(_, k)| k == kind).map_or("", |(name, _)| name);
+            format!("select:{name}")
+        }
+        Command::SelectBlock(direction) => format!("select_block:{}", direction_name(*direction)),
+        Command::BlockInsert { append: false } => "block_insert".to_string(),
+        Command::BlockInsert { append: true } => "block_append".to_string(),
+        Command::ClearSelection => "clear_selection".to_string(),
+        Command::Paste { before: false } => "paste".to_string(),
+        Command::Paste { before: true } => "paste_before".to_string(),
+        Command::AddCursor(direction) => format!("add_cursor:{}", direction_name(*direction)),
+        Command::AddNextOccurrence => "add_next_occurrence".to_string(),
+        Command::SingleCursor => "single_cursor".to_string(),
+        Command::DuplicateLines => "duplicate_lines".to_string(),
+        Command::MoveLines(direction) => format!("move_lines:{}", direction_name(*direction)),
+        Command::DeleteLines => "delete_lines".to_string(),
+        Command::JoinLines => "join_lines".to_string(),
+        Command::ToggleComment { block: false } => "toggle_comment".to_string(),
+        Command::ToggleComment { block: true } => "toggle_block_comment".to_string(),
+        Command::Undo => "undo".to_string(),
+        Command::Redo => "redo".to_string(),
+        Command::PlayMacro { register, count } => format!("play:{register}:{count}"),
+        Command::Execute(line) => format!("ex:{line}"),
+        Command::Scroll(direction) => format!("scroll:{}", direction_name(*direction)),
+        Command::Search { pattern, backward: false } => format!("search:{pattern}"),
+        Command::Search { pattern, backward: true } => format!("search_backward:{pattern}"),
+        Command::SearchNext { reverse: false } => "search_next".to_string(),
+        Command::SearchNext { reverse: true } => "search_previous".to_string(),
+        Command::Follow => "follow".to_string(),
+        Command::Build => "build".to_string(),
+        Command::Hover => "hover".to_string(),
+        Command::GotoDefinition => "goto_definition".to_string(),
+        Command::Complete => "complete".to_string(),
+        Command::NextError { backward: false } => "next_error".to_string(),
+        Command::NextError { backward: true } => "previous_error".to_string(),
+        Command::Resize(_)
+        | Command::Click { .. }
+        | Command::RecordMacro(_)
+        | Command::StopRecording
+        | Command::GotoLine
+        | Command::FindFile
+        | Command::Ex
+        | Command::StartSearch { .. }
+        | Command::Quit => return None,
+    };
+    Some(step)
+}
+
+/// the commands of a macro step, `insert:` steps type one character after another
+fn parse_step(step: &str) -> Result<Vec<Command>, String> {
+    let (name, rest) = step.split_once(':').unwrap_or((step, ""));
+    match name {
+        "insert" => return Ok(rest.chars().map(Command::Insert).collect()),
+        "ex" => return Ok(vec![Command::Execute(rest.to_string())]),
+        "search" | "search_backward" => {
+            let backward = name == "search_backward";
+            return Ok(vec![Command::Search { pattern: rest.to_string(), backward }]);
+        }
+        _ => (),
+    }
+    let args: Vec<&str> = if rest.is_empty() { Vec::new() } else { rest.split(':').collect() };
+    let bad_step = || format!("bad step \"{step}\"");
+    let direction = |index: usize| -> Result<Direction, String> {
+        let arg = args.get(index).ok_or_else(bad_step)?;
+        DIRECTIONS.iter().find(|(name, _)| name == arg).map(|(_, d)| *d).ok_or_else(bad_step)
+    };
+    let operator = |index: usize| -> Result<Operator, String> {
+        let arg = args.get(index).ok_or_else(bad_step)?;
+        OPERATORS.iter().find(|(name, _)| name == arg).map(|(_, o)| *o).ok_or_else(bad_step)
+    };
+    let number = |index: usize| -> Result<usize, String> {
+        args.get(index).and_then(|arg| arg.parse().ok()).ok_or_else(bad_step)
+    };
+    let command = match (name, args.len()) {
+        ("move", 1) => Command::Move(direction(0)?),
+        ("newline", 0) => Command::InsertNewline,
+        ("delete_backward", 0) => Command::DeleteBackward,
+        ("delete", 0) => Command::Delete,
+        ("goto", 2) => Command::Goto(Location {
+            grapheme_index: number(1)?.saturating_sub(1),
+            line_index: number(0)?.saturating_sub(1),
+        }),
+        ("operate", 3) => Command::Operate { operator: operator(0)?, direction: direction(1)?, count: number(2)? },
+        ("operate_lines", 2) => Command::OperateLines { operator: operator(0)?, count: number(1)? },
+        ("operate_selection", 1) => Command::OperateSelection(operator(0)?),
+        ("select", 1) => {
+            let kind = SELECTION_KINDS.iter().find(|(name, _)| *name == args[0]).ok_or_else(bad_step)?;
+            Command::StartSelection(kind.1)
+        }
+        ("select_block", 1) => Command::SelectBlock(direction(0)?),
+        ("block_insert", 0) => Command::BlockInsert { append: false },
+        ("block_append", 0) => Command::BlockInsert { append: true },
+        ("clear_selection", 0) => Command::ClearSelection,
+        ("paste", 0) => Command::Paste { before: false },
+        ("paste_before", 0) => Command::Paste { before: true },
+        ("add_cursor", 1) => Command::AddCursor(direction(0)?),
+        ("add_next_occurrence", 0) => Command::AddNextOccurrence,
+        ("single_cursor", 0) => Command::SingleCursor,
+        ("duplicate_lines", 0) => Command::DuplicateLines,
+        ("move_lines", 1) => Command::MoveLines(direction(0)?),
+        ("delete_lines", 0) => Command::DeleteLines,
+        ("join_lines", 0) => Command::JoinLines,
+        ("toggle_comment", 0) => Command::ToggleComment { block: false },
+        ("toggle_block_comment", 0) => Command::ToggleComment { block: true },
+        ("undo", 0) => Command::Undo,
+        ("redo", 0) => Command::Redo,
+        ("scroll", 1) => Command::Scroll(direction(0)?),
+        ("search_next", 0) => Command::SearchNext { reverse: false },
+        ("search_previous", 0) => Command::SearchNext { reverse: true },
+        ("follow", 0) => Command::Follow,
+        ("build", 0) => Command::Build,
+        ("hover", 0) => Command::Hover,
+        ("goto_definition", 0) => Command::GotoDefinition,
+        ("complete", 0) => Command::Complete,
+        ("next_error", 0) => Command::NextError { backward: false },
+        ("previous_error", 0) => Command::NextError { backward: true },
+        ("play", 2) => {
+            let mut register = args[0].chars();
+            match (register.next(), register.next()) {
+                (Some(register), None) => Command::PlayMacro { register, count: number(1)? },
+                _ => return Err(bad_step()),
+            }
+        }
+        _ => return Err(bad_step()),
+    };
+    Ok(vec![command])
+}
+
+fn direction_name(direction: Direction) -> &'static str {
+    DIRECTIONS.iter().find(|(_, d)| *d == direction).map_or("", |(name, _)| name)
+}
+
+fn operator_name(operator: Operator) -> &'static str {
+    OPERATORS.iter().find(|(_, o)| *o == operator).map_or("", |(name, _)| name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commands() -> Vec<Command> {
+        vec![
+            Command::Insert('a'),
+            Command::Insert(':'),
+            Command::Move(Direction::WordEnd),
+            Command::Insert('b'),
+            Command::Operate { operator: Operator::Change, direction: Direction::WordForward, count: 3 },
+            Command::OperateLines { operator: Operator::Delete, count: 2 },
+            Command::Goto(Location { grapheme_index: 4, line_index: 9 }),
+            Command::Execute("s/a:b/c/g".to_string()),
+            Command::Search { pattern: "x:y".to_string(), backward: true },
+            Command::StartSelection(SelectionKind::Block),
+            Command::PlayMacro { register: 'q', count: 5 },
+        ]
+    }
+
+    fn expected_steps() -> Vec<&'static str> {
+        vec![
+            "insert:a:",
+            "move:word_end",
+            "insert:b",
+            "operate:change:word_forward:3",
+            "operate_lines:delete:2",
+            "goto:10:5",
+            "ex:s/a:b/c/g",
+            "search_backward:x:y",
+            "select:block",
+            "play:q:5",
+        ]
+    }
+
+    #[test]
+    fn typed_characters_in_a_row_make_one_step() {
+        assert_eq!(steps(&commands()), expected_steps());
+    }
+
+    #[test]
+    fn steps_parse_back_into_the_same_commands() {
+        let parsed: Vec<Command> = expected_steps()
+            .into_iter()
+            .flat_map(|step| parse_step(step).unwrap())
+            .collect();
+        assert_eq!(parsed.len(), commands().len());
+        assert_eq!(steps(&parsed), expected_steps());
+    }
+
+    #[test]
+    fn macros_written_to_a_file_read_back_the_same() {
+        let path = std::env::temp_dir().join(format!("my-hecto-macros-{}.toml", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        let mut macros = Macros::default();
+        macros.start_recording('a').unwrap();
+        for command in commands() {
+            macros.record(&command);
+        }
+        // not a step, left out
+        macros.record(&Command::Quit);
+        macros.stop_recording();
+        macros.write(Some(&path)).unwrap();
+
+        let mut read = Macros::default();
+        assert_eq!(read.read(Some(&path)), Ok(1));
+        let played = read.start_playing('a').unwrap();
+        assert_eq!(steps(&played), expected_steps());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn malformed_steps_are_refused() {
+        for step in [
+            "bogus",
+            "move",
+            "move:sideways",
+            "move:up:down",
+            "operate:delete:word_forward",
+            "operate:delete:word_forward:many",
+            "operate:paint:word_forward:1",
+            "goto:1",
+            "select:circle",
+            "play:ab:1",
+            "play:a",
+            "undo:1",
+        ] {
+            assert_eq!(parse_step(step).err(), Some(format!("bad step \"{step}\"")), "{step}");
+        }
+    }
+
+    #[test]
+    fn malformed_files_are_refused() {
+        let mut macros = Macros::default();
+        assert_eq!(macros.parse("a = \"undo\""), Err("macro \"a\" must be a list of steps".to_string()));
+        assert_eq!(macros.parse("a = [1]"), Err("steps of macro \"a\" must be strings".to_string()));
+        assert_eq!(macros.parse("A = []"), Err("E354: Invalid register name: 'A'".to_string()));
+        assert_eq!(macros.parse("a = [\"move:nowhere\"]"), Err("macro \"a\": bad step \"move:nowhere\"".to_string()));
+        assert!(macros.parse("a = [").is_err());
+        assert!(macros.start_playing('a').unwrap().is_empty());
+    }
+}
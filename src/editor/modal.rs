@@ -0,0 +1,534 @@
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+
+use super::{command::{Command, Direction, Operator, SelectionKind}, keymap::{Keymap, Resolution}, view::Location};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Normal,
+    Insert,
+    /// selecting graphemes
+    Visual,
+    /// selecting whole lines
+    VisualLine,
+    /// selecting a rectangle of columns
+    VisualBlock,
+}
+
+/// vi-like modal layer in front of the view.
+/// translates keys into commands depending on the current mode
+pub struct Modal {
+    mode: Mode,
+    /// count typed before a command, like `3` in `3dw`
+    count: Option<usize>,
+    /// operator waiting for a motion, with the count typed before it
+    operator: Option<(Operator, usize)>,
+    /// whether `g` was typed, waiting for the second key of `gg`
+    pending_g: bool,
+    /// `q` or `@` typed, waiting for the name of a macro register
+    pending_register: Option<char>,
+    /// whether a macro is being recorded, so that `q` stops it
+    recording: bool,
+    /// keys of the change being typed. changes made in visual mode are not recorded
+    change: Option<Vec<KeyEvent>>,
+    /// keys of the last complete change, replayed by `.`
+    last_change: Vec<KeyEvent>,
+    /// set while replaying, so that replayed keys are not recorded again
+    replaying: bool,
+}
+
+impl Default for Modal {
+    fn default() -> Self {
+        Self {
+            mode: Mode::Normal,
+            count: None,
+            operator: None,
+            pending_g: false,
+            pending_register: None,
+            recording: false,
+            change: None,
+            last_change: Vec::new(),
+            replaying: false,
+        }
+    }
+}
+
+impl Modal {
+    pub const fn mode_name(&self) -> &'static str {
+        match self.mode {
+            Mode::Normal => "NORMAL",
+            Mode::Insert => "INSERT",
+            Mode::Visual => "VISUAL",
+            Mode::VisualLine => "V-LINE",
+            Mode::VisualBlock => "V-BLOCK",
+        }
+    }
+    pub fn set_recording(&mut self, recording: bool) {
+        self.recording = recording;
+    }
+    /// translate an event into commands for the editor.
+    /// keys without a meaning in the current mode are looked up in the keymap
+    pub fn handle(&mut self, event: Event, keymap: &mut Keymap) -> Vec<Command> {
+        match event {
+            Event::Key(key) if key.kind == KeyEventKind::Press => self.handle_key(key, keymap),
+            Event::Key(_) => Vec::new(),
+            _ => Command::try_from(event).into_iter().collect(),
+        }
+    }
+    fn handle_key(&mut self, key: KeyEvent, keymap: &mut Keymap) -> Vec<Command> {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('v') && self.mode != Mode::Insert {
+            self.reset_pending();
+            self.change = None;
+            return if self.mode == Mode::VisualBlock {
+                self.mode = Mode::Normal;
+                vec![Command::ClearSelection]
+            } else {
+                self.mode = Mode::VisualBlock;
+                vec![Command::StartSelection(SelectionKind::Block)]
+            };
+        }
+        // keys with Ctrl or Alt and function keys keep their usual meaning in every mode
+        if key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) || matches!(key.code, KeyCode::F(_)) {
+            self.reset_pending();
+            return Self::lookup(key, keymap);
+        }
+        match self.mode {
+            Mode::Insert => self.insert_key(key, keymap),
+            Mode::Normal => {
+                if !self.is_pending() && !self.replaying {
+                    self.change = Some(Vec::new());
+                }
+                self.record(key);
+                self.normal_key(key, keymap)
+            }
+            Mode::Visual | Mode::VisualLine | Mode::VisualBlock => self.visual_key(key),
+        }
+    }
+
+    // region: modes
+
+    fn insert_key(&mut self, key: KeyEvent, keymap: &mut Keymap) -> Vec<Command> {
+        self.record(key);
+        if key.code == KeyCode::Esc {
+            self.mode = Mode::Normal;
+            self.finish_change();
+            return vec![Command::Move(Direction::StepLeft)];
+        }
+        Self::lookup(key, keymap)
+    }
+    fn lookup(key: KeyEvent, keymap: &mut Keymap) -> Vec<Command> {
+        match keymap.resolve(key) {
+            Resolution::Command(command) => vec![command],
+            Resolution::Pending | Resolution::Unbound => Vec::new(),
+        }
+    }
+    fn normal_key(&mut self, key: KeyEvent, keymap: &mut Keymap) -> Vec<Command> {
+        let code = key.code;
+        if self.pending_g {
+            self.pending_g = false;
+            return match code {
+                KeyCode::Char('g') => self.motion(Direction::FileStart),
+                KeyCode::Char('d') if self.operator.is_none() => {
+                    self.count = None;
+                    vec![Command::GotoDefinition]
+                }
+                _ => self.cancel(),
+            };
+        }
+        if let Some(pending) = self.pending_register.take() {
+            let KeyCode::Char(register) = code else {
+                return self.cancel();
+            };
+            let count = self.count.take().unwrap_or(1);
+            return match pending {
+                'q' => vec![Command::RecordMacro(register)],
+                _ => vec![Command::PlayMacro { register, count }],
+            };
+        }
+        if self.count_digit(code) {
+            return Vec::new();
+        }
+        if let Some(direction) = Self::direction(code) {
+            return self.motion(direction);
+        }
+        if code == KeyCode::Esc && !self.is_pending() {
+            return vec![Command::SingleCursor];
+        }
+        let KeyCode::Char(c) = code else {
+            return self.cancel();
+        };
+        if let Some(operator) = Self::operator(c) {
+            return self.operator_key(operator);
+        }
+        if c == 'g' {
+            self.pending_g = true;
+            return Vec::new();
+        }
+        if self.operator.is_some() {
+            return self.cancel();
+        }
+        match c {
+            'q' if self.recording => {
+                self.count = None;
+                return vec![Command::StopRecording];
+            }
+            'q' | '@' => {
+                self.pending_register = Some(c);
+                return Vec::new();
+            }
+            _ => (),
+        }
+
+        let count = self.count.take().unwrap_or(1);
+        let commands = match c {
+            'x' => vec![Self::operate(Operator::Delete, Direction::StepRight, count)],
+            'X' => vec![Self::operate(Operator::Delete, Direction::StepLeft, count)],
+            'D' => vec![Self::operate(Operator::Delete, Direction::End, 1)],
+            'C' => self.insert_after(vec![Self::operate(Operator::Change, Direction::End, 1)]),
+            's' => self.insert_after(vec![Self::operate(Operator::Change, Direction::StepRight, count)]),
+            'p' | 'P' => (0..count).map(|_| Command::Paste { before: c == 'P' }).collect(),
+            'i' => self.insert_after(Vec::new()),
+            'a' => self.insert_after(vec![Command::Move(Direction::StepRight)]),
+            'I' => self.insert_after(vec![Command::Move(Direction::Home)]),
+            'A' => self.insert_after(vec![Command::Move(Direction::End)]),
+            'o' => self.insert_after(vec![Command::Move(Direction::End), Command::InsertNewline]),
+            'O' => self.insert_after(vec![
+                Command::Move(Direction::Home),
+                Command::InsertNewline,
+                Command::Move(Direction::Up),
+            ]),
+            'v' => {
+                self.mode = Mode::Visual;
+                return vec![Command::StartSelection(SelectionKind::Characters)];
+            }
+            'V' => {
+                self.mode = Mode::VisualLine;
+                return vec![Command::StartSelection(SelectionKind::Lines)];
+            }
+            // `3J` joins three lines, like `J` joins two
+            'J' => (1..count.max(2)).map(|_| Command::JoinLines).collect(),
+            'u' => return (0..count).map(|_| Command::Undo).collect(),
+            '.' => return self.repeat(count, keymap),
+            ':' => return vec![Command::Ex],
+            'K' => return vec![Command::Hover],
+            _ => return Vec::new(),
+        };
+        if self.mode == Mode::Normal {
+            self.finish_change();
+        }
+        commands
+    }
+    fn visual_key(&mut self, key: KeyEvent) -> Vec<Command> {
+        let code = key.code;
+        if self.count_digit(code) {
+            return Vec::new();
+        }
+        if self.pending_g {
+            self.pending_g = false;
+            if code == KeyCode::Char('g') {
+                return vec![Command::Move(Direction::FileStart)];
+            }
+        }
+        if let Some(direction) = Self::direction(code) {
+            let count = self.count.take().unwrap_or(1);
+            return (0..count).map(|_| Command::Move(direction)).collect();
+        }
+        self.count = None;
+        let operator = match code {
+            KeyCode::Char('d' | 'x') => Operator::Delete,
+            KeyCode::Char('c' | 's') => Operator::Change,
+            KeyCode::Char('y') => Operator::Yank,
+            KeyCode::Char('g') => {
+                self.pending_g = true;
+                return Vec::new();
+            }
+            KeyCode::Esc | KeyCode::Char('v' | 'V') => {
+                self.mode = Mode::Normal;
+                return vec![Command::ClearSelection];
+            }
+            // the command line works on the selected lines, like `:'<,'>sort`
+            KeyCode::Char(':') => {
+                self.mode = Mode::Normal;
+                return vec![Command::Ex];
+            }
+            // typing on every line of a block
+            KeyCode::Char(c @ ('I' | 'A')) if self.mode == Mode::VisualBlock => {
+                self.mode = Mode::Insert;
+                self.change = None;
+                return vec![Command::BlockInsert { append: c == 'A' }];
+            }
+            _ => return Vec::new(),
+        };
+        self.mode = if operator == Operator::Change { Mode::Insert } else { Mode::Normal };
+        self.change = None;
+        vec![Command::OperateSelection(operator)]
+    }
+    /// collect a digit of the count, returns whether the key was taken.
+    /// `0` is a motion unless it follows other digits
+    fn count_digit(&mut self, code: KeyCode) -> bool {
+        let KeyCode::Char(digit @ '0'..='9') = code else {
+            return false;
+        };
+        if digit == '0' && self.count.is_none() {
+            return false;
+        }
+        let digit = digit as usize - '0' as usize;
+        self.count = Some(self.count.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+        true
+    }
+
+    // region: operators & motions
+
+    fn operator_key(&mut self, operator: Operator) -> Vec<Command> {
+        let count = self.count.take().unwrap_or(1);
+        match self.operator.take() {
+            // doubled operators, like `dd`, work on whole lines
+            Some((pending, pending_count)) if pending == operator => {
+                let command = Command::OperateLines { operator, count: pending_count * count };
+                self.finish_operator(operator, command)
+            }
+            Some(_) => self.cancel(),
+            None => {
+                self.operator = Some((operator, count));
+                Vec::new()
+            }
+        }
+    }
+    fn motion(&mut self, direction: Direction) -> Vec<Command> {
+        let count = self.count.take();
+        match self.operator.take() {
+            Some((operator, pending_count)) => {
+                let count = pending_count * count.unwrap_or(1);
+                // `cw` changes to the end of the word, like `ce`
+                let direction = match (operator, direction) {
+                    (Operator::Change, Direction::WordForward) => Direction::WordEnd,
+                    _ => direction,
+                };
+                let command = Self::operate(operator, direction, count);
+                self.finish_operator(operator, command)
+            }
+            None => match (direction, count) {
+                (Direction::FileStart | Direction::FileEnd, Some(line)) => {
+                    vec![Command::Goto(Location { grapheme_index: 0, line_index: line.saturating_sub(1) })]
+                }
+                _ => (0..count.unwrap_or(1)).map(|_| Command::Move(direction)).collect(),
+            },
+        }
+    }
+    fn finish_operator(&mut self, operator: Operator, command: Command) -> Vec<Command> {
+        if operator == Operator::Change {
+            return self.insert_after(vec![command]);
+        }
+        if operator == Operator::Delete {
+            self.finish_change();
+        }
+        vec![command]
+    }
+    const fn operate(operator: Operator, direction: Direction, count: usize) -> Command {
+        Command::Operate { operator, direction, count }
+    }
+    const fn operator(c: char) -> Option<Operator> {
+        match c {
+            'd' => Some(Operator::Delete),
+            'c' => Some(Operator::Change),
+            'y' => Some(Operator::Yank),
+            _ => None,
+        }
+    }
+    /// motions shared by normal and visual mode
+    const fn direction(code: KeyCode) -> Option<Direction> {
+        let direction = match code {
+            KeyCode::Char('h') | KeyCode::Left | KeyCode::Backspace => Direction::StepLeft,
+            KeyCode::Char('l') | KeyCode::Right | KeyCode::Char(' ') => Direction::StepRight,
+            KeyCode::Char('j') | KeyCode::Down | KeyCode::Enter => Direction::Down,
+            KeyCode::Char('k') | KeyCode::Up => Direction::Up,
+            KeyCode::Char('w') => Direction::WordForward,
+            KeyCode::Char('b') => Direction::WordBackward,
+            KeyCode::Char('e') => Direction::WordEnd,
+            KeyCode::Char('0') | KeyCode::Home => Direction::Home,
+            KeyCode::Char('$') | KeyCode::End => Direction::End,
+            KeyCode::Char('G') => Direction::FileEnd,
+            KeyCode::Char('%') => Direction::MatchingBracket,
+            KeyCode::PageUp => Direction::PageUp,
+            KeyCode::PageDown => Direction::PageDown,
+            _ => return None,
+        };
+        Some(direction)
+    }
+
+    // region: repeating
+
+    /// switch to insert mode after given commands
+    fn insert_after(&mut self, commands: Vec<Command>) -> Vec<Command> {
+        self.mode = Mode::Insert;
+        commands
+    }
+    /// `.`, feed the keys of the last change again, `count` times
+    fn repeat(&mut self, count: usize, keymap: &mut Keymap) -> Vec<Command> {
+        if self.replaying {
+            return Vec::new();
+        }
+        let keys = self.last_change.clone();
+        self.replaying = true;
+        let mut commands = Vec::new();
+        for _ in 0..count {
+            for key in &keys {
+                commands.extend(self.handle_key(*key, keymap));
+            }
+        }
+        self.replaying = false;
+        commands
+    }
+    fn record(&mut self, key: KeyEvent) {
+        if self.replaying {
+            return;
+        }
+        if let Some(change) = self.change.as_mut() {
+            change.push(key);
+        }
+    }
+    /// the recorded keys form a complete change, remember them for `.`
+    fn finish_change(&mut self) {
+        if self.replaying {
+            return;
+        }
+        if let Some(change) = self.change.take() {
+            self.last_change = change;
+        }
+    }
+    const fn is_pending(&self) -> bool {
+        self.count.is_some() || self.operator.is_some() || self.pending_g || self.pending_register.is_some()
+    }
+    fn reset_pending(&mut self) {
+        self.count = None;
+        self.operator = None;
+        self.pending_g = false;
+        self.pending_register = None;
+    }
+    fn cancel(&mut self) -> Vec<Command> {
+        self.reset_pending();
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// commands for typed keys, `<` standing for Esc
+    fn feed(modal: &mut Modal, keys: &str) -> Vec<Command> {
+        let mut keymap = Keymap::default();
+        let mut commands = Vec::new();
+        for c in keys.chars() {
+            let code = if c == '<' { KeyCode::Esc } else { KeyCode::Char(c) };
+            commands.extend(modal.handle(Event::Key(KeyEvent::new(code, KeyModifiers::NONE)), &mut keymap));
+        }
+        commands
+    }
+
+    fn is_operate(command: &Command, operator: Operator, direction: Direction, count: usize) -> bool {
+        matches!(command, Command::Operate { operator: o, direction: d, count: c } if *o == operator && *d == direction && *c == count)
+    }
+
+    #[test]
+    fn counts_multiply_across_operator_and_motion() {
+        let mut modal = Modal::default();
+        let commands = feed(&mut modal, "3dd");
+        assert!(matches!(commands[..], [Command::OperateLines { operator: Operator::Delete, count: 3 }]));
+        let commands = feed(&mut modal, "d2w");
+        assert!(commands.len() == 1 && is_operate(&commands[0], Operator::Delete, Direction::WordForward, 2));
+        let commands = feed(&mut modal, "2d3w");
+        assert!(commands.len() == 1 && is_operate(&commands[0], Operator::Delete, Direction::WordForward, 6));
+        let commands = feed(&mut modal, "3j");
+        assert!(commands.len() == 3 && commands.iter().all(|c| matches!(c, Command::Move(Direction::Down))));
+        assert_eq!(modal.mode_name(), "NORMAL");
+    }
+
+    #[test]
+    fn change_word_changes_to_the_end_of_the_word() {
+        let mut modal = Modal::default();
+        let commands = feed(&mut modal, "cw");
+        assert!(commands.len() == 1 && is_operate(&commands[0], Operator::Change, Direction::WordEnd, 1));
+        assert_eq!(modal.mode_name(), "INSERT");
+        let commands = feed(&mut modal, "<");
+        assert!(matches!(commands[..], [Command::Move(Direction::StepLeft)]));
+        assert_eq!(modal.mode_name(), "NORMAL");
+    }
+
+    #[test]
+    fn a_count_before_g_goes_to_that_line() {
+        let mut modal = Modal::default();
+        assert!(matches!(feed(&mut modal, "gg")[..], [Command::Move(Direction::FileStart)]));
+        assert!(matches!(feed(&mut modal, "G")[..], [Command::Move(Direction::FileEnd)]));
+        let commands = feed(&mut modal, "5G");
+        assert!(matches!(commands[..], [Command::Goto(Location { grapheme_index: 0, line_index: 4 })]));
+        let commands = feed(&mut modal, "12gg");
+        assert!(matches!(commands[..], [Command::Goto(Location { grapheme_index: 0, line_index: 11 })]));
+        let commands = feed(&mut modal, "dgg");
+        assert!(commands.len() == 1 && is_operate(&commands[0], Operator::Delete, Direction::FileStart, 1));
+    }
+
+    #[test]
+    fn visual_modes_end_with_an_operator() {
+        let mut modal = Modal::default();
+        let commands = feed(&mut modal, "v2jd");
+        assert!(matches!(
+            commands[..],
+            [
+                Command::StartSelection(SelectionKind::Characters),
+                Command::Move(Direction::Down),
+                Command::Move(Direction::Down),
+                Command::OperateSelection(Operator::Delete),
+            ]
+        ));
+        assert_eq!(modal.mode_name(), "NORMAL");
+
+        let commands = feed(&mut modal, "Vc");
+        assert!(matches!(
+            commands[..],
+            [Command::StartSelection(SelectionKind::Lines), Command::OperateSelection(Operator::Change)]
+        ));
+        assert_eq!(modal.mode_name(), "INSERT");
+        feed(&mut modal, "<");
+
+        let mut keymap = Keymap::default();
+        let ctrl_v = Event::Key(KeyEvent::new(KeyCode::Char('v'), KeyModifiers::CONTROL));
+        let commands = modal.handle(ctrl_v, &mut keymap);
+        assert!(matches!(commands[..], [Command::StartSelection(SelectionKind::Block)]));
+        assert_eq!(modal.mode_name(), "V-BLOCK");
+        let commands = feed(&mut modal, "jA");
+        assert!(matches!(commands[..], [Command::Move(Direction::Down), Command::BlockInsert { append: true }]));
+        assert_eq!(modal.mode_name(), "INSERT");
+    }
+
+    #[test]
+    fn dot_repeats_the_last_change_count_times() {
+        let mut modal = Modal::default();
+        feed(&mut modal, "2dd");
+        let commands = feed(&mut modal, ".");
+        assert!(matches!(commands[..], [Command::OperateLines { operator: Operator::Delete, count: 2 }]));
+        let commands = feed(&mut modal, "3.");
+        assert!(commands.len() == 3);
+        assert!(commands.iter().all(|c| matches!(c, Command::OperateLines { operator: Operator::Delete, count: 2 })));
+
+        // motions are no changes, `.` still repeats the delete
+        feed(&mut modal, "jw");
+        assert!(matches!(feed(&mut modal, ".")[..], [Command::OperateLines { .. }]));
+
+        feed(&mut modal, "Ahi<");
+        let commands = feed(&mut modal, "2.");
+        assert!(matches!(
+            commands[..],
+            [
+                Command::Move(Direction::End),
+                Command::Insert('h'),
+                Command::Insert('i'),
+                Command::Move(Direction::StepLeft),
+                Command::Move(Direction::End),
+                Command::Insert('h'),
+                Command::Insert('i'),
+                Command::Move(Direction::StepLeft),
+            ]
+        ));
+        assert_eq!(modal.mode_name(), "NORMAL");
+    }
+}
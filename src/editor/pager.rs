@@ -0,0 +1,53 @@
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+
+use super::{command::{Command, Direction}, keymap::{Keymap, Resolution}};
+
+/// less-like layer in front of the view, for reading files without changing them.
+/// keys scroll the screen rather than move the caret
+#[derive(Default)]
+pub struct Pager;
+
+impl Pager {
+    /// translate an event into commands for the editor.
+    /// keys with Ctrl and the ones the pager has no use for are looked up in the keymap
+    pub fn handle(&self, event: Event, keymap: &mut Keymap) -> Vec<Command> {
+        match event {
+            Event::Key(key) if key.kind == KeyEventKind::Press => Self::handle_key(key, keymap),
+            Event::Key(_) => Vec::new(),
+            // the mouse wheel scrolls too
+            _ => match Command::try_from(event) {
+                Ok(Command::Move(direction)) => vec![Command::Scroll(direction)],
+                result => result.into_iter().collect(),
+            },
+        }
+    }
+    fn handle_key(key: KeyEvent, keymap: &mut Keymap) -> Vec<Command> {
+        if key.modifiers.contains(KeyModifiers::CONTROL) {
+            return Self::lookup(key, keymap);
+        }
+        let command = match key.code {
+            KeyCode::Char(' ' | 'f') | KeyCode::PageDown => Command::Scroll(Direction::PageDown),
+            KeyCode::Char('b') | KeyCode::PageUp => Command::Scroll(Direction::PageUp),
+            KeyCode::Char('j' | 'e') | KeyCode::Down | KeyCode::Enter => Command::Scroll(Direction::Down),
+            KeyCode::Char('k' | 'y') | KeyCode::Up => Command::Scroll(Direction::Up),
+            KeyCode::Char('g' | '<') | KeyCode::Home => Command::Scroll(Direction::FileStart),
+            KeyCode::Char('G' | '>') | KeyCode::End => Command::Scroll(Direction::FileEnd),
+            KeyCode::Char('/') => Command::StartSearch { backward: false },
+            KeyCode::Char('?') => Command::StartSearch { backward: true },
+            KeyCode::Char('n') => Command::SearchNext { reverse: false },
+            KeyCode::Char('N') => Command::SearchNext { reverse: true },
+            KeyCode::Char('F') => Command::Follow,
+            KeyCode::Char(':') => Command::Ex,
+            KeyCode::Char('q' | 'Q') => Command::Quit,
+            KeyCode::Left | KeyCode::Right | KeyCode::F(_) => return Self::lookup(key, keymap),
+            _ => return Vec::new(),
+        };
+        vec![command]
+    }
+    fn lookup(key: KeyEvent, keymap: &mut Keymap) -> Vec<Command> {
+        match keymap.resolve(key) {
+            Resolution::Command(command) => vec![command],
+            Resolution::Pending | Resolution::Unbound => Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use regex::Regex;
+
+use super::{diagnostic::{Diagnostic, Severity}, view::Location};
+
+/// a place in a file a build complained about
+pub struct Entry {
+    pub path: String,
+    /// 1-based
+    pub line: usize,
+    /// 1-based, 0 if the tool did not tell
+    pub column: usize,
+    /// `error`, `warning`, `note` or `panic`
+    pub severity: String,
+    /// like `E0425` for rustc
+    pub code: Option<String>,
+    pub message: String,
+}
+
+impl Entry {
+    /// how the entry shows in the list view
+    pub fn describe(&self) -> String {
+        let Self { path, line, column, severity, code, message } = self;
+        let severity = match code {
+            Some(code) => format!("{severity}[{code}]"),
+            None => severity.clone(),
+        };
+        match column {
+            0 => format!("{path}:{line}: {severity}: {message}"),
+            column => format!("{path}:{line}:{column}: {severity}: {message}"),
+        }
+    }
+    /// the entry as a problem marked in the file's view. without a column it is about the whole line
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let line_index = self.line.saturating_sub(1);
+        let start = Location { line_index, grapheme_index: self.column.saturating_sub(1) };
+        let end = match self.column {
+            0 => Location { line_index, grapheme_index: usize::MAX },
+            _ => start,
+        };
+        let severity = match self.severity.as_str() {
+            "warning" => Severity::Warning,
+            "note" => Severity::Information,
+            _ => Severity::Error,
+        };
+        Diagnostic {
+            start,
+            end,
+            severity,
+            code: self.code.clone(),
+            message: self.message.clone(),
+            source: "make".to_string(),
+        }
+    }
+}
+
+/// diagnostics of the last build, in the order the tool printed them
+#[derive(Default)]
+pub struct Quickfix {
+    entries: Vec<Entry>,
+    /// the entry jumped to last, none before the first jump
+    pub current: Option<usize>,
+    /// index of the view listing the entries, if it is open
+    pub list_view: Option<usize>,
+}
+
+impl Quickfix {
+    /// pick diagnostics out of the output of a build. understands rustc's
+    ///
+    /// ```text
+    /// error[E0425]: cannot find value `x` in this scope
+    ///  --> src/main.rs:2:5
+    /// ```
+    ///
+    /// gcc's `file:line:column: error: message`, with or without the column or the severity,
+    /// and where the Rust tests panicked
+    pub fn parse(output: &[String]) -> Vec<Entry> {
+        let header = Regex::new(r"^(error|warning)(?:\[(\w+)\])?: (.+)$").expect("valid regex");
+        let arrow = Regex::new(r"^\s*--> (.+?):(\d+):(\d+)$").expect("valid regex");
+        let panic = Regex::new(r"panicked at (.+?):(\d+):(\d+):?$").expect("valid regex");
+        let generic = Regex::new(r"^((?:[A-Za-z]:)?[^\s:][^:]*):(\d+):(?:(\d+):)?\s*(?:(fatal error|error|warning|note):)?\s*(.*)$")
+            .expect("valid regex");
+        let number = |text: Option<regex::Match>| text.and_then(|text| text.as_str().parse().ok()).unwrap_or(0);
+
+        let mut entries = Vec::new();
+        // the last rustc header, waiting for the location under it
+        let mut pending: Option<(String, Option<String>, String)> = None;
+        for (index, line) in output.iter().enumerate() {
+            if let Some(captures) = header.captures(line) {
+                let code = captures.get(2).map(|code| code.as_str().to_string());
+                pending = Some((captures[1].to_string(), code, captures[3].to_string()));
+            } else if let Some(captures) = arrow.captures(line) {
+                // only the first location of a diagnostic, the others point at related code
+                if let Some((severity, code, message)) = pending.take() {
+                    entries.push(Entry {
+                        path: captures[1].to_string(),
+                        line: number(captures.get(2)),
+                        column: number(captures.get(3)),
+                        severity,
+                        code,
+                        message,
+                    });
+                }
+            } else if let Some(captures) = panic.captures(line) {
+                // the panic message is on the next line
+                let message = output.get(index + 1).map_or("panicked", |message| message.trim());
+                entries.push(Entry {
+                    path: captures[1].to_string(),
+                    line: number(captures.get(2)),
+                    column: number(captures.get(3)),
+                    severity: "panic".to_string(),
+                    code: None,
+                    message: message.to_string(),
+                });
+            } else if let Some(captures) = generic.captures(line) {
+                entries.push(Entry {
+                    path: captures[1].to_string(),
+                    line: number(captures.get(2)),
+                    column: number(captures.get(3)),
+                    severity: captures.get(4).map_or("error", |severity| severity.as_str()).to_string(),
+                    code: None,
+                    message: captures[5].trim().to_string(),
+                });
+            }
+        }
+        entries
+    }
+    pub fn set_entries(&mut self, entries: Vec<Entry>) {
+        self.entries = entries;
+        self.current = None;
+    }
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+    /// the entry at given index, which becomes the current one
+    pub fn select(&mut self, index: usize) -> Result<&Entry, String> {
+        if self.entries.is_empty() {
+            return Err("E42: No Errors".to_string());
+        }
+        let entry = self.entries.get(index).ok_or_else(|| "E553: No more items".to_string())?;
+        self.current = Some(index);
+        Ok(entry)
+    }
+    /// index of the entry after the current one, or before it. the first one before any jump
+    pub fn neighbour(&self, backward: bool) -> Result<usize, String> {
+        let index = match self.current {
+            None => Some(0),
+            Some(current) if backward => current.checked_sub(1),
+            Some(current) => Some(current + 1),
+        };
+        match index {
+            _ if self.entries.is_empty() => Err("E42: No Errors".to_string()),
+            Some(index) if index < self.entries.len() => Ok(index),
+            _ => Err("E553: No more items".to_string()),
+        }
+    }
+    /// counts for the message shown after a build
+    pub fn summary(&self) -> String {
+        let count = |severity: &str| self.entries.iter().filter(|entry| entry.severity == severity).count();
+        let (errors, warnings) = (count("error") + count("fatal error") + count("panic"), count("warning"));
+        format!("{errors} errors, {warnings} warnings")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(output: &str) -> Vec<String> {
+        let lines: Vec<String> = output.lines().map(str::to_string).collect();
+        Quickfix::parse(&lines).iter().map(Entry::describe).collect()
+    }
+
+    #[test]
+    fn rustc_diagnostics_take_their_first_location() {
+        let output = "   Compiling demo v0.1.0 (/tmp/demo)
+error[E0425]: cannot find value `x` in this scope
+ --> src/main.rs:2:5
+  |
+2 |     x
+  |     ^ not found in this scope
+warning: unused variable: `y`
+  --> src/lib.rs:10:9
+   |
+10 |     let y = 1;
+   |         ^ help: if this is intentional, prefix it with an underscore: `_y`
+   |
+   = note: `#[warn(unused_variables)]` on by default
+error[E0308]: mismatched types
+ --> src/main.rs:7:18
+  |
+7 |     let a: u8 = \"é\";
+  |            --   ^^^ expected `u8`, found `&str`
+  |            |
+  |            expected due to this
+ --> src/other.rs:1:1
+error: could not compile `demo` (bin \"demo\") due to 2 previous errors";
+        assert_eq!(parse(output), [
+            "src/main.rs:2:5: error[E0425]: cannot find value `x` in this scope",
+            "src/lib.rs:10:9: warning: unused variable: `y`",
+            "src/main.rs:7:18: error[E0308]: mismatched types",
+        ]);
+    }
+
+    #[test]
+    fn gcc_style_lines_may_leave_out_the_column_or_severity() {
+        let output = "main.c:3:12: error: expected ';' before '}' token
+main.c:14: warning: implicit declaration of function 'foo'
+include/a b.h:1:1: fatal error: stdio2.h: No such file or directory
+C:\\src\\win.c:8:2: note: declared here
+lint.py:20: line too long
+make: *** [Makefile:4: all] Error 1
+    indented.c:1:1: error: not at the start
+compilation terminated.";
+        assert_eq!(parse(output), [
+            "main.c:3:12: error: expected ';' before '}' token",
+            "main.c:14: warning: implicit declaration of function 'foo'",
+            "include/a b.h:1:1: fatal error: stdio2.h: No such file or directory",
+            "C:\\src\\win.c:8:2: note: declared here",
+            "lint.py:20: error: line too long",
+        ]);
+    }
+
+    #[test]
+    fn panics_take_the_message_under_them() {
+        let output = "running 2 tests
+thread 'tests::adds' panicked at src/lib.rs:12:9:
+assertion `left == right` failed
+thread 'main' panicked at src/main.rs:4:5
+  index out of bounds: the len is 0 but the index is 0
+test result: FAILED. 1 passed; 1 failed";
+        assert_eq!(parse(output), [
+            "src/lib.rs:12:9: panic: assertion `left == right` failed",
+            "src/main.rs:4:5: panic: index out of bounds: the len is 0 but the index is 0",
+        ]);
+        let lines = vec!["thread 'main' panicked at src/main.rs:1:1:".to_string()];
+        assert_eq!(Quickfix::parse(&lines)[0].message, "panicked");
+    }
+
+    #[test]
+    fn entries_are_walked_in_order() {
+        let lines: Vec<String> = ["a.c:1: error: one", "a.c:2: warning: two", "a.c:3: fatal error: three"]
+            .iter()
+            .map(|line| line.to_string())
+            .collect();
+        let mut quickfix = Quickfix::default();
+        assert_eq!(quickfix.neighbour(false), Err("E42: No Errors".to_string()));
+        quickfix.set_entries(Quickfix::parse(&lines));
+        assert_eq!(quickfix.summary(), "2 errors, 1 warnings");
+        assert_eq!(quickfix.neighbour(true), Ok(0));
+        assert!(quickfix.select(2).is_ok());
+        assert_eq!(quickfix.neighbour(false), Err("E553: No more items".to_string()));
+        assert_eq!(quickfix.neighbour(true), Ok(1));
+        assert!(quickfix.select(3).is_err_and(|e| e == "E553: No more items"));
+        assert_eq!(quickfix.current, Some(2));
+    }
+
+    #[test]
+    fn entries_without_a_column_mark_the_whole_line() {
+        let lines = vec!["a.c:4: warning: w".to_string(), "a.c:2:3: note: n".to_string()];
+        let diagnostics: Vec<Diagnostic> = Quickfix::parse(&lines).iter().map(Entry::to_diagnostic).collect();
+        assert!(diagnostics[0].severity == Severity::Warning && diagnostics[0].source == "make");
+        assert!(diagnostics[0].graphemes_in(3) == Some((0, usize::MAX)));
+        assert!(diagnostics[1].severity == Severity::Information);
+        assert!(diagnostics[1].start == Location { grapheme_index: 2, line_index: 1 } && diagnostics[1].end == diagnostics[1].start);
+    }
+}
@@ -0,0 +1,249 @@
+use std::{
+    mem::discriminant,
+    ops::Range,
+    sync::mpsc::{channel, Receiver, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde_json::Value;
+
+use super::shell::Output;
+
+/// something for the editor to do later, or the result of a background worker
+pub enum Task {
+    /// clear the message in the command bar if it has been shown long enough
+    ClearMessage,
+    /// look for changes to the current file on disk
+    CheckDisk,
+    /// write unsaved changes of every buffer to its swap file
+    FlushSwap,
+    /// save buffers with unsaved changes that have autosave on
+    Autosave,
+    /// read lines appended to the files being followed
+    Follow,
+    /// open a view showing a diff
+    ShowDiff(Vec<String>),
+    /// replace lines of a view with what a shell command printed for them
+    Filtered {
+        view: usize,
+        /// version of the buffer the command got its input from
+        version: usize,
+        range: Range<usize>,
+        command: String,
+        output: Result<Output, String>,
+    },
+    /// a message from a language server, `None` once it has gone away
+    Language {
+        /// which server, servers started again get a new one
+        server: usize,
+        message: Option<Value>,
+    },
+    /// the files of the working directory, for the file finder
+    Listed(Vec<String>),
+    /// the build finished, with whether it succeeded and what it printed
+    Built {
+        command: String,
+        result: Result<(bool, Vec<String>), String>,
+    },
+}
+
+/// longest wait for input while workers are running, so that their results show up soon
+const WORKER_POLL: Duration = Duration::from_millis(50);
+
+/// longest wait for input without timers, the loop wakes up now and then anyway
+const IDLE_POLL: Duration = Duration::from_secs(60);
+
+/// what workers send to the editor's loop
+enum Post {
+    /// the result of a worker, which is done
+    Done(Task),
+    /// something from a listener, which keeps running
+    Message(Task),
+    /// a listener stopped
+    Closed,
+}
+
+/// handle of a worker that runs for long and posts tasks now and then,
+/// like the reader of a language server's output
+pub struct Listener(Sender<Post>);
+
+impl Listener {
+    /// returns false once the editor is gone
+    pub fn send(&self, task: Task) -> bool {
+        self.0.send(Post::Message(task)).is_ok()
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        let _ = self.0.send(Post::Closed);
+    }
+}
+
+/// timers and results of background workers, run by the editor between events
+pub struct Scheduler {
+    /// tasks to run once their time comes
+    timers: Vec<(Instant, Task)>,
+    sender: Sender<Post>,
+    receiver: Receiver<Post>,
+    /// workers and listeners that have not finished yet
+    running: usize,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        let (sender, receiver) = channel();
+        Self {
+            timers: Vec::new(),
+            sender,
+            receiver,
+            running: 0,
+        }
+    }
+}
+
+impl Scheduler {
+    /// run a task after given delay. a task of the same kind waiting already is put off instead
+    pub fn schedule(&mut self, delay: Duration, task: Task) {
+        self.schedule_at(Instant::now() + delay, task);
+    }
+    pub fn schedule_at(&mut self, at: Instant, task: Task) {
+        self.timers.retain(|(_, waiting)| discriminant(waiting) != discriminant(&task));
+        self.timers.push((at, task));
+    }
+    /// do some work on another thread, the task it returns is run once it's done
+    pub fn spawn(&mut self, work: impl FnOnce() -> Task + Send + 'static) {
+        let sender = self.sender.clone();
+        self.running += 1;
+        thread::spawn(move || {
+            let _ = sender.send(Post::Done(work()));
+        });
+    }
+    /// run a worker on another thread for as long as it likes, posting tasks through the listener
+    pub fn listen(&mut self, work: impl FnOnce(Listener) + Send + 'static) {
+        let listener = Listener(self.sender.clone());
+        self.running += 1;
+        thread::spawn(move || work(listener));
+    }
+    /// how long to wait for input before there is something to do
+    pub fn timeout(&self) -> Duration {
+        let now = Instant::now();
+        let mut timeout = self
+            .timers
+            .iter()
+            .map(|(at, _)| at.saturating_duration_since(now))
+            .min()
+            .unwrap_or(IDLE_POLL);
+        if self.running > 0 {
+            timeout = timeout.min(WORKER_POLL);
+        }
+        timeout
+    }
+    /// tasks whose time has come and results posted by workers, in that order
+    pub fn take_due(&mut self) -> Vec<Task> {
+        let now = Instant::now();
+        let (due, waiting) = std::mem::take(&mut self.timers).into_iter().partition(|(at, _)| *at <= now);
+        self.timers = waiting;
+        let mut tasks: Vec<Task> = due.into_iter().map(|(_, task)| task).collect();
+        for post in self.receiver.try_iter() {
+            match post {
+                Post::Done(task) => {
+                    self.running = self.running.saturating_sub(1);
+                    tasks.push(task);
+                }
+                Post::Message(task) => tasks.push(task),
+                Post::Closed => self.running = self.running.saturating_sub(1),
+            }
+        }
+        tasks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// tasks posted by workers, waiting for them to finish
+    fn wait_for_workers(scheduler: &mut Scheduler) -> Vec<Task> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut tasks = Vec::new();
+        while scheduler.running > 0 && Instant::now() < deadline {
+            thread::sleep(scheduler.timeout());
+            tasks.extend(scheduler.take_due());
+        }
+        tasks
+    }
+
+    #[test]
+    fn a_task_of_the_same_kind_replaces_the_waiting_one() {
+        let mut scheduler = Scheduler::default();
+        let past = Instant::now() - Duration::from_secs(1);
+        scheduler.schedule_at(past, Task::ClearMessage);
+        scheduler.schedule_at(past, Task::ShowDiff(vec!["old".to_string()]));
+        scheduler.schedule_at(past, Task::CheckDisk);
+        scheduler.schedule_at(past, Task::ShowDiff(vec!["new".to_string()]));
+        let tasks = scheduler.take_due();
+        assert_eq!(tasks.len(), 3);
+        assert!(matches!(tasks[0], Task::ClearMessage));
+        assert!(matches!(tasks[1], Task::CheckDisk));
+        assert!(matches!(&tasks[2], Task::ShowDiff(lines) if lines == &["new"]));
+        assert!(scheduler.take_due().is_empty());
+    }
+
+    #[test]
+    fn scheduling_again_puts_the_task_off() {
+        let mut scheduler = Scheduler::default();
+        scheduler.schedule(Duration::ZERO, Task::Follow);
+        scheduler.schedule(Duration::from_secs(30), Task::Follow);
+        assert!(scheduler.take_due().is_empty());
+        let timeout = scheduler.timeout();
+        assert!(timeout > Duration::from_secs(29) && timeout <= Duration::from_secs(30));
+
+        // a time given outright may be in the past already
+        scheduler.schedule_at(Instant::now(), Task::Follow);
+        assert!(matches!(scheduler.take_due()[..], [Task::Follow]));
+    }
+
+    #[test]
+    fn the_timeout_is_the_next_timer_or_a_poll() {
+        let mut scheduler = Scheduler::default();
+        assert_eq!(scheduler.timeout(), IDLE_POLL);
+        scheduler.schedule(Duration::from_secs(5), Task::Autosave);
+        scheduler.schedule(Duration::from_secs(2), Task::FlushSwap);
+        let timeout = scheduler.timeout();
+        assert!(timeout > Duration::from_secs(1) && timeout <= Duration::from_secs(2));
+        scheduler.schedule_at(Instant::now() - Duration::from_secs(1), Task::ClearMessage);
+        assert_eq!(scheduler.timeout(), Duration::ZERO);
+    }
+
+    #[test]
+    fn workers_deliver_their_results() {
+        let mut scheduler = Scheduler::default();
+        scheduler.spawn(|| Task::Listed(vec!["a.rs".to_string()]));
+        assert!(scheduler.timeout() <= WORKER_POLL);
+        let tasks = wait_for_workers(&mut scheduler);
+        assert!(matches!(&tasks[..], [Task::Listed(files)] if files == &["a.rs"]));
+        assert_eq!(scheduler.timeout(), IDLE_POLL);
+    }
+
+    #[test]
+    fn listeners_post_until_they_stop() {
+        let mut scheduler = Scheduler::default();
+        scheduler.listen(|listener| {
+            for server in 0..3 {
+                listener.send(Task::Language { server, message: None });
+            }
+        });
+        let tasks = wait_for_workers(&mut scheduler);
+        let servers: Vec<usize> = tasks
+            .iter()
+            .filter_map(|task| match task {
+                Task::Language { server, .. } => Some(*server),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(servers, [0, 1, 2]);
+        assert_eq!(scheduler.running, 0);
+    }
+}
@@ -0,0 +1,385 @@
+use std::collections::HashMap;
+
+use toml::{Table, Value};
+
+use super::{config::read_config, filetype};
+
+/// file in the config directory holding user settings
+const SETTINGS_FILE: &str = "settings.toml";
+
+/// pairs closed automatically unless a file type says otherwise
+const DEFAULT_PAIRS: &str = "()[]{}\"\"''";
+
+/// settings of file types that differ from the defaults, before the user's overrides
+const FILETYPE_DEFAULTS: [(&str, &str, &str); 3] = [
+    // `'` starts lifetimes in Rust and is an apostrophe in prose
+    ("rust", "auto_pairs", "()[]{}\"\""),
+    ("markdown", "auto_pairs", "()[]{}\"\""),
+    ("text", "auto_pairs", "()[]{}\"\""),
+];
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+const NAME: &str = env!("CARGO_PKG_NAME");
+
+/// editor options, changeable from `settings.toml` and by `:set`
+#[derive(Clone)]
+pub struct Settings {
+    /// columns between tab stops
+    pub tab_width: usize,
+    /// break long lines into several screen rows instead of scrolling horizontally
+    pub wrap: bool,
+    /// 1-based column to mark with a vertical guide. 0 means no ruler
+    pub ruler: usize,
+    /// lines moved by PageUp and PageDown. 0 means a screenful
+    pub scroll: usize,
+    /// shown in the middle of an empty buffer. empty means none
+    pub welcome_message: String,
+    /// drawn in the first column of a tab
+    pub tab_char: char,
+    /// drawn in place of whitespace other than spaces and tabs
+    pub whitespace_char: char,
+    /// drawn in place of control characters
+    pub control_char: char,
+    /// drawn in place of other zero-width graphemes
+    pub zero_width_char: char,
+    /// remove trailing whitespace from every line when saving
+    pub trim_trailing_whitespace: bool,
+    /// end the file with a line break when saving
+    pub final_newline: bool,
+    /// vi-like modal editing
+    pub vi: bool,
+    /// name of the color scheme
+    pub theme: String,
+    /// language of the file, picks its syntax highlighting
+    pub filetype: String,
+    /// pairs of characters closed automatically when the first one is typed, like `()[]`. empty means none
+    pub auto_pairs: String,
+    /// journal unsaved changes to a swap file next to the file, to recover them after a crash
+    pub swap_file: bool,
+    /// edits between writes of the swap file
+    pub swap_interval: usize,
+    /// seconds a message stays in the command bar. 0 means until the next one
+    pub message_timeout: usize,
+    /// seconds without input after which unsaved changes are saved. 0 means never
+    pub autosave: usize,
+    /// save unsaved changes when the terminal loses focus
+    pub autosave_on_focus_lost: bool,
+    /// refuse to change the buffer and to overwrite its file without `!`
+    pub read_only: bool,
+    /// less-like keys for reading: space and `b` page, `/` searches, `q` quits
+    pub pager: bool,
+    /// shell command `:make` runs to build the project
+    pub make_command: String,
+    /// shell command starting the language server for the file type, empty means none
+    pub language_server: String,
+    /// show the message of a line's worst problem after the line. otherwise it pops up
+    /// under the caret while the caret is on the problem
+    pub virtual_text: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            tab_width: 4,
+            wrap: false,
+            ruler: 0,
+            scroll: 0,
+            welcome_message: format!("{NAME} -- version {VERSION}"),
+            tab_char: ' ',
+            whitespace_char: '␣',
+            control_char: '▯',
+            zero_width_char: '·',
+            trim_trailing_whitespace: false,
+            final_newline: true,
+            vi: false,
+            theme: "default".to_string(),
+            filetype: "text".to_string(),
+            auto_pairs: DEFAULT_PAIRS.to_string(),
+            swap_file: true,
+            swap_interval: 20,
+            message_timeout: 5,
+            autosave: 0,
+            autosave_on_focus_lost: false,
+            read_only: false,
+            pager: false,
+            make_command: "cargo build".to_string(),
+            language_server: String::new(),
+            virtual_text: true,
+        }
+    }
+}
+
+impl Settings {
+    /// change the option of given name. `value` is the text after `=`, `None` for a bare name
+    pub fn set(&mut self, name: &str, value: Option<&str>) -> Result<(), String> {
+        match Self::canonical(name)? {
+            "tab_width" => self.tab_width = parse_number(name, value)?.max(1),
+            "wrap" => self.wrap = parse_bool(name, value)?,
+            "ruler" => self.ruler = parse_number(name, value)?,
+            "scroll" => self.scroll = parse_number(name, value)?,
+            "welcome_message" => self.welcome_message = value.unwrap_or_default().to_string(),
+            "tab_char" => self.tab_char = parse_char(name, value)?,
+            "whitespace_char" => self.whitespace_char = parse_char(name, value)?,
+            "control_char" => self.control_char = parse_char(name, value)?,
+            "zero_width_char" => self.zero_width_char = parse_char(name, value)?,
+            "trim_trailing_whitespace" => self.trim_trailing_whitespace = parse_bool(name, value)?,
+            "final_newline" => self.final_newline = parse_bool(name, value)?,
+            "vi" => self.vi = parse_bool(name, value)?,
+            "theme" => self.theme = parse_name(name, value)?,
+            "filetype" => self.filetype = parse_name(name, value)?,
+            "auto_pairs" => self.auto_pairs = parse_pairs(name, value)?,
+            "swap_file" => self.swap_file = parse_bool(name, value)?,
+            "swap_interval" => self.swap_interval = parse_number(name, value)?.max(1),
+            "message_timeout" => self.message_timeout = parse_number(name, value)?,
+            "autosave" => self.autosave = parse_number(name, value)?,
+            "autosave_on_focus_lost" => self.autosave_on_focus_lost = parse_bool(name, value)?,
+            "read_only" => self.read_only = parse_bool(name, value)?,
+            "pager" => self.pager = parse_bool(name, value)?,
+            "make_command" => self.make_command = parse_name(name, value)?,
+            "language_server" => self.language_server = value.unwrap_or_default().to_string(),
+            "virtual_text" => self.virtual_text = parse_bool(name, value)?,
+            _ => return Err(format!("E518: Unknown option: {name}")),
+        }
+        Ok(())
+    }
+    /// current value of the option of given name, as text
+    pub fn get(&self, name: &str) -> Result<String, String> {
+        let value = match Self::canonical(name)? {
+            "tab_width" => self.tab_width.to_string(),
+            "wrap" => self.wrap.to_string(),
+            "ruler" => self.ruler.to_string(),
+            "scroll" => self.scroll.to_string(),
+            "welcome_message" => self.welcome_message.clone(),
+            "tab_char" => self.tab_char.to_string(),
+            "whitespace_char" => self.whitespace_char.to_string(),
+            "control_char" => self.control_char.to_string(),
+            "zero_width_char" => self.zero_width_char.to_string(),
+            "trim_trailing_whitespace" => self.trim_trailing_whitespace.to_string(),
+            "final_newline" => self.final_newline.to_string(),
+            "vi" => self.vi.to_string(),
+            "theme" => self.theme.clone(),
+            "filetype" => self.filetype.clone(),
+            "auto_pairs" => self.auto_pairs.clone(),
+            "swap_file" => self.swap_file.to_string(),
+            "swap_interval" => self.swap_interval.to_string(),
+            "message_timeout" => self.message_timeout.to_string(),
+            "autosave" => self.autosave.to_string(),
+            "autosave_on_focus_lost" => self.autosave_on_focus_lost.to_string(),
+            "read_only" => self.read_only.to_string(),
+            "pager" => self.pager.to_string(),
+            "make_command" => self.make_command.clone(),
+            "language_server" => self.language_server.clone(),
+            "virtual_text" => self.virtual_text.to_string(),
+            _ => return Err(format!("E518: Unknown option: {name}")),
+        };
+        Ok(value)
+    }
+    /// the character closing given one, if typing it inserts a pair
+    pub fn closing_pair(&self, c: char) -> Option<char> {
+        self.pairs().find(|(open, _)| *open == c).map(|(_, close)| close)
+    }
+    /// the character opening a pair closed by given one
+    pub fn opening_pair(&self, c: char) -> Option<char> {
+        self.pairs().find(|(_, close)| *close == c).map(|(open, _)| open)
+    }
+    fn pairs(&self) -> impl Iterator<Item = (char, char)> + '_ {
+        let opens = self.auto_pairs.chars().step_by(2);
+        let closes = self.auto_pairs.chars().skip(1).step_by(2);
+        opens.zip(closes)
+    }
+    /// apply an argument of `:set`, like `wrap`, `nowrap`, `wrap!`, `tab_width=8` or `wrap?`.
+    /// returns a message to show, if any
+    pub fn apply(&mut self, option: &str) -> Result<Option<String>, String> {
+        if let Some((name, value)) = option.split_once('=') {
+            self.set(name, Some(value))?;
+        } else if let Some(name) = option.strip_suffix('?') {
+            return Ok(Some(format!("{name}={}", self.get(name)?)));
+        } else if let Some(name) = option.strip_suffix('!').or_else(|| option.strip_prefix("inv")) {
+            let value = self.get(name)? != "true";
+            self.set(name, Some(&value.to_string()))?;
+        } else if let Some(name) = option.strip_prefix("no").filter(|name| Self::canonical(name).is_ok()) {
+            self.set(name, Some("false"))?;
+        } else {
+            self.set(option, None)?;
+        }
+        Ok(None)
+    }
+    /// whether an argument of `:set` changes an option of the editor as a whole, like `theme`,
+    /// rather than one of the current file
+    pub fn is_global(option: &str) -> bool {
+        let name = option.split_once('=').map_or(option, |(name, _)| name);
+        let name = name.strip_suffix(['?', '!']).unwrap_or(name);
+        let name = name
+            .strip_prefix("inv")
+            .or_else(|| name.strip_prefix("no"))
+            .filter(|name| Self::canonical(name).is_ok())
+            .unwrap_or(name);
+        matches!(Self::canonical(name), Ok("vi" | "theme" | "pager" | "message_timeout"))
+    }
+    /// full name of an option, also accepting vi's names
+    fn canonical(name: &str) -> Result<&str, String> {
+        let name = match name {
+            "ts" | "tabstop" => "tab_width",
+            "cc" | "colorcolumn" => "ruler",
+            "scr" => "scroll",
+            "colorscheme" => "theme",
+            "ft" => "filetype",
+            "ap" => "auto_pairs",
+            "swf" | "swapfile" => "swap_file",
+            "uc" | "updatecount" => "swap_interval",
+            "ro" | "readonly" => "read_only",
+            "mp" | "makeprg" => "make_command",
+            "lsp" => "language_server",
+            "vt" => "virtual_text",
+            _ => name,
+        };
+        match name {
+            "tab_width" | "wrap" | "ruler" | "scroll" | "welcome_message" | "tab_char"
+            | "whitespace_char" | "control_char" | "zero_width_char"
+            | "trim_trailing_whitespace" | "final_newline" | "vi" | "theme" | "filetype"
+            | "auto_pairs" | "swap_file" | "swap_interval" | "message_timeout" | "autosave"
+            | "autosave_on_focus_lost" | "read_only" | "pager" | "make_command"
+            | "language_server" | "virtual_text" => Ok(name),
+            _ => Err(format!("E518: Unknown option: {name}")),
+        }
+    }
+}
+
+fn parse_number(name: &str, value: Option<&str>) -> Result<usize, String> {
+    value
+        .and_then(|value| value.trim().parse().ok())
+        .ok_or_else(|| format!("E521: Number required after =: {name}"))
+}
+
+fn parse_bool(name: &str, value: Option<&str>) -> Result<bool, String> {
+    match value.map(str::trim) {
+        None | Some("true") => Ok(true),
+        Some("false") => Ok(false),
+        Some(value) => Err(format!("E474: Invalid argument: {name}={value}")),
+    }
+}
+
+fn parse_name(name: &str, value: Option<&str>) -> Result<String, String> {
+    match value.map(str::trim) {
+        Some(value) if !value.is_empty() => Ok(value.to_string()),
+        _ => Err(format!("E474: Invalid argument: {name} needs a name")),
+    }
+}
+
+/// pairs are written one after another, like `()[]""`.
+/// a bare `auto_pairs` brings back the default ones, `noauto_pairs` turns them off
+fn parse_pairs(name: &str, value: Option<&str>) -> Result<String, String> {
+    match value {
+        None | Some("true") => Ok(DEFAULT_PAIRS.to_string()),
+        Some("false") => Ok(String::new()),
+        Some(pairs) if pairs.chars().count() % 2 == 0 => Ok(pairs.to_string()),
+        Some(pairs) => Err(format!("E474: Invalid argument: {name}={pairs}")),
+    }
+}
+
+fn parse_char(name: &str, value: Option<&str>) -> Result<char, String> {
+    let mut chars = value.unwrap_or_default().chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(format!("E474: Invalid argument: {name} needs a single character")),
+    }
+}
+
+/// settings loaded from `settings.toml`: global options at the top level,
+/// overridden per file type in `[filetype.<name>]` tables, for example:
+///
+/// ```toml
+/// tab_width = 4
+///
+/// [filetype.rust]
+/// ruler = 100
+///
+/// [filetype.markdown]
+/// wrap = true
+/// ```
+#[derive(Default)]
+pub struct SettingsFile {
+    pub global: Settings,
+    /// options and their values by file type
+    filetypes: HashMap<String, Vec<(String, String)>>,
+}
+
+impl SettingsFile {
+    /// load `settings.toml` if it exists. returns problems found in it along with the settings
+    pub fn load() -> (Self, Vec<String>) {
+        let mut settings = Self::default();
+        let errors = match read_config(SETTINGS_FILE) {
+            None => Vec::new(),
+            Some(Err(e)) => vec![format!("{SETTINGS_FILE}: {e}")],
+            Some(Ok(contents)) => settings.apply(&contents),
+        };
+        (settings, errors)
+    }
+    /// settings for a file, with overrides of its file type applied
+    pub fn for_file(&self, path: Option<&str>) -> Settings {
+        let mut settings = self.global.clone();
+        let filetype = path.map_or("text", filetype::detect);
+        settings.filetype = filetype.to_string();
+        for (_, name, value) in FILETYPE_DEFAULTS.iter().filter(|(name, ..)| *name == filetype) {
+            // unless the user changed the option for all files
+            if settings.get(name) == Settings::default().get(name) {
+                let _ = settings.set(name, Some(value));
+            }
+        }
+        for (name, value) in self.filetypes.get(filetype).into_iter().flatten() {
+            // overrides were validated when loading
+            let _ = settings.set(name, Some(value));
+        }
+        settings
+    }
+    fn apply(&mut self, contents: &str) -> Vec<String> {
+        let table = match contents.parse::<Table>() {
+            Ok(table) => table,
+            Err(e) => return vec![format!("{SETTINGS_FILE}: {}", e.message())],
+        };
+        let mut errors = Vec::new();
+        for (name, value) in table {
+            if name == "filetype" {
+                let Value::Table(filetypes) = value else {
+                    errors.push(format!("{SETTINGS_FILE}: filetype must be a table"));
+                    continue;
+                };
+                for (filetype, options) in filetypes {
+                    self.apply_filetype(&filetype, options, &mut errors);
+                }
+                continue;
+            }
+            let result = to_text(&name, &value).and_then(|value| self.global.set(&name, Some(&value)));
+            if let Err(e) = result {
+                errors.push(format!("{SETTINGS_FILE}: {e}"));
+            }
+        }
+        errors
+    }
+    fn apply_filetype(&mut self, filetype: &str, options: Value, errors: &mut Vec<String>) {
+        let Value::Table(options) = options else {
+            errors.push(format!("{SETTINGS_FILE}: filetype.{filetype} must be a table"));
+            return;
+        };
+        let overrides = self.filetypes.entry(filetype.to_string()).or_default();
+        for (name, value) in options {
+            // validate on a scratch copy, so that only good overrides are kept
+            let result = to_text(&name, &value)
+                .and_then(|text| Settings::default().set(&name, Some(&text)).map(|()| text));
+            match result {
+                Ok(text) => overrides.push((name, text)),
+                Err(e) => errors.push(format!("{SETTINGS_FILE}: [filetype.{filetype}] {e}")),
+            }
+        }
+    }
+}
+
+/// values of options are handled as text, the same way as typed after `:set name=`
+fn to_text(name: &str, value: &Value) -> Result<String, String> {
+    match value {
+        Value::String(text) => Ok(text.clone()),
+        Value::Integer(number) => Ok(number.to_string()),
+        Value::Boolean(flag) => Ok(flag.to_string()),
+        _ => Err(format!("unsupported value for {name}")),
+    }
+}
@@ -0,0 +1,83 @@
+use std::{
+    io::{ErrorKind, Write},
+    process::{Command, Stdio},
+    thread,
+};
+
+/// what a command printed
+pub struct Output {
+    pub lines: Vec<String>,
+    /// lines written to stderr, joined by `; `. commands that succeed may still warn
+    pub stderr: String,
+}
+
+/// run a shell command with given lines as its input.
+/// a command that fails gives what it wrote to stderr and its exit status
+pub fn filter(command: &str, lines: Vec<String>) -> Result<Output, String> {
+    let mut child = shell(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("E282: Cannot run \"{command}\": {e}"))?;
+    // writing on another thread, a command printing a lot before reading all of its input
+    // would wait for us to read while we wait for it to read
+    let mut stdin = child.stdin.take();
+    let writer = thread::spawn(move || {
+        let Some(stdin) = stdin.as_mut() else {
+            return Ok(());
+        };
+        for line in lines {
+            stdin.write_all(line.as_bytes())?;
+            stdin.write_all(b"\n")?;
+        }
+        Ok(())
+    });
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("E282: Cannot run \"{command}\": {e}"))?;
+    let written: std::io::Result<()> = writer.join().unwrap_or(Ok(()));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let stderr = stderr.lines().map(str::trim).filter(|line| !line.is_empty()).collect::<Vec<_>>().join("; ");
+    if !output.status.success() {
+        let status = output.status.code().map_or("was killed".to_string(), |code| format!("returned {code}"));
+        return Err(if stderr.is_empty() {
+            format!("shell {status}, text left unchanged")
+        } else {
+            format!("shell {status}: {stderr}")
+        });
+    }
+    // commands that do not read their input, like `date`, close it early
+    if let Err(e) = written {
+        if e.kind() != ErrorKind::BrokenPipe {
+            return Err(format!("E482: Cannot write to \"{command}\": {e}"));
+        }
+    }
+    let lines = String::from_utf8_lossy(&output.stdout).lines().map(str::to_string).collect();
+    Ok(Output { lines, stderr })
+}
+
+/// run a shell command without input, like a build. returns whether it succeeded and the lines
+/// it printed, with stdout and stderr together in the order they were written
+pub fn run(command: &str) -> Result<(bool, Vec<String>), String> {
+    let output = shell(&format!("({command}) 2>&1"))
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("E282: Cannot run \"{command}\": {e}"))?;
+    let lines = String::from_utf8_lossy(&output.stdout).lines().map(str::to_string).collect();
+    Ok((output.status.success(), lines))
+}
+
+#[cfg(windows)]
+pub fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.args(["/C", command]);
+    shell
+}
+
+#[cfg(not(windows))]
+pub fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.args(["-c", command]);
+    shell
+}
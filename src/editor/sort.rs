@@ -0,0 +1,231 @@
+use std::{cmp::Ordering, collections::HashSet};
+
+/// how two lines compare
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Order {
+    /// by their characters
+    Lexicographic,
+    /// by the first number in them. lines without one come first
+    Numeric,
+    /// by their characters, but runs of digits by their value, so that `a2` goes before `a10`
+    Natural,
+}
+
+/// arguments of `:sort`, like `:sort! iu k=2 t=,`
+pub struct Sort {
+    order: Order,
+    ignore_case: bool,
+    /// keep only the first of lines that compare equal
+    unique: bool,
+    /// largest first
+    reverse: bool,
+    /// 1-based field to compare instead of the whole line
+    field: Option<usize>,
+    /// text between fields. fields are separated by whitespace without one
+    delimiter: Option<String>,
+}
+
+impl Sort {
+    /// flags are `i` to ignore case, `n` for numeric and `v` for natural order, and `u` to remove
+    /// duplicates. `k=N` compares the N-th field, `t=D` splits fields at D. `!` reverses the order
+    pub fn parse(args: &str, reverse: bool) -> Result<Self, String> {
+        let mut ret = Self {
+            order: Order::Lexicographic,
+            ignore_case: false,
+            unique: false,
+            reverse,
+            field: None,
+            delimiter: None,
+        };
+        let invalid = || format!("E474: Invalid argument: {args}");
+        for word in args.split_whitespace() {
+            if let Some(field) = word.strip_prefix("k=") {
+                ret.field = Some(field.parse().ok().filter(|field| *field > 0).ok_or_else(invalid)?);
+                continue;
+            }
+            if let Some(delimiter) = word.strip_prefix("t=") {
+                // `\t` for tabs, which are hard to type into the command line
+                let delimiter = if delimiter == "\\t" { "\t" } else { delimiter };
+                ret.delimiter = Some(delimiter.to_string()).filter(|delimiter| !delimiter.is_empty());
+                continue;
+            }
+            for flag in word.chars() {
+                let order = match flag {
+                    'i' => {
+                        ret.ignore_case = true;
+                        continue;
+                    }
+                    'u' => {
+                        ret.unique = true;
+                        continue;
+                    }
+                    'n' => Order::Numeric,
+                    'v' => Order::Natural,
+                    _ => return Err(invalid()),
+                };
+                if ret.order != Order::Lexicographic && ret.order != order {
+                    return Err(invalid());
+                }
+                ret.order = order;
+            }
+        }
+        Ok(ret)
+    }
+    /// sort lines in place. lines that compare equal keep their order
+    pub fn apply(&self, lines: &mut Vec<String>) {
+        if self.reverse {
+            lines.sort_by(|a, b| self.compare(b, a));
+        } else {
+            lines.sort_by(|a, b| self.compare(a, b));
+        }
+        if self.unique {
+            lines.dedup_by(|later, first| self.compare(first, later) == Ordering::Equal);
+        }
+    }
+    fn compare(&self, a: &str, b: &str) -> Ordering {
+        let (a, b) = (self.key(a), self.key(b));
+        match self.order {
+            Order::Lexicographic if self.ignore_case => a.to_lowercase().cmp(&b.to_lowercase()),
+            Order::Lexicographic => a.cmp(b),
+            Order::Numeric => match (number(a), number(b)) {
+                (Some(a), Some(b)) => a.total_cmp(&b),
+                (a, b) => a.is_some().cmp(&b.is_some()),
+            },
+            Order::Natural => natural(a, b, self.ignore_case),
+        }
+    }
+    /// the part of the line compared, the whole line unless a field is given
+    fn key<'a>(&self, line: &'a str) -> &'a str {
+        let Some(field) = self.field else {
+            return line;
+        };
+        let field = match &self.delimiter {
+            Some(delimiter) => line.split(delimiter.as_str()).nth(field - 1),
+            None => line.split_whitespace().nth(field - 1),
+        };
+        field.unwrap_or_default()
+    }
+}
+
+/// remove lines seen before, keeping the first of each
+pub fn dedupe(lines: &mut Vec<String>, ignore_case: bool) {
+    let mut seen = HashSet::new();
+    lines.retain(|line| seen.insert(if ignore_case { line.to_lowercase() } else { line.clone() }));
+}
+
+/// the first number in the text, with its sign and fraction
+fn number(text: &str) -> Option<f64> {
+    let start = text.find(|c: char| c.is_ascii_digit())?;
+    let start = if text[..start].ends_with('-') { start - 1 } else { start };
+    let mut end = start + 1;
+    let mut fraction = false;
+    let rest = &text[end..];
+    for (index, c) in rest.char_indices() {
+        let after = index + c.len_utf8();
+        let next_is_digit = rest[after..].starts_with(|c: char| c.is_ascii_digit());
+        if c.is_ascii_digit() || (c == '.' && !fraction && next_is_digit) {
+            fraction |= c == '.';
+            end = start + 1 + after;
+        } else {
+            break;
+        }
+    }
+    text[start..end].parse().ok()
+}
+
+/// compare runs of digits by their value and the text between them by its characters
+fn natural(a: &str, b: &str, ignore_case: bool) -> Ordering {
+    let (mut a, mut b) = (chunks(a), chunks(b));
+    loop {
+        let ordering = match (a.next(), b.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a), Some(b)) if is_digits(a) && is_digits(b) => {
+                let (a, b) = (a.trim_start_matches('0'), b.trim_start_matches('0'));
+                a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+            }
+            (Some(a), Some(b)) if ignore_case => a.to_lowercase().cmp(&b.to_lowercase()),
+            (Some(a), Some(b)) => a.cmp(b),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+/// the text split into runs of digits and runs of other characters
+fn chunks(text: &str) -> impl Iterator<Item = &str> {
+    let mut rest = text;
+    std::iter::from_fn(move || {
+        let digits = rest.starts_with(|c: char| c.is_ascii_digit());
+        let len = rest.find(|c: char| c.is_ascii_digit() != digits).unwrap_or(rest.len());
+        let (chunk, after) = rest.split_at(len);
+        rest = after;
+        (!chunk.is_empty()).then_some(chunk)
+    })
+}
+
+fn is_digits(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(args: &str, reverse: bool, lines: &[&str]) -> Vec<String> {
+        let mut lines = lines.iter().map(|line| line.to_string()).collect();
+        Sort::parse(args, reverse).unwrap().apply(&mut lines);
+        lines
+    }
+
+    #[test]
+    fn number_takes_the_first_one_with_sign_and_fraction() {
+        assert_eq!(number("x -12.5 y"), Some(-12.5));
+        assert_eq!(number("v1.2.3"), Some(1.2));
+        assert_eq!(number("10."), Some(10.0));
+        assert_eq!(number("no digits"), None);
+    }
+
+    #[test]
+    fn number_stops_before_multibyte_characters() {
+        assert_eq!(number("5°C"), Some(5.0));
+        assert_eq!(number("température 21.5°"), Some(21.5));
+        assert_eq!(number("1😀2"), Some(1.0));
+        assert_eq!(number("3.é"), Some(3.0));
+    }
+
+    #[test]
+    fn numeric_sort_puts_lines_without_numbers_first() {
+        assert_eq!(sorted("n", false, &["10°C", "x", "-3°C", "5°C"]), ["x", "-3°C", "5°C", "10°C"]);
+    }
+
+    #[test]
+    fn natural_order_compares_digit_runs_by_value() {
+        assert_eq!(natural("a2", "a10", false), Ordering::Less);
+        assert_eq!(natural("a010", "a9", false), Ordering::Greater);
+        assert_eq!(natural("A2", "a2", true), Ordering::Equal);
+        assert_eq!(natural("été2", "été10", false), Ordering::Less);
+        assert_eq!(sorted("v", false, &["file10", "file2", "file1"]), ["file1", "file2", "file10"]);
+    }
+
+    #[test]
+    fn parse_reads_flags_fields_and_delimiters() {
+        let sort = Sort::parse("iu k=2 t=\\t", true).unwrap();
+        assert!(sort.ignore_case && sort.unique && sort.reverse);
+        assert_eq!(sort.field, Some(2));
+        assert_eq!(sort.delimiter.as_deref(), Some("\t"));
+        assert!(Sort::parse("nv", false).is_err());
+        assert!(Sort::parse("k=0", false).is_err());
+        assert!(Sort::parse("x", false).is_err());
+        assert!(Sort::parse("é", false).is_err());
+    }
+
+    #[test]
+    fn sorts_by_field_and_removes_duplicates() {
+        assert_eq!(sorted("k=2 t=;", false, &["a;ü", "b;a", "c;ä"]), ["b;a", "c;ä", "a;ü"]);
+        assert_eq!(sorted("iu", false, &["b", "A", "a", "B"]), ["A", "b"]);
+        assert_eq!(sorted("", true, &["a", "c", "b"]), ["c", "b", "a"]);
+    }
+}
@@ -0,0 +1,61 @@
+use super::{terminal::Terminal, theme::Theme};
+
+/// information about the document shown in the status bar
+#[derive(Default)]
+pub struct DocumentStatus {
+    pub file_name: Option<String>,
+    pub total_lines: usize,
+    /// 0-based index of the line the caret is on
+    pub current_line: usize,
+    pub dirty: bool,
+    pub read_only: bool,
+    /// set while following the file as it grows, true while the screen sticks to its end
+    pub follow: Option<bool>,
+    /// problems the language server found
+    pub errors: usize,
+    pub warnings: usize,
+}
+
+/// the row above the command bar, describing the current document
+#[derive(Default)]
+pub struct StatusBar {
+    row: usize,
+    width: usize,
+}
+
+impl StatusBar {
+    /// place the bar at given row of the screen
+    pub fn resize(&mut self, row: usize, width: usize) {
+        self.row = row;
+        self.width = width;
+    }
+    /// editing mode, file name and modification on the left, caret line on the right
+    pub fn render(&self, status: &DocumentStatus, mode: Option<&str>, theme: &Theme) {
+        let mode = mode.map(|mode| format!("{mode} | ")).unwrap_or_default();
+        let name = status.file_name.as_deref().unwrap_or("[No Name]");
+        let modified = if status.dirty { " [+]" } else { "" };
+        let read_only = if status.read_only { " [RO]" } else { "" };
+        let follow = match status.follow {
+            Some(true) => " [follow]",
+            Some(false) => " [follow paused]",
+            None => "",
+        };
+        let mut problems = String::new();
+        if status.errors > 0 {
+            problems.push_str(&format!(" E:{}", status.errors));
+        }
+        if status.warnings > 0 {
+            problems.push_str(&format!(" W:{}", status.warnings));
+        }
+        let left = format!("{mode}{name}{modified}{read_only}{follow} - {} lines{problems}", status.total_lines);
+        let right = format!("{}/{}", status.current_line + 1, status.total_lines.max(1));
+
+        let padding = self.width.saturating_sub(left.chars().count() + right.chars().count());
+        let mut text = format!("{left}{}{right}", " ".repeat(padding));
+        if let Some((index, _)) = text.char_indices().nth(self.width) {
+            text.truncate(index);
+        }
+        let ret = Terminal::print_styled_row(self.row, &text, theme.element("status_bar"));
+        debug_assert!(ret.is_ok(), "Failed to render status bar!");
+    }
+}
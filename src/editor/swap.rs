@@ -0,0 +1,237 @@
+use std::{fs, io::ErrorKind, path::{Path, PathBuf}, process};
+
+use super::{diff, line::Line};
+
+/// first line of every swap file
+const MAGIC: &str = concat!(env!("CARGO_PKG_NAME"), " swap");
+
+/// what was found next to a file about to be edited
+pub enum Found {
+    /// no swap file, or one without unsaved changes
+    Nothing,
+    /// another running instance is editing the file, with its process id
+    Live(u32),
+    /// unsaved changes left behind by an instance that is gone, described by how they differ from the file
+    Stale(String),
+}
+
+/// contents of a swap file
+struct Journal {
+    pid: u32,
+    /// unsaved lines of the buffer, `None` if it was saved
+    lines: Option<Vec<String>>,
+}
+
+/// journal of a buffer's unsaved changes, kept as `.<name>.hswp` next to its file
+pub struct Swap {
+    path: PathBuf,
+    /// version of the buffer last written
+    version: usize,
+}
+
+impl Swap {
+    /// look for a swap file left for given file. `lines` are the file's own contents
+    pub fn find(file: &str, lines: &[Line]) -> Result<Found, String> {
+        let Some(journal) = read(&path_for(file))? else {
+            return Ok(Found::Nothing);
+        };
+        if is_alive(journal.pid) {
+            return Ok(Found::Live(journal.pid));
+        }
+        let found = match journal.lines {
+            Some(swapped) => {
+                let lines: Vec<String> = lines.iter().map(Line::to_string).collect();
+                if swapped == lines {
+                    Found::Nothing
+                } else {
+                    Found::Stale(diff::summary(&diff::diff(&lines, &swapped)))
+                }
+            }
+            None => Found::Nothing,
+        };
+        Ok(found)
+    }
+    /// the unsaved lines kept in the swap file of given file
+    pub fn recover(file: &str) -> Result<Vec<String>, String> {
+        read(&path_for(file))?
+            .and_then(|journal| journal.lines)
+            .ok_or_else(|| "E305: No swap file found".to_string())
+    }
+    /// start journaling given file, replacing any swap file left for it.
+    /// `version` is that of the buffer, which matches the file
+    pub fn create(file: &str, version: usize) -> Result<Self, String> {
+        let mut swap = Self { path: path_for(file), version };
+        swap.write(&[], version, false)?;
+        Ok(swap)
+    }
+    /// write the buffer if it changed at least `interval` times since last written
+    pub fn update(&mut self, lines: &[Line], version: usize, interval: usize) -> Result<(), String> {
+        if version.abs_diff(self.version) < interval {
+            return Ok(());
+        }
+        self.write(lines, version, true)
+    }
+    /// write the buffer, or just who is editing the file if there is nothing unsaved
+    pub fn write(&mut self, lines: &[Line], version: usize, dirty: bool) -> Result<(), String> {
+        let mut contents = format!("{MAGIC}\npid {}\n", process::id());
+        if dirty {
+            contents.push_str("unsaved\n");
+            for line in lines {
+                contents.push_str(&line.to_string());
+                contents.push('\n');
+            }
+        } else {
+            contents.push_str("saved\n");
+        }
+        fs::write(&self.path, contents).map_err(|e| format!("E297: Write error in swap file: {e}"))?;
+        self.version = version;
+        Ok(())
+    }
+    /// stop journaling, the changes are saved or given up
+    pub fn remove(self) {
+        let _ = fs::remove_file(self.path);
+    }
+}
+
+/// `dir/.name.hswp` for `dir/name`
+fn path_for(file: &str) -> PathBuf {
+    let path = Path::new(file);
+    let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    path.with_file_name(format!(".{name}.hswp"))
+}
+
+fn read(path: &Path) -> Result<Option<Journal>, String> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("{}: {e}", path.display())),
+    };
+    let bad_file = || format!("{} is not a swap file of {MAGIC}", path.display());
+    let mut lines = contents.lines();
+    if lines.next() != Some(MAGIC) {
+        return Err(bad_file());
+    }
+    let pid = lines
+        .next()
+        .and_then(|line| line.strip_prefix("pid "))
+        .and_then(|pid| pid.parse().ok())
+        .ok_or_else(bad_file)?;
+    let lines = match lines.next() {
+        Some("unsaved") => Some(lines.map(str::to_string).collect()),
+        Some("saved") => None,
+        _ => return Err(bad_file()),
+    };
+    Ok(Some(Journal { pid, lines }))
+}
+
+/// whether another process of given id runs. processes are looked up in `/proc`,
+/// so elsewhere every swap file is taken as left behind
+fn is_alive(pid: u32) -> bool {
+    pid != process::id() && Path::new(&format!("/proc/{pid}")).exists()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    /// a file that doesn't exist in a directory of its own, removed with what's in it when dropped
+    struct Scratch(PathBuf);
+
+    impl Scratch {
+        fn new(name: &str) -> Self {
+            let dir = env::temp_dir().join(format!("my-hecto-swap-{name}-{}", process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+        fn file(&self) -> String {
+            self.0.join("notes.txt").to_string_lossy().to_string()
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn lines(texts: &[&str]) -> Vec<Line> {
+        texts.iter().map(|text| Line::from(text)).collect()
+    }
+
+    fn find(file: &str, texts: &[&str]) -> Result<String, String> {
+        Ok(match Swap::find(file, &lines(texts))? {
+            Found::Nothing => "nothing".to_string(),
+            Found::Live(pid) => format!("live {pid}"),
+            Found::Stale(summary) => summary,
+        })
+    }
+
+    #[test]
+    fn swap_files_sit_next_to_their_file() {
+        assert_eq!(path_for("dir/name.rs"), Path::new("dir/.name.rs.hswp"));
+        assert_eq!(path_for("name"), Path::new(".name.hswp"));
+    }
+
+    #[test]
+    fn unsaved_changes_left_behind_are_recovered() {
+        let scratch = Scratch::new("recover");
+        let file = scratch.file();
+        let mut swap = Swap::create(&file, 1).unwrap();
+        assert_eq!(find(&file, &["a"]), Ok("nothing".to_string()));
+        assert_eq!(Swap::recover(&file), Err("E305: No swap file found".to_string()));
+
+        swap.write(&lines(&["a", "new", "", "b"]), 2, true).unwrap();
+        // written by this process, which is as good as gone to itself
+        assert_eq!(find(&file, &["a", "b"]), Ok("+2 -0 lines from line 2".to_string()));
+        assert_eq!(Swap::recover(&file), Ok(vec!["a".to_string(), "new".to_string(), String::new(), "b".to_string()]));
+        // the file has the changes already
+        assert_eq!(find(&file, &["a", "new", "", "b"]), Ok("nothing".to_string()));
+
+        swap.remove();
+        assert_eq!(find(&file, &["a"]), Ok("nothing".to_string()));
+        assert!(!path_for(&file).exists());
+    }
+
+    #[test]
+    fn updates_wait_for_enough_changes() {
+        let scratch = Scratch::new("update");
+        let file = scratch.file();
+        let mut swap = Swap::create(&file, 10).unwrap();
+        swap.update(&lines(&["x"]), 12, 3).unwrap();
+        assert_eq!(find(&file, &[]), Ok("nothing".to_string()));
+        swap.update(&lines(&["x"]), 13, 3).unwrap();
+        assert_eq!(find(&file, &[]), Ok("+1 -0 lines from line 1".to_string()));
+        // counted from the last write
+        swap.update(&lines(&["y"]), 14, 3).unwrap();
+        assert_eq!(Swap::recover(&file), Ok(vec!["x".to_string()]));
+        // an undo goes back in version, which counts as changing too
+        swap.update(&lines(&["y"]), 10, 3).unwrap();
+        assert_eq!(Swap::recover(&file), Ok(vec!["y".to_string()]));
+    }
+
+    #[test]
+    fn files_edited_by_a_running_instance_are_told() {
+        // pid 1 runs wherever `/proc` exists
+        if !Path::new("/proc/1").exists() {
+            return;
+        }
+        let scratch = Scratch::new("live");
+        let file = scratch.file();
+        fs::write(path_for(&file), format!("{MAGIC}\npid 1\nunsaved\nx\n")).unwrap();
+        assert_eq!(find(&file, &[]), Ok("live 1".to_string()));
+    }
+
+    #[test]
+    fn other_files_are_not_taken_for_swap_files() {
+        let scratch = Scratch::new("bad");
+        let file = scratch.file();
+        let swap = path_for(&file);
+        for contents in ["", "hello\n", &format!("{MAGIC}\npid x\nsaved\n"), &format!("{MAGIC}\npid 7\nmaybe\n")] {
+            fs::write(&swap, contents).unwrap();
+            assert!(find(&file, &[]).is_err_and(|e| e.ends_with(&format!("is not a swap file of {MAGIC}"))));
+        }
+    }
+}
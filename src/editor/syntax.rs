@@ -0,0 +1,462 @@
+use std::ops::Range;
+
+use super::{highlight::Highlight, line::Line};
+
+/// what a line starts inside of, carried over from the end of the line above
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum State {
+    #[default]
+    Code,
+    BlockComment,
+    /// a string opened by given quote
+    String(char),
+}
+
+/// how a language writes comments, strings and words of special meaning
+struct Rules {
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [char],
+    /// `'` starts a character literal only when closed right away, so that Rust lifetimes are left alone
+    char_literals: bool,
+    keywords: &'static [&'static str],
+    types: &'static [&'static str],
+    constants: &'static [&'static str],
+    /// identifiers starting with an uppercase letter are types
+    capitalized_types: bool,
+}
+
+const C_LIKE: Rules = Rules {
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\''],
+    char_literals: false,
+    keywords: &[],
+    types: &[],
+    constants: &["true", "false"],
+    capitalized_types: false,
+};
+
+const RUST: Rules = Rules {
+    quotes: &['"'],
+    char_literals: true,
+    keywords: &[
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern", "fn",
+        "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return",
+        "self", "Self", "static", "struct", "super", "trait", "type", "unsafe", "use", "where", "while",
+    ],
+    types: &[
+        "bool", "char", "str", "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32", "i64",
+        "i128", "isize", "f32", "f64",
+    ],
+    constants: &["true", "false", "None", "Some", "Ok", "Err"],
+    capitalized_types: true,
+    ..C_LIKE
+};
+
+const C: Rules = Rules {
+    keywords: &[
+        "break", "case", "class", "const", "continue", "default", "delete", "do", "else", "enum", "extern",
+        "for", "goto", "if", "inline", "namespace", "new", "private", "protected", "public", "return",
+        "sizeof", "static", "struct", "switch", "template", "typedef", "union", "using", "virtual",
+        "volatile", "while", "#include", "#define", "#ifdef", "#ifndef", "#endif", "#if", "#else",
+    ],
+    types: &[
+        "auto", "bool", "char", "double", "float", "int", "long", "short", "signed", "unsigned", "void",
+        "size_t",
+    ],
+    constants: &["true", "false", "NULL", "nullptr"],
+    ..C_LIKE
+};
+
+const GO: Rules = Rules {
+    quotes: &['"', '\'', '`'],
+    keywords: &[
+        "break", "case", "chan", "const", "continue", "default", "defer", "else", "fallthrough", "for",
+        "func", "go", "goto", "if", "import", "interface", "map", "package", "range", "return", "select",
+        "struct", "switch", "type", "var",
+    ],
+    types: &[
+        "bool", "byte", "error", "float32", "float64", "int", "int8", "int16", "int32", "int64", "rune",
+        "string", "uint", "uint8", "uint16", "uint32", "uint64", "uintptr",
+    ],
+    constants: &["true", "false", "nil", "iota"],
+    ..C_LIKE
+};
+
+const JAVA: Rules = Rules {
+    keywords: &[
+        "abstract", "break", "case", "catch", "class", "continue", "default", "do", "else", "enum",
+        "extends", "final", "finally", "for", "if", "implements", "import", "instanceof", "interface", "new",
+        "package", "private", "protected", "public", "return", "static", "super", "switch", "this",
+        "throw", "throws", "try", "var", "void", "while",
+    ],
+    types: &["boolean", "byte", "char", "double", "float", "int", "long", "short"],
+    constants: &["true", "false", "null"],
+    capitalized_types: true,
+    ..C_LIKE
+};
+
+const JAVASCRIPT: Rules = Rules {
+    quotes: &['"', '\'', '`'],
+    keywords: &[
+        "async", "await", "break", "case", "catch", "class", "const", "continue", "default", "delete", "do",
+        "else", "export", "extends", "finally", "for", "from", "function", "if", "import", "in",
+        "instanceof", "interface", "let", "new", "of", "return", "static", "switch", "this", "throw",
+        "try", "type", "typeof", "var", "void", "while", "yield",
+    ],
+    types: &["any", "boolean", "never", "number", "string", "unknown"],
+    constants: &["true", "false", "null", "undefined", "NaN"],
+    capitalized_types: true,
+    ..C_LIKE
+};
+
+const PYTHON: Rules = Rules {
+    line_comments: &["#"],
+    block_comment: None,
+    quotes: &['"', '\''],
+    char_literals: false,
+    keywords: &[
+        "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del", "elif", "else",
+        "except", "finally", "for", "from", "global", "if", "import", "in", "is", "lambda", "nonlocal",
+        "not", "or", "pass", "raise", "return", "try", "while", "with", "yield", "self",
+    ],
+    types: &["bool", "bytes", "dict", "float", "int", "list", "set", "str", "tuple"],
+    constants: &["True", "False", "None"],
+    capitalized_types: true,
+};
+
+const SHELL: Rules = Rules {
+    keywords: &[
+        "case", "do", "done", "elif", "else", "esac", "export", "fi", "for", "function", "if", "in",
+        "local", "return", "then", "until", "while",
+    ],
+    types: &[],
+    constants: &[],
+    capitalized_types: false,
+    ..PYTHON
+};
+
+/// for configuration formats: comments, strings and constants only
+const CONFIG: Rules = Rules {
+    keywords: &[],
+    types: &[],
+    constants: &["true", "false", "null"],
+    capitalized_types: false,
+    ..PYTHON
+};
+
+const JSON: Rules = Rules {
+    line_comments: &[],
+    quotes: &['"'],
+    ..CONFIG
+};
+
+const LUA: Rules = Rules {
+    line_comments: &["--"],
+    block_comment: Some(("--[[", "]]")),
+    keywords: &[
+        "and", "break", "do", "else", "elseif", "end", "for", "function", "goto", "if", "in", "local", "not",
+        "or", "repeat", "return", "then", "until", "while",
+    ],
+    constants: &["true", "false", "nil"],
+    ..CONFIG
+};
+
+const CSS: Rules = Rules {
+    line_comments: &[],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\''],
+    ..CONFIG
+};
+
+/// markup has comments only, quotes in prose are no strings
+const MARKUP: Rules = Rules {
+    line_comments: &[],
+    block_comment: Some(("<!--", "-->")),
+    quotes: &[],
+    constants: &[],
+    ..CONFIG
+};
+
+/// highlights comments, strings, numbers and words of special meaning of a file type
+#[derive(Default)]
+pub struct Syntax {
+    rules: Option<&'static Rules>,
+}
+
+impl Syntax {
+    pub fn for_filetype(filetype: &str) -> Self {
+        let rules = match filetype {
+            "rust" => &RUST,
+            "c" | "cpp" => &C,
+            "go" => &GO,
+            "java" => &JAVA,
+            "javascript" | "typescript" => &JAVASCRIPT,
+            "python" => &PYTHON,
+            "sh" => &SHELL,
+            "toml" | "yaml" | "make" | "dockerfile" => &CONFIG,
+            "json" => &JSON,
+            "lua" => &LUA,
+            "css" => &CSS,
+            "html" | "markdown" => &MARKUP,
+            _ => return Self::default(),
+        };
+        Self { rules: Some(rules) }
+    }
+    /// the token starting a line comment and the ones around a block comment, as far as the file type has them
+    pub fn comment_tokens(&self) -> (Option<&'static str>, Option<(&'static str, &'static str)>) {
+        self.rules.map_or((None, None), |rules| (rules.line_comments.first().copied(), rules.block_comment))
+    }
+    /// highlighted grapheme ranges of a line. `state` is where the line starts,
+    /// it's updated to where the next line starts
+    pub fn highlight_line(&self, line: &Line, state: &mut State) -> Vec<(Range<usize>, Highlight)> {
+        let mut ret = Vec::new();
+        let Some(rules) = self.rules else {
+            return ret;
+        };
+        let graphemes: Vec<&str> = (0..line.grapheme_len()).filter_map(|index| line.grapheme_at(index)).collect();
+        let len = graphemes.len();
+        let mut start = 0;
+        let mut index = 0;
+        loop {
+            // finish what is still open, a comment or string from the line above or from this one
+            match *state {
+                State::Code => (),
+                State::BlockComment => {
+                    let close = rules.block_comment.map_or("", |(_, close)| close);
+                    let end = (index..len).find(|at| starts_with(&graphemes, *at, close));
+                    index = end.map_or(len, |end| end + close.len());
+                    ret.push((start..index, Highlight::Comment));
+                    if end.is_some() {
+                        *state = State::Code;
+                    }
+                }
+                State::String(quote) => {
+                    let closed = string_end(&graphemes, index, quote);
+                    index = closed.unwrap_or(len);
+                    ret.push((start..index, Highlight::String));
+                    if closed.is_some() {
+                        *state = State::Code;
+                    }
+                }
+            }
+            if index >= len {
+                break;
+            }
+
+            start = index;
+            let grapheme = graphemes[index];
+            let first = grapheme.chars().next().unwrap_or_default();
+            if let Some((open, _)) = rules.block_comment.filter(|(open, _)| starts_with(&graphemes, index, open)) {
+                *state = State::BlockComment;
+                index += open.len();
+            } else if rules.line_comments.iter().any(|comment| starts_with(&graphemes, index, comment)) {
+                ret.push((index..len, Highlight::Comment));
+                break;
+            } else if rules.quotes.contains(&first) && grapheme.len() == 1 {
+                *state = State::String(first);
+                index += 1;
+            } else if rules.char_literals && grapheme == "'" {
+                index = char_literal_end(&graphemes, index).map_or(index + 1, |end| {
+                    ret.push((index..end, Highlight::String));
+                    end
+                });
+            } else if is_word(grapheme) || grapheme == "#" {
+                let end = (index + 1..len).find(|at| !is_word(graphemes[*at])).unwrap_or(len);
+                let word: String = graphemes[index..end].concat();
+                if let Some(highlight) = classify(rules, &word) {
+                    ret.push((index..end, highlight));
+                }
+                index = end;
+            } else {
+                index += 1;
+            }
+        }
+        ret
+    }
+}
+
+fn classify(rules: &Rules, word: &str) -> Option<Highlight> {
+    let first = word.chars().next()?;
+    if first.is_ascii_digit() {
+        Some(Highlight::Number)
+    } else if rules.keywords.contains(&word) {
+        Some(Highlight::Keyword)
+    } else if rules.types.contains(&word) {
+        Some(Highlight::Type)
+    } else if rules.constants.contains(&word) {
+        Some(Highlight::Constant)
+    } else if rules.capitalized_types && first.is_uppercase() && word.chars().any(char::is_lowercase) {
+        Some(Highlight::Type)
+    } else {
+        None
+    }
+}
+
+fn is_word(grapheme: &str) -> bool {
+    grapheme.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// whether the graphemes from given index spell `pattern`, which is ascii
+fn starts_with(graphemes: &[&str], index: usize, pattern: &str) -> bool {
+    !pattern.is_empty()
+        && graphemes
+            .get(index..index + pattern.len())
+            .is_some_and(|slice| slice.concat() == pattern)
+}
+
+/// index after the quote closing a string, if it is closed within the line
+fn string_end(graphemes: &[&str], mut index: usize, quote: char) -> Option<usize> {
+    while let Some(grapheme) = graphemes.get(index) {
+        if *grapheme == "\\" {
+            index += 2;
+            continue;
+        }
+        index += 1;
+        if grapheme.len() == 1 && grapheme.starts_with(quote) {
+            return Some(index);
+        }
+    }
+    None
+}
+
+/// index after a character literal like `'a'` or `'\n'` starting at given index
+fn char_literal_end(graphemes: &[&str], index: usize) -> Option<usize> {
+    match graphemes.get(index + 1) {
+        Some(&"\\") => (index + 3..graphemes.len().min(index + 12))
+            .find(|at| graphemes[*at] == "'")
+            .map(|at| at + 1),
+        Some(_) if graphemes.get(index + 2) == Some(&"'") => Some(index + 3),
+        _ => None,
+    }
+}
+
+/// states at the start of lines, kept between redraws so that drawing the end of a long file
+/// does not highlight all of it from the top every time
+#[derive(Default)]
+pub struct StateCache {
+    /// the state at the start of each line, as far as it was needed since the lines above changed
+    states: Vec<State>,
+}
+
+impl StateCache {
+    /// forget the states of the lines after given one, it was edited
+    pub fn invalidate_from(&mut self, line_index: usize) {
+        self.states.truncate(line_index + 1);
+    }
+    pub fn clear(&mut self) {
+        self.states.clear();
+    }
+    /// the state at the start of given line, highlighting the lines above it that weren't yet
+    pub fn state_at(&mut self, syntax: &Syntax, lines: &[Line], line_index: usize) -> State {
+        let line_index = line_index.min(lines.len());
+        if self.states.is_empty() {
+            self.states.push(State::default());
+        }
+        while self.states.len() <= line_index {
+            let last = self.states.len() - 1;
+            let mut state = self.states[last];
+            syntax.highlight_line(&lines[last], &mut state);
+            self.states.push(state);
+        }
+        self.states[line_index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(texts: &[&str]) -> Vec<Line> {
+        texts.iter().map(|text| Line::from(text)).collect()
+    }
+
+    /// highlighted ranges of a line with the name of their theme element
+    fn highlights(syntax: &Syntax, text: &str, state: &mut State) -> Vec<(Range<usize>, &'static str)> {
+        syntax
+            .highlight_line(&Line::from(text), state)
+            .into_iter()
+            .map(|(range, highlight)| (range, highlight.element()))
+            .collect()
+    }
+
+    #[test]
+    fn words_numbers_and_comments_are_highlighted() {
+        let syntax = Syntax::for_filetype("rust");
+        let mut state = State::Code;
+        let line = highlights(&syntax, "let x: u32 = 42; // done", &mut state);
+        assert_eq!(line, [(0..3, "keyword"), (7..10, "type"), (13..15, "number"), (17..24, "comment")]);
+        let line = highlights(&syntax, "Self::new(Vec::new(), None)", &mut state);
+        assert_eq!(line, [(0..4, "keyword"), (10..13, "type"), (22..26, "constant")]);
+        assert!(state == State::Code);
+
+        let text = Syntax::for_filetype("text");
+        assert!(highlights(&text, "let x = 1; // done", &mut state).is_empty());
+        let c = Syntax::for_filetype("c");
+        assert_eq!(highlights(&c, "#include <stdio.h>", &mut state), [(0..8, "keyword")]);
+    }
+
+    #[test]
+    fn lifetimes_are_no_char_literals() {
+        let syntax = Syntax::for_filetype("rust");
+        let mut state = State::Code;
+        let line = highlights(&syntax, "fn f<'a>(c: &'a str) -> char { 'x' }", &mut state);
+        assert_eq!(line, [(0..2, "keyword"), (16..19, "type"), (24..28, "type"), (31..34, "string")]);
+        assert!(state == State::Code);
+    }
+
+    #[test]
+    fn escaped_quotes_do_not_end_strings() {
+        let syntax = Syntax::for_filetype("rust");
+        let mut state = State::Code;
+        let line = highlights(&syntax, r#"let s = "a\"b"; let c = '\n';"#, &mut state);
+        assert_eq!(line, [(0..3, "keyword"), (8..14, "string"), (16..19, "keyword"), (24..28, "string")]);
+        assert!(state == State::Code);
+        let line = highlights(&syntax, r#"let s = "a\\"; 1"#, &mut state);
+        assert_eq!(line, [(0..3, "keyword"), (8..13, "string"), (15..16, "number")]);
+    }
+
+    #[test]
+    fn comments_and_strings_go_on_over_lines() {
+        let syntax = Syntax::for_filetype("rust");
+        let mut state = State::Code;
+        assert_eq!(highlights(&syntax, "x /* a", &mut state), [(2..6, "comment")]);
+        assert!(state == State::BlockComment);
+        assert_eq!(highlights(&syntax, "b */ Vec", &mut state), [(0..4, "comment"), (5..8, "type")]);
+        assert!(state == State::Code);
+        assert_eq!(highlights(&syntax, "let s = \"abc", &mut state), [(0..3, "keyword"), (8..12, "string")]);
+        assert!(state == State::String('"'));
+        assert_eq!(highlights(&syntax, "def\" 1", &mut state), [(0..4, "string"), (5..6, "number")]);
+        assert!(state == State::Code);
+    }
+
+    #[test]
+    fn cached_states_carry_over_lines() {
+        let syntax = Syntax::for_filetype("rust");
+        let lines = lines(&["let a = 1; /* open", "still comment", "*/ let s = \"", "x"]);
+        let mut cache = StateCache::default();
+        assert!(cache.state_at(&syntax, &lines, 3) == State::String('"'));
+        assert!(cache.state_at(&syntax, &lines, 0) == State::Code);
+        assert!(cache.state_at(&syntax, &lines, 1) == State::BlockComment);
+        assert!(cache.state_at(&syntax, &lines, 2) == State::BlockComment);
+        // past the end is where the last line leaves off
+        assert!(cache.state_at(&syntax, &lines, 99) == State::String('"'));
+    }
+
+    #[test]
+    fn edited_lines_are_highlighted_again() {
+        let syntax = Syntax::for_filetype("rust");
+        let mut lines = lines(&["fn f() {", "    g();", "}"]);
+        let mut cache = StateCache::default();
+        assert!(cache.state_at(&syntax, &lines, 2) == State::Code);
+        lines[0] = Line::from("/* fn f() {");
+        cache.invalidate_from(0);
+        assert!(cache.state_at(&syntax, &lines, 0) == State::Code);
+        assert!(cache.state_at(&syntax, &lines, 2) == State::BlockComment);
+        lines[1] = Line::from("    g(); */");
+        cache.invalidate_from(1);
+        assert!(cache.state_at(&syntax, &lines, 1) == State::BlockComment);
+        assert!(cache.state_at(&syntax, &lines, 2) == State::Code);
+    }
+}
@@ -1,9 +1,12 @@
 use std::io::{stdout, Error as IoE, Write};
-use crossterm::{queue, style::Print, Command};
+use crossterm::{queue, style::{ContentStyle, Print, PrintStyledContent}, Command};
 use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{DisableFocusChange, DisableMouseCapture, EnableFocusChange, EnableMouseCapture};
+use unicode_width::UnicodeWidthStr;
+use super::{highlight::Highlight, theme::Theme};
 use crossterm::terminal::{self, disable_raw_mode, enable_raw_mode, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
 
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
 pub struct Position {
     pub col: usize,
     pub row: usize,
@@ -16,6 +19,16 @@ pub struct Size {
     pub height: usize,
 }
 
+/// colors the terminal is able to show
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ColorSupport {
+    /// the 16 basic colors
+    Ansi16,
+    Ansi256,
+    /// 24-bit colors
+    TrueColor,
+}
+
 pub struct Terminal();
 
 #[allow(unused)]
@@ -24,11 +37,15 @@ impl Terminal {
     pub fn initialize() -> Result<(), IoE> {
         enable_raw_mode()?;
         Self::enter_alternate_screen()?;
+        Self::push_command_queue(EnableMouseCapture)?;
+        Self::push_command_queue(EnableFocusChange)?;
         Self::clear_screen()?;
         Self::execute()
     }
     /// do some work before exiting
     pub fn terminate() -> Result<(), IoE> {
+        Self::push_command_queue(DisableMouseCapture)?;
+        Self::push_command_queue(DisableFocusChange)?;
         Self::leave_alternate_screen()?;
         Self::show_cursor()?;
         Self::execute()?;
@@ -85,6 +102,46 @@ impl Terminal {
         Self::clear_line()?;
         Self::print(text)
     }
+    /// print segments of text at given row, each drawn in the theme's style of its highlight.
+    /// the rest of the row is filled in the style of plain text
+    pub fn print_highlighted_at(row: usize, segments: &[(String, Option<Highlight>)], theme: &Theme) -> Result<(), IoE> {
+        Self::move_cursor(Position { col: 0, row })?;
+        let mut width = 0;
+        for (text, highlight) in segments {
+            let style = highlight.map_or_else(|| theme.text(), |highlight| theme.style(highlight));
+            Self::print_styled(text, style)?;
+            width += text.width();
+        }
+        let rest = Self::size()?.width.saturating_sub(width);
+        Self::print_styled(&" ".repeat(rest), theme.text())
+    }
+    /// print a whole row in given style, padded to the screen width
+    pub fn print_styled_row(row: usize, text: &str, style: ContentStyle) -> Result<(), IoE> {
+        let width = Self::size()?.width;
+        Self::move_cursor(Position { col: 0, row })?;
+        Self::print_styled(&format!("{text:width$.width$}"), style)
+    }
+    fn print_styled(text: &str, style: ContentStyle) -> Result<(), IoE> {
+        if text.is_empty() {
+            return Ok(());
+        }
+        Self::push_command_queue(PrintStyledContent(style.apply(text)))
+    }
+    /// colors the terminal supports, judged by `COLORTERM` and `TERM`
+    pub fn color_support() -> ColorSupport {
+        let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+        let term = std::env::var("TERM").unwrap_or_default();
+        Self::color_support_of(&colorterm, &term)
+    }
+    fn color_support_of(colorterm: &str, term: &str) -> ColorSupport {
+        if matches!(colorterm, "truecolor" | "24bit") || term.ends_with("-direct") {
+            ColorSupport::TrueColor
+        } else if term.contains("256color") {
+            ColorSupport::Ansi256
+        } else {
+            ColorSupport::Ansi16
+        }
+    }
     /// push a command to the command queue
     fn push_command_queue<T: Command>(command: T) -> Result<(), IoE> {
         queue!(stdout(), command)?;
@@ -99,4 +156,25 @@ impl Position {
             row: self.row - other.row,
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_support_is_judged_by_the_environment() {
+        for (colorterm, term, support) in [
+            ("truecolor", "xterm-256color", ColorSupport::TrueColor),
+            ("24bit", "", ColorSupport::TrueColor),
+            ("", "xterm-direct", ColorSupport::TrueColor),
+            ("", "xterm-256color", ColorSupport::Ansi256),
+            ("", "screen-256color", ColorSupport::Ansi256),
+            ("yes", "tmux-256color", ColorSupport::Ansi256),
+            ("", "xterm", ColorSupport::Ansi16),
+            ("", "linux", ColorSupport::Ansi16),
+            ("", "", ColorSupport::Ansi16),
+        ] {
+            assert!(Terminal::color_support_of(colorterm, term) == support, "{colorterm:?} {term:?}");
+        }
+    }
+}
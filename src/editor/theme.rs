@@ -0,0 +1,404 @@
+use std::collections::HashMap;
+
+use crossterm::style::{Attribute, Color, ContentStyle};
+use toml::{Table, Value};
+
+use super::{config::read_config, highlight::Highlight, terminal::ColorSupport};
+
+/// parts of the screen a theme gives colors to
+const ELEMENTS: [&str; 22] = [
+    "text", "gutter", "status_bar", "selection", "search", "ruler", "bracket", "cursor",
+    "keyword", "type", "string", "comment", "number", "constant",
+    "error", "warning", "info", "hint",
+    "error_underline", "warning_underline", "info_underline", "hint_underline",
+];
+
+/// the 16 basic colors with their usual values, used to pick the closest one
+const BASIC_COLORS: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::DarkRed, (128, 0, 0)),
+    (Color::DarkGreen, (0, 128, 0)),
+    (Color::DarkYellow, (128, 128, 0)),
+    (Color::DarkBlue, (0, 0, 128)),
+    (Color::DarkMagenta, (128, 0, 128)),
+    (Color::DarkCyan, (0, 128, 128)),
+    (Color::Grey, (192, 192, 192)),
+    (Color::DarkGrey, (128, 128, 128)),
+    (Color::Red, (255, 0, 0)),
+    (Color::Green, (0, 255, 0)),
+    (Color::Yellow, (255, 255, 0)),
+    (Color::Blue, (0, 0, 255)),
+    (Color::Magenta, (255, 0, 255)),
+    (Color::Cyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+/// a named color scheme. built-in themes are `default` and `night`,
+/// others are loaded from `themes/<name>.toml` in the config directory, for example:
+///
+/// ```toml
+/// text = { fg = "#c5c8c6", bg = "#1d1f21" }
+/// status_bar = { fg = "black", bg = "#81a2be", bold = true }
+/// keyword = { fg = 170, italic = true }
+/// ```
+///
+/// colors are `#rrggbb`, a 256-color index or a name like `dark_red`.
+/// elements missing from the file are taken from the `default` theme
+pub struct Theme {
+    styles: HashMap<&'static str, ContentStyle>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            styles: parse_styles(DEFAULT_THEME).unwrap_or_default(),
+        }
+    }
+}
+
+impl Theme {
+    /// load the theme of given name, with colors fitted to what the terminal supports
+    pub fn load(name: &str, support: ColorSupport) -> Result<Self, String> {
+        let mut theme = match name {
+            "default" => Self::default(),
+            "night" => Self::parse(NIGHT_THEME)?,
+            _ => {
+                let file = format!("themes/{name}.toml");
+                match read_config(&file) {
+                    None => return Err(format!("E185: Cannot find color scheme '{name}'")),
+                    Some(Err(e)) => return Err(format!("{file}: {e}")),
+                    Some(Ok(contents)) => Self::parse(&contents).map_err(|e| format!("{file}: {e}"))?,
+                }
+            }
+        };
+        for style in theme.styles.values_mut() {
+            style.foreground_color = style.foreground_color.map(|color| fit_color(color, support));
+            style.background_color = style.background_color.map(|color| fit_color(color, support));
+        }
+        Ok(theme)
+    }
+    /// style of plain text
+    pub fn text(&self) -> ContentStyle {
+        self.styles.get("text").copied().unwrap_or_default()
+    }
+    /// style of given element, colors it leaves out are those of plain text
+    pub fn element(&self, name: &str) -> ContentStyle {
+        let text = self.text();
+        let mut style = self.styles.get(name).copied().unwrap_or(text);
+        style.foreground_color = style.foreground_color.or(text.foreground_color);
+        style.background_color = style.background_color.or(text.background_color);
+        style
+    }
+    pub fn style(&self, highlight: Highlight) -> ContentStyle {
+        self.element(highlight.element())
+    }
+    /// a theme on top of the default one
+    fn parse(contents: &str) -> Result<Self, String> {
+        let mut theme = Self::default();
+        theme.styles.extend(parse_styles(contents)?);
+        Ok(theme)
+    }
+}
+
+fn parse_styles(contents: &str) -> Result<HashMap<&'static str, ContentStyle>, String> {
+    let table = contents.parse::<Table>().map_err(|e| e.message().to_string())?;
+    let mut styles = HashMap::new();
+    for (name, value) in table {
+        let Some(element) = ELEMENTS.iter().find(|element| **element == name) else {
+            return Err(format!("unknown element \"{name}\""));
+        };
+        styles.insert(*element, parse_style(&name, &value)?);
+    }
+    Ok(styles)
+}
+
+fn parse_style(name: &str, value: &Value) -> Result<ContentStyle, String> {
+    let Value::Table(table) = value else {
+        return Err(format!("{name} must be a table"));
+    };
+    let mut style = ContentStyle::new();
+    for (key, value) in table {
+        match (key.as_str(), value) {
+            ("fg", _) => style.foreground_color = Some(parse_color(name, value)?),
+            ("bg", _) => style.background_color = Some(parse_color(name, value)?),
+            ("bold", Value::Boolean(true)) => style.attributes.set(Attribute::Bold),
+            ("italic", Value::Boolean(true)) => style.attributes.set(Attribute::Italic),
+            ("underline", Value::Boolean(true)) => style.attributes.set(Attribute::Underlined),
+            ("reverse", Value::Boolean(true)) => style.attributes.set(Attribute::Reverse),
+            ("bold" | "italic" | "underline" | "reverse", Value::Boolean(false)) => (),
+            _ => return Err(format!("bad attribute \"{key}\" of {name}")),
+        }
+    }
+    Ok(style)
+}
+
+fn parse_color(name: &str, value: &Value) -> Result<Color, String> {
+    let color = match value {
+        Value::Integer(index) => u8::try_from(*index).ok().map(Color::AnsiValue),
+        Value::String(text) => match text.strip_prefix('#') {
+            Some(hex) if hex.len() == 6 => u32::from_str_radix(hex, 16).ok().map(|rgb| Color::Rgb {
+                r: (rgb >> 16) as u8,
+                g: (rgb >> 8) as u8,
+                b: rgb as u8,
+            }),
+            Some(_) => None,
+            None => Color::try_from(text.as_str()).ok(),
+        },
+        _ => None,
+    };
+    color.ok_or_else(|| format!("bad color {value} of {name}"))
+}
+
+/// the closest color the terminal can show
+fn fit_color(color: Color, support: ColorSupport) -> Color {
+    match (support, color) {
+        (ColorSupport::TrueColor, _) => color,
+        (ColorSupport::Ansi256, Color::Rgb { r, g, b }) => Color::AnsiValue(rgb_to_ansi256((r, g, b))),
+        (ColorSupport::Ansi16, Color::AnsiValue(index)) if index < 16 => BASIC_COLORS[index as usize].0,
+        (ColorSupport::Ansi16, Color::AnsiValue(index)) => closest_basic(ansi256_to_rgb(index)),
+        (ColorSupport::Ansi16, Color::Rgb { r, g, b }) => closest_basic((r, g, b)),
+        _ => color,
+    }
+}
+
+/// levels of each channel in the 6x6x6 color cube of 256-color terminals
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn rgb_to_ansi256(rgb: (u8, u8, u8)) -> u8 {
+    let closest_level = |value: u8| {
+        (0..CUBE_LEVELS.len())
+            .min_by_key(|index| CUBE_LEVELS[*index].abs_diff(value))
+            .unwrap_or(0)
+    };
+    let (r, g, b) = (closest_level(rgb.0), closest_level(rgb.1), closest_level(rgb.2));
+    let cube = (16 + 36 * r + 6 * g + b) as u8;
+    // the gray ramp is finer than the cube's grays
+    let average = (u16::from(rgb.0) + u16::from(rgb.1) + u16::from(rgb.2)) / 3;
+    let gray_step = (average.saturating_sub(3) / 10).min(23) as u8;
+    let gray = 232 + gray_step;
+    if distance(ansi256_to_rgb(gray), rgb) < distance(ansi256_to_rgb(cube), rgb) {
+        gray
+    } else {
+        cube
+    }
+}
+
+fn ansi256_to_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..=15 => BASIC_COLORS[index as usize].1,
+        16..=231 => {
+            let index = index - 16;
+            let level = |value: u8| CUBE_LEVELS[value as usize];
+            (level(index / 36), level(index / 6 % 6), level(index % 6))
+        }
+        _ => {
+            let value = 8 + (index - 232) * 10;
+            (value, value, value)
+        }
+    }
+}
+
+/// picks by hue rather than by distance, so that muted colors don't all end up gray
+fn closest_basic((r, g, b): (u8, u8, u8)) -> Color {
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let chroma = f32::from(max - min);
+    if chroma <= f32::from(max) * 0.2 {
+        return match (u16::from(r) + u16::from(g) + u16::from(b)) / 3 {
+            0..=47 => Color::Black,
+            48..=159 => Color::DarkGrey,
+            160..=223 => Color::Grey,
+            _ => Color::White,
+        };
+    }
+    let (r, g, b) = (f32::from(r), f32::from(g), f32::from(b));
+    let sector = if max == min {
+        0.0
+    } else if r >= g && r >= b {
+        ((g - b) / chroma).rem_euclid(6.0)
+    } else if g >= b {
+        (b - r) / chroma + 2.0
+    } else {
+        (r - g) / chroma + 4.0
+    };
+    let bright = max >= 192;
+    let hues = if bright {
+        [Color::Red, Color::Yellow, Color::Green, Color::Cyan, Color::Blue, Color::Magenta]
+    } else {
+        [Color::DarkRed, Color::DarkYellow, Color::DarkGreen, Color::DarkCyan, Color::DarkBlue, Color::DarkMagenta]
+    };
+    hues[(sector + 0.5) as usize % 6]
+}
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let channel = |x: u8, y: u8| u32::from(x.abs_diff(y)).pow(2);
+    channel(a.0, b.0) + channel(a.1, b.1) + channel(a.2, b.2)
+}
+
+/// uses the terminal's own colors, so it looks right everywhere
+const DEFAULT_THEME: &str = r##"
+text = {}
+gutter = { fg = "dark_grey" }
+status_bar = { reverse = true }
+selection = { reverse = true }
+search = { fg = "black", bg = "yellow" }
+ruler = { bg = "dark_grey" }
+bracket = { bold = true, underline = true }
+cursor = { reverse = true }
+keyword = { fg = "dark_magenta", bold = true }
+type = { fg = "dark_cyan" }
+string = { fg = "dark_green" }
+comment = { fg = "dark_grey", italic = true }
+number = { fg = "dark_yellow" }
+constant = { fg = "dark_red" }
+error = { fg = "red" }
+warning = { fg = "yellow" }
+info = { fg = "blue" }
+hint = { fg = "dark_grey" }
+error_underline = { fg = "red", underline = true }
+warning_underline = { fg = "yellow", underline = true }
+info_underline = { underline = true }
+hint_underline = { underline = true }
+"##;
+
+const NIGHT_THEME: &str = r##"
+text = { fg = "#c5c8c6", bg = "#1d1f21" }
+gutter = { fg = "#5c6370", bg = "#1d1f21" }
+status_bar = { fg = "#1d1f21", bg = "#81a2be", bold = true }
+selection = { bg = "#373b41" }
+search = { fg = "#1d1f21", bg = "#f0c674" }
+ruler = { bg = "#282a2e" }
+bracket = { fg = "#f0c674", bold = true, underline = true }
+cursor = { fg = "#1d1f21", bg = "#c5c8c6" }
+keyword = { fg = "#b294bb", bold = true }
+type = { fg = "#8abeb7" }
+string = { fg = "#b5bd68" }
+comment = { fg = "#969896", italic = true }
+number = { fg = "#de935f" }
+constant = { fg = "#cc6666" }
+error = { fg = "#cc6666" }
+warning = { fg = "#f0c674" }
+info = { fg = "#81a2be" }
+hint = { fg = "#969896" }
+error_underline = { fg = "#cc6666", underline = true }
+warning_underline = { fg = "#f0c674", underline = true }
+info_underline = { underline = true }
+hint_underline = { underline = true }
+"##;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(toml: &str) -> Value {
+        toml.parse::<Table>().unwrap().remove("v").unwrap()
+    }
+
+    #[test]
+    fn rgb_maps_to_the_closest_of_the_256_colors() {
+        for (rgb, index) in [
+            ((0, 0, 0), 16),
+            ((255, 255, 255), 231),
+            ((255, 0, 0), 196),
+            ((0, 255, 0), 46),
+            ((0, 0, 255), 21),
+            ((95, 135, 175), 67),
+            ((250, 130, 10), 208),
+        ] {
+            assert_eq!(rgb_to_ansi256(rgb), index, "{rgb:?}");
+        }
+    }
+
+    #[test]
+    fn grays_map_to_the_gray_ramp() {
+        for (rgb, index) in [((18, 18, 18), 233), ((128, 128, 128), 244), ((130, 126, 128), 244), ((238, 238, 238), 255)] {
+            assert_eq!(rgb_to_ansi256(rgb), index, "{rgb:?}");
+        }
+        for index in 232..=255 {
+            assert_eq!(rgb_to_ansi256(ansi256_to_rgb(index)), index);
+        }
+    }
+
+    #[test]
+    fn basic_colors_are_picked_by_hue() {
+        for (rgb, color) in [
+            ((0, 0, 0), Color::Black),
+            ((30, 30, 30), Color::Black),
+            ((128, 128, 128), Color::DarkGrey),
+            ((200, 200, 200), Color::Grey),
+            ((255, 255, 255), Color::White),
+            ((255, 0, 0), Color::Red),
+            ((178, 34, 34), Color::DarkRed),
+            ((200, 100, 0), Color::Yellow),
+            ((0, 128, 0), Color::DarkGreen),
+            ((0, 128, 128), Color::DarkCyan),
+            ((0, 0, 255), Color::Blue),
+            ((255, 0, 255), Color::Magenta),
+            ((255, 0, 128), Color::Magenta),
+            // muted, but not gray
+            ((135, 95, 95), Color::DarkRed),
+        ] {
+            assert_eq!(closest_basic(rgb), color, "{rgb:?}");
+        }
+    }
+
+    #[test]
+    fn colors_fit_what_the_terminal_supports() {
+        let orange = Color::Rgb { r: 250, g: 130, b: 10 };
+        assert_eq!(fit_color(orange, ColorSupport::TrueColor), orange);
+        assert_eq!(fit_color(orange, ColorSupport::Ansi256), Color::AnsiValue(208));
+        assert_eq!(fit_color(Color::AnsiValue(100), ColorSupport::Ansi256), Color::AnsiValue(100));
+        assert_eq!(fit_color(Color::DarkRed, ColorSupport::Ansi256), Color::DarkRed);
+        assert_eq!(fit_color(Color::AnsiValue(9), ColorSupport::Ansi16), Color::Red);
+        assert_eq!(fit_color(Color::AnsiValue(196), ColorSupport::Ansi16), Color::Red);
+        assert_eq!(fit_color(Color::AnsiValue(244), ColorSupport::Ansi16), Color::DarkGrey);
+        assert_eq!(fit_color(Color::Rgb { r: 0, g: 0, b: 0 }, ColorSupport::Ansi16), Color::Black);
+    }
+
+    #[test]
+    fn colors_are_hex_indices_or_names() {
+        assert_eq!(parse_color("x", &value("v = \"#ff8000\"")), Ok(Color::Rgb { r: 255, g: 128, b: 0 }));
+        assert_eq!(parse_color("x", &value("v = 170")), Ok(Color::AnsiValue(170)));
+        assert_eq!(parse_color("x", &value("v = \"dark_red\"")), Ok(Color::DarkRed));
+        for (bad, shown) in [
+            ("300", "300"),
+            ("-1", "-1"),
+            ("\"#12345\"", "\"#12345\""),
+            ("\"#gggggg\"", "\"#gggggg\""),
+            ("\"blurple\"", "\"blurple\""),
+            ("true", "true"),
+        ] {
+            let error = parse_color("keyword", &value(&format!("v = {bad}")));
+            assert_eq!(error, Err(format!("bad color {shown} of keyword")));
+        }
+    }
+
+    #[test]
+    fn bad_theme_files_are_refused() {
+        assert_eq!(parse_styles("foo = {}").err(), Some("unknown element \"foo\"".to_string()));
+        assert_eq!(parse_styles("text = 1").err(), Some("text must be a table".to_string()));
+        assert_eq!(parse_styles("text = { blink = true }").err(), Some("bad attribute \"blink\" of text".to_string()));
+        assert_eq!(parse_styles("text = { bold = 1 }").err(), Some("bad attribute \"bold\" of text".to_string()));
+        assert!(parse_styles("text = {").is_err());
+        assert!(Theme::parse("keyword = { fg = \"nope\" }").is_err());
+        assert_eq!(
+            Theme::load("no-such-theme", ColorSupport::TrueColor).err(),
+            Some("E185: Cannot find color scheme 'no-such-theme'".to_string())
+        );
+    }
+
+    #[test]
+    fn themes_fill_in_from_the_default_one() {
+        let theme = Theme::parse("text = { fg = \"#c5c8c6\", bg = 16 }\nkeyword = { fg = 170, italic = true }").unwrap();
+        let keyword = theme.element("keyword");
+        assert_eq!(keyword.foreground_color, Some(Color::AnsiValue(170)));
+        assert_eq!(keyword.background_color, Some(Color::AnsiValue(16)));
+        assert!(keyword.attributes.has(Attribute::Italic));
+        // left out of the file, so as in the default theme
+        assert_eq!(theme.element("string").foreground_color, Some(Color::DarkGreen));
+
+        let night = Theme::load("night", ColorSupport::Ansi256).unwrap();
+        assert_eq!(night.text().foreground_color, Some(Color::AnsiValue(251)));
+        assert!(night.styles.values().all(|style| !matches!(style.background_color, Some(Color::Rgb { .. }))));
+    }
+}
@@ -1,19 +1,65 @@
-use std::cmp::min;
+use std::{cmp::{min, Ordering}, fs, io::Error as IoE, ops::Range};
 
-use super::{buffer::Buffer, command::{Command, Direction}, line::Line, terminal::*};
+use regex::Regex;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
-const VERSION: &str = env!("CARGO_PKG_VERSION");
-const NAME: &str = env!("CARGO_PKG_NAME");
+use super::{bracket, buffer::Buffer, comment, command::{Command, Direction, MotionKind, Operator, SelectionKind}, diagnostic::{Diagnostic, Severity}, diff, excommand::Substitution, highlight::Highlight, line::Line, settings::Settings, statusbar::DocumentStatus, swap::{Found, Swap}, syntax::Syntax, terminal::*, theme::Theme};
 
 type Offset = Position;
 
 /// changed position expression to better support graphemes
-#[derive(Copy, Clone, Default)]
+#[derive(Copy, Clone, Default, PartialEq, Eq)]
 pub struct Location {
     pub grapheme_index: usize, 
     pub line_index: usize,
 }
 
+impl Ord for Location {
+    /// locations are ordered as they appear in the buffer
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.line_index, self.grapheme_index).cmp(&(other.line_index, other.grapheme_index))
+    }
+}
+
+impl PartialOrd for Location {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Location {
+    /// parse "line" or "line:col" typed by the user. both are 1-based
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut parts = text.trim().splitn(2, ':');
+        let line = parts.next()?.trim().parse::<usize>().ok()?;
+        let col = match parts.next() {
+            Some(col) => col.trim().parse::<usize>().ok()?,
+            None => 1,
+        };
+        Some(Self {
+            grapheme_index: col.saturating_sub(1),
+            line_index: line.saturating_sub(1),
+        })
+    }
+}
+
+/// text cut or copied by an operator, waiting to be pasted
+#[derive(Clone, Default)]
+pub struct Register {
+    pub text: String,
+    /// whole lines are pasted as new lines, blocks on the lines from the caret down
+    pub kind: SelectionKind,
+}
+
+/// text between the anchor and the caret
+#[derive(Clone, Copy)]
+struct Selection {
+    /// where selecting started
+    anchor: Location,
+    kind: SelectionKind,
+}
+
 /// contents shown on the screen
 #[derive(Default)]
 pub struct View {
@@ -23,6 +69,26 @@ pub struct View {
     /// current position of the cursor
     location: Location,
     offset: Offset,
+    selection: Option<Selection>,
+    settings: Settings,
+    syntax: Syntax,
+    /// the bracket at the caret and its match, as last drawn
+    brackets: Option<(Location, Location)>,
+    /// the caret and the buffer version `brackets` were searched for
+    brackets_searched: Option<(Location, usize)>,
+    /// carets other than `location`, sorted and never on the same spot as another caret
+    cursors: Vec<Location>,
+    /// journal of unsaved changes, while the file is edited here only
+    swap: Option<Swap>,
+    /// pattern of the last search, its matches are highlighted
+    search: Option<Regex>,
+    /// whether lines appended to the file are read as it grows, like `tail -f`
+    follow: bool,
+    /// problems found in the buffer, of every source, sorted by start
+    diagnostics: Vec<Diagnostic>,
+    /// index of the diagnostic whose message pops up under the caret and where the caret was,
+    /// as last drawn
+    popup: Option<(usize, Position)>,
 }
 
 impl View {
@@ -33,30 +99,352 @@ impl View {
             ..Default::default()
         }
     }
+    /// a view of given lines that belong to no file, like a diff
+    pub fn scratch(lines: &[String]) -> Self {
+        let mut view = Self::default();
+        view.buffer.lines = lines.iter().map(|line| Line::from(line)).collect();
+        view
+    }
     pub fn command_handler(&mut self, command: Command) {
+        if !self.cursors.is_empty()
+            && matches!(
+                command,
+                Command::Move(_) | Command::Insert(_) | Command::InsertNewline | Command::DeleteBackward | Command::Delete
+            )
+        {
+            self.for_each_cursor(|view| view.command_handler(command.clone()));
+            return;
+        }
         match command {
-            Command::Quit => (),
+            // handled by the editor
+            Command::Quit
+            | Command::GotoLine
+            | Command::FindFile
+            | Command::Ex
+            | Command::Execute(_)
+            | Command::Undo
+            | Command::Redo
+            | Command::RecordMacro(_)
+            | Command::StopRecording
+            | Command::PlayMacro { .. }
+            | Command::Search { .. }
+            | Command::SearchNext { .. }
+            | Command::StartSearch { .. }
+            | Command::Follow
+            | Command::Build
+            | Command::Hover
+            | Command::GotoDefinition
+            | Command::Complete
+            | Command::NextError { .. }
+            | Command::ToggleComment { .. } => (),
+            Command::Scroll(direction) => self.scroll(direction),
+            Command::Click { position, add } => self.click(position, add),
+            Command::AddCursor(direction) => self.add_cursor(direction),
+            Command::AddNextOccurrence => self.add_next_occurrence(),
+            Command::SingleCursor => {
+                self.single_cursor();
+                self.clear_selection();
+            }
+            Command::DuplicateLines => self.duplicate_lines(),
+            Command::MoveLines(direction) => self.move_lines(direction == Direction::Up),
+            Command::DeleteLines => {
+                let lines = self.selected_lines();
+                self.clear_selection();
+                self.delete_lines(lines);
+            }
+            Command::JoinLines => {
+                let lines = self.selected_lines();
+                let lines = if lines.len() > 1 { lines } else { lines.start..lines.start + 2 };
+                self.join_lines(lines);
+            }
             Command::Move(direction) => self.move_location(direction),
             Command::Insert(c) => self.insert(c),
+            Command::InsertNewline => self.insert_newline(),
+            Command::DeleteBackward => self.delete_backward(),
+            Command::Delete => self.delete(),
+            Command::Goto(location) => self.goto(location),
             Command::Resize(size) => self.resize(size),
+            Command::StartSelection(kind) => self.start_selection(kind),
+            Command::SelectBlock(direction) => {
+                if self.selection.is_none_or(|selection| selection.kind != SelectionKind::Block) {
+                    self.start_selection(SelectionKind::Block);
+                }
+                self.move_location(direction);
+            }
+            Command::BlockInsert { append } => self.block_insert(append),
+            Command::ClearSelection => self.clear_selection(),
+            // these produce or consume a register, see the methods below
+            Command::Operate { .. } | Command::OperateLines { .. } | Command::OperateSelection(_) | Command::Paste { .. } => (),
+        }
+    }
+    /// load file from given path. an inexistent file is treated as a new one,
+    /// one that cannot be written is read only
+    pub fn load_file(&mut self, path: &str) -> Result<(), IoE> {
+        self.buffer.load_file(path)?;
+        if fs::metadata(path).is_ok_and(|metadata| metadata.permissions().readonly()) {
+            self.settings.read_only = true;
+        }
+        if let Some(swap) = self.swap.as_mut() {
+            let _ = swap.write(&[], self.buffer.version, false);
+        }
+        self.location = Location::default();
+        self.cursors.clear();
+        self.offset = Offset::default();
+        self.need_redraw = true;
+        Ok(())
+    }
+    pub fn save(&mut self) -> Result<(), IoE> {
+        self.buffer.save(&self.settings)?;
+        self.snap_to_buffer();
+        if let Some(swap) = self.swap.as_mut() {
+            let _ = swap.write(&[], self.buffer.version, false);
+        }
+        self.need_redraw = true;
+        Ok(())
+    }
+    pub fn save_as(&mut self, path: &str) -> Result<(), IoE> {
+        let renamed = self.file_name() != Some(path);
+        self.buffer.save_as(path, &self.settings)?;
+        self.snap_to_buffer();
+        if renamed && self.swap.is_some() {
+            self.close_swap();
+            let _ = self.start_swap();
+        } else if let Some(swap) = self.swap.as_mut() {
+            let _ = swap.write(&[], self.buffer.version, false);
         }
+        self.need_redraw = true;
+        Ok(())
+    }
+    /// keep the carets and the selection on existing text, after saving trimmed lines
+    fn snap_to_buffer(&mut self) {
+        self.location = self.buffer.clamp(self.location);
+        for cursor in &mut self.cursors {
+            *cursor = self.buffer.clamp(*cursor);
+        }
+        self.merge_cursors();
+        if let Some(selection) = &mut self.selection {
+            selection.anchor = self.buffer.clamp(selection.anchor);
+        }
+        self.scroll_screen();
+    }
+    pub fn file_name(&self) -> Option<&str> {
+        self.buffer.file_name.as_deref()
     }
-    /// load file from given path. if file inexists, just panic
-    pub fn load_file(&mut self, path: &str) {
-        if let Err(e) = self.buffer.load_file(path) {
-            panic!("\x1b[31mError when loading file: {e}\x1b[0m");
+    pub fn is_dirty(&self) -> bool {
+        self.buffer.dirty
+    }
+    pub fn status(&self) -> DocumentStatus {
+        DocumentStatus {
+            file_name: self.buffer.file_name.clone(),
+            total_lines: self.buffer.total_lines(),
+            current_line: self.location.line_index,
+            dirty: self.buffer.dirty,
+            read_only: self.settings.read_only,
+            follow: self.follow.then(|| self.at_end()),
+            errors: self.count_diagnostics(Severity::Error),
+            warnings: self.count_diagnostics(Severity::Warning),
         }
     }
+    pub const fn settings(&self) -> &Settings {
+        &self.settings
+    }
+    pub fn set_settings(&mut self, settings: Settings) {
+        self.syntax = Syntax::for_filetype(&settings.filetype);
+        self.buffer.syntax_states.clear();
+        self.brackets_searched = None;
+        self.settings = settings;
+        if !self.settings.swap_file {
+            self.close_swap();
+        }
+        if self.settings.wrap {
+            self.offset.col = 0;
+        }
+        self.scroll_screen();
+        self.need_redraw = true;
+    }
+    /// draw everything again on next render, e.g. after switching to this view
+    pub fn mark_redraw(&mut self) {
+        self.need_redraw = true;
+    }
+    /// move the caret to given location and put it in the middle of the screen.
+    /// out-of-range locations are snapped to the nearest valid one
+    pub fn goto(&mut self, location: Location) {
+        let last_line = self.buffer.total_lines().saturating_sub(1);
+        self.location = location;
+        self.location.line_index = min(location.line_index, last_line);
+        self.snap_to_valid_grapheme();
+        self.center_screen();
+    }
     /// react to resize event
     pub fn resize(&mut self, size: Size) {
         self.size = size;
         self.need_redraw = true;
     }
 
+    // region: changes on disk
+
+    /// whether the file changed on disk since it was last read or written
+    pub fn changed_on_disk(&mut self) -> bool {
+        self.buffer.changed_on_disk()
+    }
+    /// read the file again, keeping the caret where it was. can be undone
+    pub fn reload(&mut self) -> Result<(), String> {
+        self.buffer.reload().map_err(|e| format!("Error when loading file: {e}"))?;
+        if let Some(swap) = self.swap.as_mut() {
+            let _ = swap.write(&[], self.buffer.version, false);
+        }
+        self.single_cursor();
+        self.clear_selection();
+        self.snap_to_valid_line();
+        self.snap_to_valid_grapheme();
+        self.scroll_screen();
+        self.need_redraw = true;
+        Ok(())
+    }
+    /// keep the buffer as it is, the file on disk is not asked about again until it changes once more
+    pub fn keep_buffer(&mut self) {
+        self.buffer.accept_disk();
+    }
+    /// work telling how the file on disk differs from the buffer, in unified diff format.
+    /// it has what it needs to run on another thread
+    pub fn disk_diff(&self) -> Result<impl FnOnce() -> Vec<String> + Send, String> {
+        let disk = self.buffer.read_disk().map_err(|e| format!("Error when loading file: {e}"))?;
+        let lines: Vec<String> = self.buffer.lines.iter().map(Line::to_string).collect();
+        let name = self.file_name().unwrap_or_default().to_string();
+        Ok(move || diff::unified(&diff::diff(&lines, &disk), &format!("{name} (buffer)"), &format!("{name} (disk)")))
+    }
+
+    // region: following
+
+    /// start or stop reading lines appended to the file as it grows. following starts at the end
+    pub fn set_follow(&mut self, follow: bool) -> Result<(), String> {
+        if follow && self.file_name().is_none() {
+            return Err("E32: No file name".to_string());
+        }
+        self.follow = follow;
+        if follow {
+            self.move_location(Direction::FileEnd);
+        }
+        Ok(())
+    }
+    pub const fn is_following(&self) -> bool {
+        self.follow
+    }
+    /// the screen sticks to the end of the file while the caret is on the last line
+    fn at_end(&self) -> bool {
+        self.location.line_index + 1 >= self.buffer.total_lines()
+    }
+    /// read lines appended to the file since last time, if following it
+    pub fn follow(&mut self) -> Result<(), String> {
+        if !self.follow {
+            return Ok(());
+        }
+        let at_end = self.at_end();
+        let changed = self.buffer.read_appended().map_err(|e| format!("Error when loading file: {e}"))?;
+        if !changed {
+            return Ok(());
+        }
+        self.shift_diagnostics();
+        if at_end {
+            self.single_cursor();
+            self.clear_selection();
+            self.location = Location {
+                grapheme_index: 0,
+                line_index: self.buffer.total_lines().saturating_sub(1),
+            };
+        } else {
+            // the file may have been read again as a whole
+            self.snap_to_valid_line();
+            self.snap_to_valid_grapheme();
+        }
+        self.offset.row = min(self.offset.row, self.last_top_line());
+        self.scroll_screen();
+        self.need_redraw = true;
+        Ok(())
+    }
+
+    // region: swap file
+
+    /// look for a swap file left for the file. journaling starts right away, unless another instance
+    /// is editing the file or changes were left behind, which the user decides about first.
+    /// files only read are not journaled
+    pub fn open_swap(&mut self) -> Result<Found, String> {
+        let Some(file) = self.buffer.file_name.as_deref() else {
+            return Ok(Found::Nothing);
+        };
+        if !self.settings.swap_file || self.settings.read_only {
+            return Ok(Found::Nothing);
+        }
+        let found = Swap::find(file, &self.buffer.lines)?;
+        if matches!(found, Found::Nothing) {
+            self.start_swap()?;
+        }
+        Ok(found)
+    }
+    /// journal into a new swap file, replacing any left for the file
+    pub fn start_swap(&mut self) -> Result<(), String> {
+        let Some(file) = self.buffer.file_name.as_deref() else {
+            return Ok(());
+        };
+        let mut swap = Swap::create(file, self.buffer.version)?;
+        if self.buffer.dirty {
+            swap.write(&self.buffer.lines, self.buffer.version, true)?;
+        }
+        self.swap = Some(swap);
+        Ok(())
+    }
+    /// take the unsaved changes left in the swap file, as one change that can be undone
+    pub fn recover_swap(&mut self) -> Result<(), String> {
+        let Some(file) = self.buffer.file_name.as_deref() else {
+            return Ok(());
+        };
+        let lines = Swap::recover(file)?;
+        self.buffer.replace_all(&lines);
+        self.snap_to_valid_line();
+        self.snap_to_valid_grapheme();
+        self.scroll_screen();
+        self.need_redraw = true;
+        self.start_swap()
+    }
+    /// write unsaved changes to the swap file once there were enough of them since last time,
+    /// or right away if `now`
+    pub fn update_swap(&mut self, now: bool) -> Result<(), String> {
+        let Some(swap) = self.swap.as_mut() else {
+            return Ok(());
+        };
+        if !self.buffer.dirty {
+            return Ok(());
+        }
+        let interval = if now { 1 } else { self.settings.swap_interval };
+        swap.update(&self.buffer.lines, self.buffer.version, interval)
+    }
+    /// stop journaling and remove the swap file
+    pub fn close_swap(&mut self) {
+        if let Some(swap) = self.swap.take() {
+            swap.remove();
+        }
+    }
+
     // region: rendering
 
     /// render the terminal window
-    pub fn render(&mut self) {
+    pub fn render(&mut self, theme: &Theme) {
+        self.shift_diagnostics();
+        let searched = (self.location, self.buffer.version);
+        if self.brackets_searched != Some(searched) {
+            self.brackets_searched = Some(searched);
+            let brackets = bracket::find_match(&self.buffer.lines, &self.syntax, &mut self.buffer.syntax_states, self.location)
+                .map(|other| (self.location, other));
+            if brackets != self.brackets {
+                self.brackets = brackets;
+                self.need_redraw = true;
+            }
+        }
+        let popup = self.popup_diagnostic();
+        if popup != self.popup {
+            self.popup = popup;
+            self.need_redraw = true;
+        }
         if !self.need_redraw {
             return;
         }
@@ -67,42 +455,1145 @@ impl View {
         }
 
         let msg_row = height / 3;
-        let offset_row = self.offset.row;
-        for row in 0..height {
-            if let Some(line) = self.buffer.lines.get(row + offset_row) {
-                let left = self.offset.col;
-                let right = left + width;
-                Self::render_line(row, &line.get_graphems(left..right));
+        let popup = self.popup.and_then(|(index, caret)| {
+            // under the caret, or above it on the last row
+            let row = if caret.row + 1 < height { caret.row + 1 } else { caret.row.checked_sub(1)? };
+            let diagnostic = &self.diagnostics[index];
+            Some((row, caret.col, format!(" {} ", diagnostic.describe()), diagnostic.severity))
+        });
+        let brackets = self.brackets;
+        let mut state = self.buffer.syntax_states.state_at(&self.syntax, &self.buffer.lines, self.offset.row);
+        // syntax highlights of the line being drawn, shared by its wrapped rows
+        let mut syntax = Vec::new();
+        let mut syntax_line = None;
+        for (row, visible) in self.screen_rows().into_iter().enumerate() {
+            let mut segments = if let Some((line_index, columns)) = visible {
+                let first_row = syntax_line != Some(line_index);
+                if first_row {
+                    syntax = self.syntax.highlight_line(&self.buffer.lines[line_index], &mut state);
+                    syntax_line = Some(line_index);
+                    for location in brackets.iter().flat_map(|(a, b)| [a, b]) {
+                        if location.line_index == line_index {
+                            let index = location.grapheme_index;
+                            syntax.push((index..index + 1, Highlight::Bracket));
+                        }
+                    }
+                }
+                let mut segments = self.sign(line_index, first_row);
+                segments.extend(self.text_row(line_index, columns, &syntax));
+                segments
             } else if row == msg_row * 2 && self.buffer.is_empty() {
-                Self::render_line(row, &Self::welcome_message(width));
+                vec![(Self::welcome_message(&self.settings.welcome_message, width), None)]
             } else {
-                Self::render_line(row, "~");
+                vec![("~".to_string(), None)]
+            };
+            if let Some((_, col, text, severity)) = popup.as_ref().filter(|(popup_row, ..)| *popup_row == row) {
+                // moved left as far as needed to fit
+                let text = clip(text, width);
+                let col = min(*col, width - text.width());
+                segments = overlay(&segments, col, (text, Some(Highlight::Diagnostic(*severity))));
             }
+            let ret = Terminal::print_highlighted_at(row, &segments, theme);
+            debug_assert!(ret.is_ok(), "Failed to render line!");
         }
 
         self.need_redraw = false;
     }
-    /// render a single line at provided row index
-    fn render_line(row: usize, text: &str) {
-        let ret = Terminal::print_at(row, text);
-        debug_assert!(ret.is_ok(), "Failed to render line!");
+    /// the line and its range of columns shown in each screen row, if any
+    fn screen_rows(&self) -> Vec<Option<(usize, Range<usize>)>> {
+        let Size { height, width } = self.text_size();
+        if !self.settings.wrap {
+            let left = self.offset.col;
+            return (0..height)
+                .map(|row| {
+                    let line_index = row + self.offset.row;
+                    (line_index < self.buffer.total_lines()).then_some((line_index, left..left + width))
+                })
+                .collect();
+        }
+
+        let mut ret = Vec::with_capacity(height);
+        for (line_index, line) in self.buffer.lines.iter().enumerate().skip(self.offset.row) {
+            let starts = line.wrap_starts(width, &self.settings);
+            for (index, start) in starts.iter().enumerate() {
+                if ret.len() >= height {
+                    return ret;
+                }
+                let left = line.width_until(*start, &self.settings);
+                let right = starts
+                    .get(index + 1)
+                    .map_or(left + width, |next| line.width_until(*next, &self.settings));
+                ret.push(Some((line_index, left..right)));
+            }
+        }
+        ret.resize(height, None);
+        ret
+    }
+    /// the sign column of a screen row: the mark of the worst problem starting in the line
+    /// on its first row
+    fn sign(&self, line_index: usize, first_row: bool) -> Vec<(String, Option<Highlight>)> {
+        if self.gutter_width() == 0 {
+            return Vec::new();
+        }
+        match self.worst_diagnostic(line_index).filter(|_| first_row) {
+            Some(diagnostic) => {
+                let severity = diagnostic.severity;
+                vec![(format!("{} ", severity.sign()), Some(Highlight::Diagnostic(severity)))]
+            }
+            None => vec![(" ".repeat(self.gutter_width()), Some(Highlight::Gutter))],
+        }
+    }
+    /// the visible columns of a line, with syntax highlights, the ruler, problems and the selection.
+    /// the message of the line's worst problem follows its end
+    fn text_row(
+        &self,
+        line_index: usize,
+        columns: Range<usize>,
+        syntax: &[(Range<usize>, Highlight)],
+    ) -> Vec<(String, Option<Highlight>)> {
+        let Some(line) = self.buffer.lines.get(line_index) else {
+            return Vec::new();
+        };
+        let mut highlights = syntax.to_vec();
+        // cells to highlight past the end of the line
+        let mut beyond = Vec::new();
+        let line_end = line.width_until(line.grapheme_len(), &self.settings);
+        if self.settings.ruler > 0 {
+            // when wrapping, every screen row has its own ruler
+            let ruler = if self.settings.wrap { columns.start } else { 0 } + self.settings.ruler - 1;
+            if ruler >= columns.start && ruler < columns.start + self.text_size().width {
+                match line.grapheme_at_column(ruler, &self.settings) {
+                    Some(index) => highlights.push((index..index + 1, Highlight::Ruler)),
+                    None => beyond.push((ruler, Highlight::Ruler)),
+                }
+            }
+        }
+        // the worst problems go last, to be drawn over the others
+        let mut problems: Vec<&Diagnostic> = self.diagnostics.iter().collect();
+        problems.sort_by_key(|diagnostic| std::cmp::Reverse(diagnostic.severity));
+        for diagnostic in problems {
+            let Some((first, last)) = diagnostic.graphemes_in(line_index) else {
+                continue;
+            };
+            let highlight = Highlight::Underline(diagnostic.severity);
+            if first < line.grapheme_len() {
+                highlights.push((first..last.max(first + 1), highlight));
+            } else if columns.contains(&line_end) {
+                // like a missing `;`, after the last character
+                beyond.push((line_end, highlight));
+            }
+        }
+        highlights.extend(self.highlights(line_index));
+        for cursor in self.cursors.iter().filter(|cursor| cursor.line_index == line_index) {
+            let index = cursor.grapheme_index;
+            if index < line.grapheme_len() {
+                highlights.push((index..index + 1, Highlight::Cursor));
+            } else if columns.contains(&line_end) {
+                beyond.push((line_end, Highlight::Cursor));
+            }
+        }
+
+        // carets go over problems, and those over the ruler
+        beyond.sort_by_key(|(column, highlight)| {
+            (*column, *highlight == Highlight::Ruler, matches!(highlight, Highlight::Underline(_)))
+        });
+        let mut padding = Vec::new();
+        let mut pos = line_end.max(columns.start);
+        for (column, highlight) in beyond {
+            if column < pos {
+                continue;
+            }
+            padding.push((" ".repeat(column - pos), None));
+            padding.push((" ".to_string(), Some(highlight)));
+            pos = column + 1;
+        }
+        let message = self
+            .worst_diagnostic(line_index)
+            .filter(|_| self.settings.virtual_text && line_end < columns.end)
+            .and_then(|diagnostic| {
+                let text = clip(&format!("  {}", diagnostic.message.lines().next()?), columns.end.saturating_sub(pos));
+                Some((text, Some(Highlight::Diagnostic(diagnostic.severity))))
+            });
+
+        let mut segments = line.get_highlighted_graphems(columns, &highlights, &self.settings);
+        segments.extend(padding);
+        segments.extend(message);
+        segments
+    }
+    /// highlighted grapheme ranges of the line at given index
+    fn highlights(&self, line_index: usize) -> Vec<(Range<usize>, Highlight)> {
+        let mut ret = Vec::new();
+        if let Some((lines, columns)) = self.block_bounds() {
+            if let Some(line) = self.buffer.lines.get(line_index).filter(|_| lines.contains(&line_index)) {
+                let (graphemes, ..) = line.graphemes_in_columns(columns, &self.settings);
+                ret.push((graphemes, Highlight::Selection));
+            }
+        } else if let Some((start, end)) = self.selection_bounds() {
+            if (start.line_index..=end.line_index).contains(&line_index) {
+                let first = if line_index == start.line_index { start.grapheme_index } else { 0 };
+                let last = if line_index == end.line_index { end.grapheme_index } else { usize::MAX - 1 };
+                ret.push((first..last + 1, Highlight::Selection));
+            }
+        }
+        if let (Some(pattern), Some(line)) = (&self.search, self.buffer.lines.get(line_index)) {
+            let text = line.to_string();
+            for found in pattern.find_iter(&text).filter(|found| !found.is_empty()) {
+                ret.push((line.grapheme_index(found.start())..line.grapheme_index(found.end()), Highlight::Search));
+            }
+        }
+        ret
+    }
+    /// columns of the sign column, there is one while the buffer has problems
+    fn gutter_width(&self) -> usize {
+        if self.diagnostics.is_empty() { 0 } else { 2 }
+    }
+    /// the part of the view showing text, right of the sign column
+    fn text_size(&self) -> Size {
+        Size { height: self.size.height, width: self.size.width.saturating_sub(self.gutter_width()) }
+    }
+    /// the worst problem starting in given line, the first of those equally bad
+    fn worst_diagnostic(&self, line_index: usize) -> Option<&Diagnostic> {
+        self.diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.start.line_index == line_index)
+            .min_by_key(|diagnostic| diagnostic.severity)
+    }
+    /// the worst problem the caret is on and the caret's position, when messages pop up
+    /// instead of following lines
+    fn popup_diagnostic(&self) -> Option<(usize, Position)> {
+        if self.settings.virtual_text {
+            return None;
+        }
+        self.diagnostics
+            .iter()
+            .enumerate()
+            .filter(|(_, diagnostic)| diagnostic.covers(self.location))
+            .min_by_key(|(_, diagnostic)| diagnostic.severity)
+            .map(|(index, _)| (index, self.caret_position()))
+    }
+
+    // region: editing
+
+    /// remove whole lines, leaving the caret on the line after them
+    pub fn delete_lines(&mut self, range: Range<usize>) {
+        self.location.line_index = range.start;
+        self.buffer.delete_lines(range);
+        let last_line = self.buffer.total_lines().saturating_sub(1);
+        self.location.line_index = min(self.location.line_index, last_line);
+        self.move_to_line_start();
+        self.scroll_screen();
+        self.need_redraw = true;
+    }
+    /// replace matches within given lines. returns how many lines changed
+    pub fn substitute(&mut self, range: Range<usize>, substitution: &Substitution) -> usize {
+        let Substitution { pattern, replacement, global } = substitution;
+        let mut changed = 0;
+        let mut last_changed = None;
+        for line_index in range {
+            let Some(line) = self.buffer.lines.get(line_index) else {
+                break;
+            };
+            let text = line.to_string();
+            let limit = if *global { 0 } else { 1 };
+            let new_text = pattern.replacen(&text, limit, replacement.as_str());
+            if new_text != text {
+                self.buffer.replace_line(line_index, &new_text);
+                changed += 1;
+                last_changed = Some(line_index);
+            }
+        }
+        if let Some(line_index) = last_changed {
+            self.location = Location { grapheme_index: 0, line_index };
+            self.scroll_screen();
+            self.need_redraw = true;
+        }
+        changed
+    }
+    /// start a change, edits until the matching `end_change` are undone as one step
+    pub fn begin_change(&mut self) {
+        self.buffer.begin_change(self.location);
+    }
+    pub fn end_change(&mut self) {
+        self.buffer.end_change(self.location);
+        self.shift_diagnostics();
+    }
+    /// revert the last change, returns false if there is nothing to undo
+    pub fn undo(&mut self) -> bool {
+        let location = self.buffer.undo(self.location);
+        self.restore_caret(location)
+    }
+    /// apply the last undone change again, returns false if there is nothing to redo
+    pub fn redo(&mut self) -> bool {
+        let location = self.buffer.redo(self.location);
+        self.restore_caret(location)
+    }
+    fn restore_caret(&mut self, location: Option<Location>) -> bool {
+        let Some(location) = location else {
+            return false;
+        };
+        self.shift_diagnostics();
+        self.single_cursor();
+        self.clear_selection();
+        self.location = location;
+        self.scroll_screen();
+        self.need_redraw = true;
+        true
     }
     fn insert(&mut self, c: char) {
+        if let Some((lines, columns)) = self.block_bounds() {
+            // typing replaces the block on every line
+            self.clear_selection();
+            self.operate_on_block(Operator::Change, lines, columns);
+            self.for_each_cursor(|view| view.insert(c));
+            return;
+        }
+        let next = self.grapheme_after_caret();
+        if self.settings.opening_pair(c).is_some() && next == Some(c.to_string().as_str()) {
+            // step over the closer, as if it had not been inserted automatically
+            self.move_right();
+            return;
+        }
+        let close = self.settings.closing_pair(c).filter(|close| self.should_pair(c, *close));
         self.buffer.insert_at(self.location, c);
         self.move_right();
+        if let Some(close) = close {
+            self.buffer.insert_at(self.location, close);
+        }
+        self.need_redraw = true;
+    }
+    /// pairs are only closed before blanks or closers. quotes are not closed right after a word,
+    /// which is an apostrophe rather than a string
+    fn should_pair(&self, open: char, close: char) -> bool {
+        let next_is_free = self.grapheme_after_caret().is_none_or(|next| {
+            next.trim().is_empty() || next.chars().next().is_some_and(|c| self.settings.opening_pair(c).is_some())
+        });
+        let after_word = self.grapheme_before_caret().is_some_and(|prev| prev.chars().all(char::is_alphanumeric));
+        next_is_free && !(open == close && after_word)
+    }
+    /// remove the block if one is selected, returns whether there was one
+    fn delete_block(&mut self) -> bool {
+        let Some((lines, columns)) = self.block_bounds() else {
+            return false;
+        };
+        self.clear_selection();
+        self.operate_on_block(Operator::Delete, lines, columns);
+        true
+    }
+    fn grapheme_after_caret(&self) -> Option<&str> {
+        let Location { grapheme_index, line_index } = self.location;
+        self.buffer.lines.get(line_index)?.grapheme_at(grapheme_index)
+    }
+    fn grapheme_before_caret(&self) -> Option<&str> {
+        let Location { grapheme_index, line_index } = self.location;
+        self.buffer.lines.get(line_index)?.grapheme_at(grapheme_index.checked_sub(1)?)
+    }
+    fn insert_newline(&mut self) {
+        self.location = self.buffer.insert_str(self.location, "\n");
+        self.scroll_screen();
+        self.need_redraw = true;
+    }
+    fn delete_backward(&mut self) {
+        if self.delete_block() {
+            return;
+        }
+        // an empty pair goes away as a whole
+        let pair = self.grapheme_before_caret().zip(self.grapheme_after_caret()).is_some_and(|(prev, next)| {
+            let mut prev = prev.chars();
+            match (prev.next(), prev.next()) {
+                (Some(open), None) => self.settings.closing_pair(open).is_some_and(|close| next == close.to_string()),
+                _ => false,
+            }
+        });
+        if pair {
+            self.delete();
+        }
+        let end = self.location;
+        self.move_left();
+        self.buffer.delete_range(self.location, end);
+        self.scroll_screen();
+        self.need_redraw = true;
+    }
+    fn delete(&mut self) {
+        if self.delete_block() {
+            return;
+        }
+        let start = self.location;
+        self.move_right();
+        let end = self.location;
+        self.location = start;
+        self.buffer.delete_range(start, end);
+        self.need_redraw = true;
+    }
+
+    // region: line operations
+
+    /// lines covered by the selection, or the caret's line
+    fn selected_lines(&self) -> Range<usize> {
+        let (first, last) = match self.selection {
+            Some(Selection { anchor, .. }) => (
+                anchor.line_index.min(self.location.line_index),
+                anchor.line_index.max(self.location.line_index),
+            ),
+            None => (self.location.line_index, self.location.line_index),
+        };
+        first..(last + 1).min(self.buffer.total_lines())
+    }
+    /// the caret and the selection follow the lines they are on when these move
+    fn shift_lines(&mut self, down: usize, up: usize) {
+        self.location.line_index = (self.location.line_index + down).saturating_sub(up);
+        if let Some(selection) = self.selection.as_mut() {
+            selection.anchor.line_index = (selection.anchor.line_index + down).saturating_sub(up);
+        }
+        self.scroll_screen();
+        self.need_redraw = true;
+    }
+    /// copy the lines below them, the caret and the selection go to the copy
+    fn duplicate_lines(&mut self) {
+        let lines = self.selected_lines();
+        if lines.is_empty() {
+            return;
+        }
+        let len = lines.len();
+        self.buffer.duplicate_lines(lines);
+        self.shift_lines(len, 0);
+    }
+    /// swap the lines with the one above or below, taking the caret and the selection along
+    fn move_lines(&mut self, up: bool) {
+        if self.buffer.move_lines(self.selected_lines(), up) {
+            if up {
+                self.shift_lines(0, 1);
+            } else {
+                self.shift_lines(1, 0);
+            }
+        }
+    }
+    /// join lines into one, leaving the caret where the last two met
+    pub fn join_lines(&mut self, range: Range<usize>) {
+        let start = range.start;
+        if let Some(grapheme_index) = self.buffer.join_lines(range) {
+            self.clear_selection();
+            self.location = Location { grapheme_index, line_index: start };
+            self.scroll_screen();
+            self.need_redraw = true;
+        }
+    }
+    /// change the lines in range as one edit, like sorting them. returns how many lines were taken out
+    pub fn rewrite_lines(&mut self, range: Range<usize>, rewrite: impl FnOnce(&mut Vec<String>)) -> usize {
+        let end = range.end.min(self.buffer.total_lines());
+        let range = range.start.min(end)..end;
+        let old = self.line_texts(range.clone());
+        let mut lines = old.clone();
+        rewrite(&mut lines);
+        self.clear_selection();
+        if lines != old {
+            self.buffer.replace_lines(range.clone(), &lines);
+        }
+        self.location = Location { grapheme_index: 0, line_index: range.start };
+        self.scroll_screen();
+        self.need_redraw = true;
+        old.len().saturating_sub(lines.len())
+    }
+    pub fn line_texts(&self, range: Range<usize>) -> Vec<String> {
+        let end = range.end.min(self.buffer.total_lines());
+        self.buffer.lines[range.start.min(end)..end].iter().map(Line::to_string).collect()
+    }
+    /// counts the edits made to the buffer, tells whether it changed since
+    pub fn version(&self) -> usize {
+        self.buffer.version
+    }
+    pub const fn location(&self) -> Location {
+        self.location
+    }
+    /// the part of the word that ends at the caret
+    pub fn word_before_caret(&self) -> String {
+        let Location { grapheme_index, line_index } = self.location;
+        let Some(line) = self.buffer.lines.get(line_index) else {
+            return String::new();
+        };
+        let is_word = |index: usize| {
+            line.grapheme_at(index)
+                .is_some_and(|grapheme| grapheme.chars().all(|c| c.is_alphanumeric() || c == '_'))
+        };
+        let start = (0..grapheme_index).rev().find(|index| !is_word(*index)).map_or(0, |index| index + 1);
+        line.text_between(start..grapheme_index)
+    }
+    /// type text at the caret, as one edit
+    pub fn insert_text(&mut self, text: &str) {
+        self.location = self.buffer.insert_str(self.location, text);
+        self.scroll_screen();
+        self.need_redraw = true;
+    }
+    /// replace the problems reported by given source, like `lsp` or `make`
+    pub fn set_diagnostics(&mut self, source: &str, diagnostics: Vec<Diagnostic>) {
+        self.shift_diagnostics();
+        self.diagnostics.retain(|diagnostic| diagnostic.source != source);
+        self.diagnostics.extend(diagnostics);
+        self.diagnostics.sort_by_key(|diagnostic| diagnostic.start);
+        self.popup = None;
+        // the sign column may have come or gone
+        self.scroll_screen();
+        self.need_redraw = true;
+    }
+    /// follow the lines edited since last time: problems below an edit move with their lines,
+    /// those on edited lines are dropped until their source reports them again
+    fn shift_diagnostics(&mut self) {
+        let edits = self.buffer.take_edits();
+        if edits.is_empty() || self.diagnostics.is_empty() {
+            return;
+        }
+        let count = self.diagnostics.len();
+        for (range, len) in edits {
+            self.diagnostics.retain_mut(|diagnostic| {
+                if diagnostic.end.line_index < range.start {
+                    return true;
+                }
+                if diagnostic.start.line_index < range.end {
+                    return false;
+                }
+                let shift = |line_index: usize| line_index + len - range.len();
+                diagnostic.start.line_index = shift(diagnostic.start.line_index);
+                diagnostic.end.line_index = shift(diagnostic.end.line_index);
+                true
+            });
+        }
+        if self.diagnostics.len() != count {
+            self.popup = None;
+            // the sign column may have gone
+            self.scroll_screen();
+        }
+        self.need_redraw = true;
+    }
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+    fn count_diagnostics(&self, severity: Severity) -> usize {
+        self.diagnostics.iter().filter(|diagnostic| diagnostic.severity == severity).count()
+    }
+    /// the lines of the selection, if there is one
+    pub fn selection_lines(&self) -> Option<Range<usize>> {
+        self.selection.is_some().then(|| self.selected_lines())
+    }
+
+    /// comment out the lines of the caret or the selection with the file type's comments,
+    /// or uncomment them. the caret and the selection stay on the same text
+    pub fn toggle_comment(&mut self, block: bool) -> Result<(), String> {
+        let lines = self.selected_lines();
+        let mut splices = if block {
+            comment::toggle_block_comment(&self.buffer.lines, lines, &self.syntax)?
+        } else {
+            comment::toggle_lines(&self.buffer.lines, lines, &self.syntax)?
+        };
+        // later changes of a line first, so that the earlier ones still find their graphemes
+        splices.sort_by_key(|splice| std::cmp::Reverse((splice.line_index, splice.at)));
+        for splice in &splices {
+            let Some(line) = self.buffer.lines.get(splice.line_index) else {
+                continue;
+            };
+            let text = format!(
+                "{}{}{}",
+                line.text_between(0..splice.at),
+                splice.inserted,
+                line.text_between(splice.at + splice.removed..line.grapheme_len())
+            );
+            self.buffer.replace_line(splice.line_index, &text);
+            let anchor = self.selection.as_mut().map(|selection| &mut selection.anchor);
+            for location in [Some(&mut self.location), anchor].into_iter().flatten() {
+                if location.line_index == splice.line_index {
+                    location.grapheme_index = splice.shift(location.grapheme_index);
+                }
+            }
+        }
+        self.scroll_screen();
+        self.need_redraw = true;
+        Ok(())
+    }
+
+    // region: operators & registers
+
+    /// apply an operator from the caret to where moving `count` times in a direction leads.
+    /// returns what the operator took, if anything
+    pub fn operate(&mut self, operator: Operator, direction: Direction, count: usize) -> Option<Register> {
+        let start = self.location;
+        for _ in 0..count.max(1) {
+            self.move_location(direction);
+        }
+        let end = self.location;
+        self.location = start;
+        let (first, last) = (min(start, end), start.max(end));
+        match direction.kind() {
+            MotionKind::Linewise => self.operate_on_lines(operator, first.line_index..last.line_index + 1),
+            MotionKind::Inclusive => self.operate_on_range(operator, first, self.after(last)),
+            MotionKind::Exclusive => {
+                // an exclusive motion ending at the start of a later line stops at the end of the line before
+                let last = if last.line_index > first.line_index && last.grapheme_index == 0 {
+                    let line_index = last.line_index - 1;
+                    Location { line_index, grapheme_index: self.line_len(line_index) }
+                } else {
+                    last
+                };
+                self.operate_on_range(operator, first, last)
+            }
+        }
+    }
+    /// apply an operator to `count` whole lines starting from the caret
+    pub fn operate_lines(&mut self, operator: Operator, count: usize) -> Option<Register> {
+        let start = self.location.line_index;
+        let end = min(start + count.max(1), self.buffer.total_lines());
+        self.operate_on_lines(operator, start..end)
+    }
+    /// apply an operator to the selection, then clear it
+    pub fn operate_selection(&mut self, operator: Operator) -> Option<Register> {
+        if let Some((lines, columns)) = self.block_bounds() {
+            self.clear_selection();
+            return Some(self.operate_on_block(operator, lines, columns));
+        }
+        let (start, end) = self.selection_bounds()?;
+        let kind = self.selection.map(|selection| selection.kind);
+        self.clear_selection();
+        if kind == Some(SelectionKind::Lines) {
+            self.operate_on_lines(operator, start.line_index..end.line_index + 1)
+        } else {
+            self.operate_on_range(operator, start, self.after(end))
+        }
+    }
+    /// insert the register after or before the caret.
+    /// lines are pasted below or above the current line
+    pub fn paste(&mut self, register: &Register, before: bool) {
+        if register.text.is_empty() {
+            return;
+        }
+        if register.kind == SelectionKind::Block {
+            self.paste_block(&register.text, before);
+            return;
+        }
+        if register.kind == SelectionKind::Lines {
+            let line_index = if before || self.buffer.is_empty() {
+                self.location.line_index
+            } else {
+                self.location.line_index + 1
+            };
+            let at_end = line_index >= self.buffer.total_lines() && !self.buffer.is_empty();
+            self.location = if at_end {
+                // there is no line to insert before, so start a new one after the last line
+                let end = self.buffer.end_location();
+                self.buffer.insert_str(end, &format!("\n{}", register.text.trim_end_matches('\n')));
+                Location { line_index: end.line_index + 1, grapheme_index: 0 }
+            } else {
+                let start = Location { line_index, grapheme_index: 0 };
+                self.buffer.insert_str(start, &register.text);
+                start
+            };
+        } else {
+            if !before && self.location.grapheme_index < self.line_len(self.location.line_index) {
+                self.location.grapheme_index += 1;
+            }
+            self.location = self.buffer.insert_str(self.location, &register.text);
+            self.move_left();
+        }
+        self.scroll_screen();
+        self.need_redraw = true;
+    }
+    fn operate_on_range(&mut self, operator: Operator, start: Location, end: Location) -> Option<Register> {
+        let register = Register {
+            text: self.buffer.text_range(start, end),
+            kind: SelectionKind::Characters,
+        };
+        if operator != Operator::Yank {
+            self.buffer.delete_range(start, end);
+        }
+        self.location = start;
+        self.snap_to_valid_grapheme();
+        self.scroll_screen();
+        self.need_redraw = true;
+        Some(register)
+    }
+    fn operate_on_lines(&mut self, operator: Operator, lines: Range<usize>) -> Option<Register> {
+        if lines.start >= self.buffer.total_lines() {
+            return None;
+        }
+        let last_line = min(lines.end, self.buffer.total_lines()) - 1;
+        let start = Location { line_index: lines.start, grapheme_index: 0 };
+        let end = Location { line_index: last_line, grapheme_index: self.line_len(last_line) };
+        let register = Register {
+            text: format!("{}\n", self.buffer.text_range(start, end)),
+            kind: SelectionKind::Lines,
+        };
+        match operator {
+            Operator::Delete => {
+                self.delete_lines(lines);
+                return Some(register);
+            }
+            // changed lines are replaced by a single empty one
+            Operator::Change => self.buffer.delete_range(start, end),
+            Operator::Yank => (),
+        }
+        self.location = start;
+        self.scroll_screen();
+        self.need_redraw = true;
+        Some(register)
+    }
+    /// the location right after given one, without leaving its line
+    fn after(&self, location: Location) -> Location {
+        Location {
+            grapheme_index: min(location.grapheme_index + 1, self.line_len(location.line_index)),
+            ..location
+        }
+    }
+    fn line_len(&self, line_index: usize) -> usize {
+        self.buffer.lines.get(line_index).map_or(0, Line::grapheme_len)
+    }
+
+    // region: multiple cursors
+
+    /// all carets in order, the main one included
+    fn carets(&self) -> Vec<Location> {
+        let mut carets = self.cursors.clone();
+        carets.push(self.location);
+        carets.sort();
+        carets
+    }
+    /// run an action at every caret, from the last one to the first,
+    /// so that edits never move the carets still to be handled
+    fn for_each_cursor(&mut self, mut action: impl FnMut(&mut Self)) {
+        let main = self.location;
+        let carets = self.carets();
+        let cursors = std::mem::take(&mut self.cursors);
+        // carets already handled are kept as distances from the end of their line and of the buffer,
+        // which edits before them don't change
+        let mut from_end = Vec::with_capacity(carets.len());
+        for caret in carets.iter().rev() {
+            self.location = *caret;
+            action(self);
+            let Location { grapheme_index, line_index } = self.location;
+            from_end.push((
+                self.buffer.total_lines().saturating_sub(line_index),
+                self.line_len(line_index).saturating_sub(grapheme_index),
+                *caret == main,
+            ));
+        }
+        let total = self.buffer.total_lines();
+        self.cursors = Vec::with_capacity(cursors.len());
+        for (lines, graphemes, is_main) in from_end.into_iter().rev() {
+            let line_index = total.saturating_sub(lines);
+            let location = Location {
+                grapheme_index: self.line_len(line_index).saturating_sub(graphemes),
+                line_index,
+            };
+            if is_main {
+                self.location = location;
+            } else {
+                self.cursors.push(location);
+            }
+        }
+        self.merge_cursors();
+        self.need_redraw = true;
+        self.scroll_screen();
+    }
+    /// keep carets sorted, dropping those that ended up on the same spot
+    fn merge_cursors(&mut self) {
+        self.cursors.sort();
+        self.cursors.dedup();
+        let main = self.location;
+        self.cursors.retain(|cursor| *cursor != main);
+    }
+    fn add_caret(&mut self, location: Location) {
+        self.cursors.push(location);
+        self.merge_cursors();
+        self.need_redraw = true;
+    }
+    pub fn single_cursor(&mut self) {
+        if !self.cursors.is_empty() {
+            self.cursors.clear();
+            self.need_redraw = true;
+        }
+    }
+    /// add a caret above the first caret or below the last one, at the same screen column
+    fn add_cursor(&mut self, direction: Direction) {
+        let carets = self.carets();
+        let (from, line_index) = match direction {
+            Direction::Up => (carets[0], carets[0].line_index.checked_sub(1)),
+            _ => {
+                let last = carets[carets.len() - 1];
+                (last, Some(last.line_index + 1).filter(|index| *index < self.buffer.total_lines()))
+            }
+        };
+        let (Some(from_line), Some(line_index)) = (self.buffer.lines.get(from.line_index), line_index) else {
+            return;
+        };
+        let column = from_line.width_until(from.grapheme_index, &self.settings);
+        let grapheme_index = self.buffer.lines[line_index]
+            .grapheme_at_column(column, &self.settings)
+            .unwrap_or_else(|| self.line_len(line_index));
+        self.add_caret(Location { grapheme_index, line_index });
+    }
+    /// add a caret at the start of the next occurrence of the selected text, or of the word under the caret.
+    /// the search goes on after the last caret and wraps around the end of the buffer
+    fn add_next_occurrence(&mut self) {
+        let selected = self
+            .selection_bounds()
+            .filter(|(start, end)| start.line_index == end.line_index)
+            .filter(|_| self.selection.is_some_and(|selection| selection.kind == SelectionKind::Characters));
+        let (needle, whole_word) = if let Some((start, end)) = selected {
+            let end = Location { grapheme_index: end.grapheme_index + 1, ..end };
+            self.clear_selection();
+            self.location = start;
+            (self.buffer.text_range(start, end), false)
+        } else {
+            let Some((start, word)) = self.word_at_caret() else {
+                return;
+            };
+            self.location = start;
+            (word, true)
+        };
+        if needle.is_empty() {
+            return;
+        }
+
+        let carets = self.carets();
+        let last = carets[carets.len() - 1];
+        let total = self.buffer.total_lines();
+        for step in 0..=total {
+            let line_index = (last.line_index + step) % total;
+            let line = &self.buffer.lines[line_index];
+            let text = line.to_string();
+            for (byte_index, _) in text.match_indices(&needle) {
+                let grapheme_index = line.grapheme_index(byte_index);
+                let after = line.grapheme_index(byte_index + needle.len());
+                let is_word = |index: Option<usize>| {
+                    index
+                        .and_then(|index| line.grapheme_at(index))
+                        .is_some_and(|grapheme| grapheme.chars().all(|c| c.is_alphanumeric() || c == '_'))
+                };
+                if whole_word && (is_word(grapheme_index.checked_sub(1)) || is_word(Some(after))) {
+                    continue;
+                }
+                let location = Location { grapheme_index, line_index };
+                let in_order = match step {
+                    0 => location > last,
+                    _ if step == total => location < last,
+                    _ => true,
+                };
+                if in_order && !carets.contains(&location) {
+                    self.add_caret(location);
+                    return;
+                }
+            }
+        }
+    }
+    /// the start of the next match of a pattern after the caret, or of the previous one if `backward`.
+    /// the search wraps around the ends of the buffer. matches are highlighted from then on
+    pub fn find(&mut self, pattern: &Regex, backward: bool) -> Option<Location> {
+        self.search = Some(pattern.clone());
+        self.need_redraw = true;
+        let caret = self.location;
+        let total = self.buffer.total_lines();
+        if total == 0 {
+            return None;
+        }
+        for step in 0..=total {
+            let line_index = if backward {
+                (caret.line_index + total - step % total) % total
+            } else {
+                (caret.line_index + step) % total
+            };
+            let line = &self.buffer.lines[line_index];
+            let text = line.to_string();
+            let mut starts = pattern
+                .find_iter(&text)
+                .map(|found| Location { grapheme_index: line.grapheme_index(found.start()), line_index });
+            // on the caret's line, only matches on the searched side of it count at first.
+            // after going around the buffer, the others do
+            let in_order = |location: &Location| step > 0 || if backward { *location < caret } else { *location > caret };
+            let found = if backward { starts.filter(in_order).last() } else { starts.find(in_order) };
+            if found.is_some() {
+                return found;
+            }
+        }
+        None
+    }
+    /// start of the word under the caret and the word itself
+    fn word_at_caret(&self) -> Option<(Location, String)> {
+        let Location { grapheme_index, line_index } = self.location;
+        let line = self.buffer.lines.get(line_index)?;
+        let is_word = |index: usize| {
+            line.grapheme_at(index)
+                .is_some_and(|grapheme| grapheme.chars().all(|c| c.is_alphanumeric() || c == '_'))
+        };
+        if !is_word(grapheme_index) {
+            return None;
+        }
+        let start = (0..grapheme_index).rev().find(|index| !is_word(*index)).map_or(0, |index| index + 1);
+        let end = (grapheme_index..line.grapheme_len()).find(|index| !is_word(*index)).unwrap_or(line.grapheme_len());
+        Some((Location { grapheme_index: start, line_index }, line.text_between(start..end)))
+    }
+    /// move the caret to a clicked spot, or toggle an extra caret there
+    fn click(&mut self, position: Position, add: bool) {
+        let Some(location) = self.location_at(position) else {
+            return;
+        };
+        if !add {
+            self.single_cursor();
+            self.location = location;
+        } else if let Some(index) = self.cursors.iter().position(|cursor| *cursor == location) {
+            self.cursors.remove(index);
+        } else if location != self.location {
+            self.cursors.push(location);
+            self.merge_cursors();
+        }
+        self.need_redraw = true;
+        self.scroll_screen();
+    }
+    /// the text location shown at given screen position. past the end of the text is the end of the last line
+    fn location_at(&self, position: Position) -> Option<Location> {
+        let row = self.screen_rows().into_iter().nth(position.row)?;
+        let Some((line_index, columns)) = row else {
+            let line_index = self.buffer.total_lines().saturating_sub(1);
+            return Some(Location { grapheme_index: self.line_len(line_index), line_index });
+        };
+        let column = (columns.start + position.col.saturating_sub(self.gutter_width())).min(columns.end.saturating_sub(1));
+        let grapheme_index = self.buffer.lines[line_index]
+            .grapheme_at_column(column, &self.settings)
+            .unwrap_or_else(|| self.line_len(line_index));
+        Some(Location { grapheme_index, line_index })
+    }
+
+    // region: selection
+
+    fn start_selection(&mut self, kind: SelectionKind) {
+        let anchor = self.selection.map_or(self.location, |selection| selection.anchor);
+        self.selection = Some(Selection { anchor, kind });
+        self.need_redraw = true;
+    }
+    fn clear_selection(&mut self) {
+        self.selection = None;
+        self.need_redraw = true;
+    }
+    /// first and last selected location, both inclusive
+    fn selection_bounds(&self) -> Option<(Location, Location)> {
+        let Selection { anchor, kind } = self.selection?;
+        let (start, end) = (min(anchor, self.location), anchor.max(self.location));
+        if kind == SelectionKind::Lines {
+            let last = end.line_index;
+            Some((
+                Location { line_index: start.line_index, grapheme_index: 0 },
+                Location { line_index: last, grapheme_index: self.line_len(last).saturating_sub(1) },
+            ))
+        } else {
+            Some((start, end))
+        }
+    }
+
+    // region: block selection
+
+    /// lines and screen columns covered by a block selection.
+    /// the columns reach to the right edge of the grapheme at the rightmost corner
+    fn block_bounds(&self) -> Option<(Range<usize>, Range<usize>)> {
+        let Selection { anchor, kind } = self.selection?;
+        if kind != SelectionKind::Block {
+            return None;
+        }
+        let column = |location: Location| {
+            self.buffer.lines.get(location.line_index).map_or((0, 1), |line| {
+                let start = line.width_until(location.grapheme_index, &self.settings);
+                let end = line.width_until(location.grapheme_index + 1, &self.settings);
+                (start, end.max(start + 1))
+            })
+        };
+        let (anchor_start, anchor_end) = column(anchor);
+        let (caret_start, caret_end) = column(self.location);
+        let lines = min(anchor.line_index, self.location.line_index)..anchor.line_index.max(self.location.line_index) + 1;
+        let columns = min(anchor_start, caret_start)..anchor_end.max(caret_end);
+        Some((lines, columns))
+    }
+    /// copy or cut the block. graphemes sticking out of it are cut as a whole, leaving spaces for
+    /// the part outside, so that the text on either side stays in its columns.
+    /// changing puts a caret on every line reaching the block, to type on all of them
+    fn operate_on_block(&mut self, operator: Operator, lines: Range<usize>, columns: Range<usize>) -> Register {
+        let rows: Vec<String> = lines
+            .clone()
+            .filter_map(|line_index| self.buffer.lines.get(line_index))
+            .map(|line| line.text_in_columns(columns.clone(), &self.settings))
+            .collect();
+        let register = Register {
+            text: rows.join("\n"),
+            kind: SelectionKind::Block,
+        };
+        let top = Location {
+            grapheme_index: self.buffer.lines.get(lines.start).map_or(0, |line| {
+                line.grapheme_starting_at(columns.start, &self.settings)
+            }),
+            line_index: lines.start,
+        };
+        if operator == Operator::Yank {
+            self.location = top;
+        } else {
+            for line_index in lines.clone() {
+                let Some(line) = self.buffer.lines.get(line_index) else {
+                    break;
+                };
+                let (graphemes, before, after) = line.graphemes_in_columns(columns.clone(), &self.settings);
+                let start = Location { grapheme_index: graphemes.start, line_index };
+                self.buffer.delete_range(start, Location { grapheme_index: graphemes.end, line_index });
+                if before + after > 0 {
+                    self.buffer.insert_str(start, &" ".repeat(before + after));
+                }
+            }
+            self.location = top;
+            if operator == Operator::Change {
+                self.put_block_carets(lines, columns.start, false);
+            }
+        }
+        self.snap_to_valid_grapheme();
+        self.scroll_screen();
+        self.need_redraw = true;
+        register
+    }
+    /// `I` and `A` on a block, type on every line of it
+    fn block_insert(&mut self, append: bool) {
+        let Some((lines, columns)) = self.block_bounds() else {
+            return;
+        };
+        self.clear_selection();
+        let column = if append { columns.end } else { columns.start };
+        self.put_block_carets(lines, column, append);
+        self.scroll_screen();
+    }
+    /// put the caret on the first of given lines at a screen column, and extra carets on the others.
+    /// lines too short to reach the column are skipped, or padded with spaces when `pad`
+    fn put_block_carets(&mut self, lines: Range<usize>, column: usize, pad: bool) {
+        let mut carets = Vec::new();
+        for line_index in lines {
+            let Some(line) = self.buffer.lines.get(line_index) else {
+                break;
+            };
+            let width = line.width_until(line.grapheme_len(), &self.settings);
+            if width < column {
+                if !pad {
+                    continue;
+                }
+                let end = Location { grapheme_index: line.grapheme_len(), line_index };
+                self.buffer.insert_str(end, &" ".repeat(column - width));
+            }
+            let grapheme_index = self.buffer.lines[line_index].grapheme_starting_at(column, &self.settings);
+            carets.push(Location { grapheme_index, line_index });
+        }
+        if carets.is_empty() {
+            return;
+        }
+        self.location = carets.remove(0);
+        self.cursors = carets;
+        self.need_redraw = true;
+    }
+    /// insert the rows of a block on the lines from the caret down, all at the caret's column.
+    /// short lines are padded with spaces, missing lines are added
+    fn paste_block(&mut self, text: &str, before: bool) {
+        let Location { grapheme_index, line_index } = self.location;
+        let column = self.buffer.lines.get(line_index).map_or(0, |line| {
+            let index = if before { grapheme_index } else { min(grapheme_index + 1, line.grapheme_len()) };
+            line.width_until(index, &self.settings)
+        });
+        for (offset, row) in text.split('\n').enumerate() {
+            let line_index = line_index + offset;
+            if line_index >= self.buffer.total_lines() {
+                let end = self.buffer.end_location();
+                self.buffer.insert_str(end, "\n");
+            }
+            let line = &self.buffer.lines[line_index];
+            let width = line.width_until(line.grapheme_len(), &self.settings);
+            let mut at = Location { grapheme_index: line.grapheme_starting_at(column, &self.settings), line_index };
+            if width < column {
+                at.grapheme_index = line.grapheme_len();
+                at = self.buffer.insert_str(at, &" ".repeat(column - width));
+            }
+            self.buffer.insert_str(at, row);
+        }
+        self.location = Location {
+            grapheme_index: self.buffer.lines[line_index].grapheme_starting_at(column, &self.settings),
+            line_index,
+        };
+        self.scroll_screen();
         self.need_redraw = true;
     }
 
     // region: scrolling
 
+    /// move the screen by a line, a page or to either end of the file, like a pager.
+    /// the caret goes to the top line shown, or to the last line once the end of the file is shown
+    fn scroll(&mut self, direction: Direction) {
+        let page = if self.settings.scroll == 0 { self.size.height.saturating_sub(1) } else { self.settings.scroll };
+        let top = self.offset.row;
+        let top = match direction {
+            Direction::Up => top.saturating_sub(1),
+            Direction::Down => top.saturating_add(1),
+            Direction::PageUp => top.saturating_sub(page),
+            Direction::PageDown => top.saturating_add(page),
+            Direction::FileStart => 0,
+            Direction::FileEnd => usize::MAX,
+            _ => return,
+        };
+        let last_top = self.last_top_line();
+        let downward = matches!(direction, Direction::Down | Direction::PageDown | Direction::FileEnd);
+        let line_index = if downward && top >= last_top {
+            self.buffer.total_lines().saturating_sub(1)
+        } else {
+            min(top, last_top)
+        };
+        self.show_at_top(Location { grapheme_index: 0, line_index });
+    }
+    /// scroll so that the line of given location is at the top of the screen, as far as the end
+    /// of the file allows, and put the caret there
+    pub fn show_at_top(&mut self, location: Location) {
+        self.single_cursor();
+        self.location = location;
+        self.snap_to_valid_line();
+        self.snap_to_valid_grapheme();
+        self.offset.row = min(self.location.line_index, self.last_top_line());
+        if !self.settings.wrap {
+            let col = self.loc_to_pos().col;
+            self.scroll_horizontal(col);
+        }
+        self.need_redraw = true;
+    }
+    /// the first line shown when the screen ends with the last line
+    fn last_top_line(&self) -> usize {
+        let mut top = self.buffer.total_lines();
+        let mut used = 0;
+        while top > 0 {
+            let rows = if self.settings.wrap { self.rows_of_line(top - 1) } else { 1 };
+            if used + rows > self.size.height {
+                break;
+            }
+            used += rows;
+            top -= 1;
+        }
+        top.min(self.buffer.total_lines().saturating_sub(1))
+    }
+
+    /// scroll so that the caret sits in the middle of the screen
+    fn center_screen(&mut self) {
+        let Size { height, width } = self.text_size();
+        self.need_redraw = true;
+        if self.settings.wrap {
+            self.offset.row = self.top_line_for(height / 2 + 1);
+            return;
+        }
+        let Position { col, row } = self.loc_to_pos();
+        self.offset.row = row.saturating_sub(height / 2);
+        self.offset.col = if col < width { 0 } else { col - width / 2 };
+    }
+
     /// judge if the cursor is out of view's bound
     fn scroll_screen(&mut self) {
+        if self.settings.wrap {
+            self.scroll_wrapped();
+            return;
+        }
         let Position { col, row } = self.loc_to_pos();
         self.scroll_horizontal(col);
         self.scroll_vertical(row);
     }
     fn scroll_horizontal(&mut self, to: usize) {
-        let Size { width, .. } = self.size;
+        let Size { width, .. } = self.text_size();
         let out_of_bound = if to < self.offset.col {
             self.offset.col = to;
             true
@@ -130,6 +1621,50 @@ impl View {
         self.need_redraw |= out_of_bound;
     }
 
+    /// when wrapping, lines are scrolled as a whole and never horizontally
+    fn scroll_wrapped(&mut self) {
+        let line_index = self.location.line_index;
+        let top = if line_index < self.offset.row {
+            line_index
+        } else {
+            self.offset.row.max(self.top_line_for(self.size.height))
+        };
+        self.need_redraw |= top != self.offset.row || self.offset.col != 0;
+        self.offset = Offset { row: top, col: 0 };
+    }
+    /// the first line to show, so that the caret ends up in given screen row (1-based) when wrapping.
+    /// as far as possible from the caret, but the caret's row stays visible
+    fn top_line_for(&self, rows: usize) -> usize {
+        let mut used = self.wrapped_pos_in_line().row + 1;
+        let mut top = self.location.line_index;
+        while top > 0 {
+            let above = self.rows_of_line(top - 1);
+            if used + above > rows {
+                break;
+            }
+            used += above;
+            top -= 1;
+        }
+        top
+    }
+    /// screen rows a line takes when wrapping
+    fn rows_of_line(&self, line_index: usize) -> usize {
+        self.buffer.lines.get(line_index).map_or(1, |line| {
+            line.wrap_starts(self.text_size().width, &self.settings).len()
+        })
+    }
+    /// the caret's row within its wrapped line, and its column within that row
+    fn wrapped_pos_in_line(&self) -> Position {
+        let Location { grapheme_index, line_index } = self.location;
+        self.buffer.lines.get(line_index).map_or_else(Position::default, |line| {
+            let starts = line.wrap_starts(self.text_size().width, &self.settings);
+            let row = starts.iter().rposition(|start| *start <= grapheme_index).unwrap_or(0);
+            let col = line.width_until(grapheme_index, &self.settings)
+                - line.width_until(starts[row], &self.settings);
+            Position { col, row }
+        })
+    }
+
     // region: location & position handling
 
     /// convert grapheme index to caret position.
@@ -138,12 +1673,24 @@ impl View {
     fn loc_to_pos(&self) -> Position {
         let row = self.location.line_index;
         let col = self.buffer.lines.get(row).map_or(0, |line| {
-            line.width_until(self.location.grapheme_index)
+            line.width_until(self.location.grapheme_index, &self.settings)
         });
         Position { row, col }
     }
     pub fn caret_position(&self) -> Position {
-        self.loc_to_pos().subtract(&self.offset)
+        let gutter = self.gutter_width();
+        if !self.settings.wrap {
+            let Position { col, row } = self.loc_to_pos().subtract(&self.offset);
+            return Position { col: col + gutter, row };
+        }
+        let Position { col, row } = self.wrapped_pos_in_line();
+        let above: usize = (self.offset.row..self.location.line_index)
+            .map(|line_index| self.rows_of_line(line_index))
+            .sum();
+        Position {
+            col: gutter + min(col, self.text_size().width.saturating_sub(1)),
+            row: above + row,
+        }
     }
 
     // region: text location movement
@@ -152,18 +1699,52 @@ impl View {
     /// due to the introduction of graphemes, we need to adjust the cursor to navigate among graphemes
     pub fn move_location(&mut self, direction: Direction) {
         let Size { height, .. } = self.size;
+        let page = if self.settings.scroll == 0 { height.saturating_sub(1) } else { self.settings.scroll };
         match direction {
             Direction::Up => self.move_up(1),
             Direction::Down => self.move_down(1),
             Direction::Left => self.move_left(),
             Direction::Right => self.move_right(),
-            Direction::PageUp => self.move_up(height - 1),
-            Direction::PageDown => self.move_down(height - 1),
+            Direction::PageUp => self.move_up(page),
+            Direction::PageDown => self.move_down(page),
             Direction::Home => self.move_to_line_start(),
             Direction::End => self.move_to_line_end(),
+            Direction::StepLeft => self.location.grapheme_index = self.location.grapheme_index.saturating_sub(1),
+            Direction::StepRight => {
+                if self.location.grapheme_index < self.line_len(self.location.line_index) {
+                    self.location.grapheme_index += 1;
+                }
+            }
+            Direction::WordForward => self.location = self.buffer.next_word_start(self.location),
+            Direction::WordBackward => self.location = self.buffer.prev_word_start(self.location),
+            Direction::WordEnd => self.location = self.buffer.next_word_end(self.location),
+            Direction::FileStart => self.location = Location::default(),
+            Direction::FileEnd => {
+                self.location = Location {
+                    line_index: self.buffer.total_lines().saturating_sub(1),
+                    grapheme_index: 0,
+                };
+            }
+            Direction::MatchingBracket => {
+                if let Some(location) = self.matching_bracket() {
+                    self.location = location;
+                }
+            }
+        }
+        if self.selection.is_some() {
+            self.need_redraw = true;
         }
         self.scroll_screen();
     }
+    /// the bracket matching the one at the caret, or the first one after it in the line, like vi's `%`
+    fn matching_bracket(&mut self) -> Option<Location> {
+        let Location { grapheme_index, line_index } = self.location;
+        let line = self.buffer.lines.get(line_index)?;
+        let start = (grapheme_index..line.grapheme_len())
+            .find(|index| line.grapheme_at(*index).is_some_and(bracket::is_bracket))?;
+        let at = Location { grapheme_index: start, line_index };
+        bracket::find_match(&self.buffer.lines, &self.syntax, &mut self.buffer.syntax_states, at)
+    }
     fn move_up(&mut self, step: usize) {
         self.location.line_index = self.location.line_index.saturating_sub(step);
         self.snap_to_valid_grapheme();
@@ -176,7 +1757,7 @@ impl View {
     fn move_left(&mut self) {
         if self.location.grapheme_index > 0 {
             self.location.grapheme_index -= 1;
-        } else {
+        } else if self.location.line_index > 0 {
             self.move_up(1);
             self.move_to_line_end();
         }
@@ -217,21 +1798,505 @@ impl View {
         self.location.line_index = min(self.location.line_index, self.buffer.total_lines())
     }
     
-    /// returns a string including project name and version
-    fn welcome_message(width: usize) -> String {
+    /// returns the welcome message from the settings, centered
+    fn welcome_message(msg: &str, width: usize) -> String {
         if width == 0 {
             return " ".to_string();
         }
-        let msg = format!("{NAME} -- version {VERSION}");
-        let len = msg.len();
+        let len = msg.width();
         if width <= len {
             return "~".to_string();
         }
 
         let padding = (width - len) / 2;
-        let mut ret = format!("~{}{}", " ".repeat(padding), msg);
-        ret.truncate(width);
+        clip(&format!("~{}{}", " ".repeat(padding), msg), width)
+    }
+}
 
-        ret
+/// the start of a text that fits in given columns
+fn clip(text: &str, width: usize) -> String {
+    let mut used = 0;
+    text.graphemes(true)
+        .take_while(|grapheme| {
+            used += grapheme.width();
+            used <= width
+        })
+        .collect()
+}
+
+/// segments of a screen row with a text put over them from given column, like a popup.
+/// a wide grapheme cut by the text is replaced by spaces
+fn overlay(
+    segments: &[(String, Option<Highlight>)],
+    column: usize,
+    text: (String, Option<Highlight>),
+) -> Vec<(String, Option<Highlight>)> {
+    let end = column + text.0.width();
+    let mut before = Vec::new();
+    let mut after = Vec::new();
+    let mut pos = 0;
+    for (segment, highlight) in segments {
+        let (mut left, mut right) = (String::new(), String::new());
+        for grapheme in segment.graphemes(true) {
+            let width = grapheme.width();
+            if pos + width <= column {
+                left.push_str(grapheme);
+            } else if pos >= end {
+                right.push_str(grapheme);
+            } else if pos < column {
+                left.push_str(&" ".repeat(column - pos));
+            } else if pos + width > end {
+                right.push_str(&" ".repeat(pos + width - end));
+            }
+            pos += width;
+        }
+        before.push((left, *highlight));
+        after.push((right, *highlight));
+    }
+    if pos < column {
+        before.push((" ".repeat(column - pos), None));
+    }
+    before.push(text);
+    before.extend(after);
+    before.retain(|(segment, _)| !segment.is_empty());
+    before
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::settings::SettingsFile;
+
+    fn at(line_index: usize, grapheme_index: usize) -> Location {
+        Location { grapheme_index, line_index }
+    }
+
+    fn view(texts: &[&str]) -> View {
+        View::scratch(&texts.iter().map(ToString::to_string).collect::<Vec<_>>())
+    }
+
+    fn texts(view: &View) -> Vec<String> {
+        view.line_texts(0..view.buffer.total_lines())
+    }
+
+    /// handle commands as one change, like the editor does for each key
+    fn run(view: &mut View, commands: &[Command]) {
+        view.begin_change();
+        for command in commands {
+            view.command_handler(command.clone());
+        }
+        view.end_change();
+    }
+
+    /// every caret in order, the main one included
+    fn carets(view: &View) -> Vec<(usize, usize)> {
+        view.carets().iter().map(|caret| (caret.line_index, caret.grapheme_index)).collect()
+    }
+
+    // region: multiple cursors
+
+    #[test]
+    fn carets_on_the_same_line_type_and_delete_together() {
+        let mut view = view(&["ab ab"]);
+        view.add_caret(at(0, 3));
+        run(&mut view, &[Command::Insert('x')]);
+        assert_eq!(texts(&view), ["xab xab"]);
+        assert_eq!(carets(&view), [(0, 1), (0, 5)]);
+        run(&mut view, &[Command::Insert('y'), Command::Move(Direction::Right), Command::Delete]);
+        assert_eq!(texts(&view), ["xya xya"]);
+        assert_eq!(carets(&view), [(0, 3), (0, 7)]);
+        run(&mut view, &[Command::DeleteBackward, Command::DeleteBackward]);
+        assert_eq!(texts(&view), ["x x"]);
+        assert_eq!(carets(&view), [(0, 1), (0, 3)]);
+        // each key is one step, whatever the number of carets
+        assert!(view.undo());
+        assert_eq!(texts(&view), ["xya xya"]);
+    }
+
+    #[test]
+    fn carets_on_adjacent_lines_type_and_delete_together() {
+        let mut view = view(&["a", "b"]);
+        view.goto(at(0, 1));
+        view.command_handler(Command::AddCursor(Direction::Down));
+        assert_eq!(carets(&view), [(0, 1), (1, 1)]);
+        run(&mut view, &[Command::InsertNewline]);
+        assert_eq!(texts(&view), ["a", "", "b", ""]);
+        assert_eq!(carets(&view), [(1, 0), (3, 0)]);
+        run(&mut view, &[Command::Insert('!')]);
+        assert_eq!(texts(&view), ["a", "!", "b", "!"]);
+        run(&mut view, &[Command::Move(Direction::Home), Command::DeleteBackward]);
+        assert_eq!(texts(&view), ["a!", "b!"]);
+        assert_eq!(carets(&view), [(0, 1), (1, 1)]);
+        // joining lines takes the caret of the line below along
+        run(&mut view, &[Command::Move(Direction::Home), Command::DeleteBackward]);
+        assert_eq!(texts(&view), ["a!b!"]);
+        assert_eq!(carets(&view), [(0, 0), (0, 2)]);
+    }
+
+    #[test]
+    fn carets_meeting_on_one_spot_merge() {
+        let mut view = view(&["abc"]);
+        view.goto(at(0, 1));
+        view.add_caret(at(0, 2));
+        run(&mut view, &[Command::Move(Direction::Left)]);
+        assert_eq!(carets(&view), [(0, 0), (0, 1)]);
+        run(&mut view, &[Command::Move(Direction::Left)]);
+        assert_eq!(carets(&view), [(0, 0)]);
+
+        let mut other = super::tests::view(&["abc"]);
+        other.goto(at(0, 1));
+        other.add_caret(at(0, 2));
+        run(&mut other, &[Command::DeleteBackward]);
+        assert_eq!(texts(&other), ["c"]);
+        assert_eq!(carets(&other), [(0, 0)]);
+        // one caret is left, so keys act there only
+        run(&mut other, &[Command::Insert('x')]);
+        assert_eq!(texts(&other), ["xc"]);
+    }
+
+    #[test]
+    fn next_occurrences_of_the_word_get_a_caret() {
+        let mut view = view(&["foo bar foo", "foofoo foo"]);
+        view.goto(at(0, 1));
+        view.command_handler(Command::AddNextOccurrence);
+        assert_eq!(carets(&view), [(0, 0), (0, 8)]);
+        view.command_handler(Command::AddNextOccurrence);
+        assert_eq!(carets(&view), [(0, 0), (0, 8), (1, 7)]);
+        // all found, wrapping around adds nothing
+        view.command_handler(Command::AddNextOccurrence);
+        assert_eq!(carets(&view), [(0, 0), (0, 8), (1, 7)]);
+        run(&mut view, &[Command::Insert('_')]);
+        assert_eq!(texts(&view), ["_foo bar _foo", "foofoo _foo"]);
+    }
+
+    #[test]
+    fn next_occurrences_of_the_selection_get_a_caret() {
+        let mut view = view(&["foo bar foo", "foofoo foo"]);
+        view.goto(at(0, 1));
+        run(&mut view, &[Command::StartSelection(SelectionKind::Characters), Command::Move(Direction::Right)]);
+        view.command_handler(Command::AddNextOccurrence);
+        assert_eq!(carets(&view), [(0, 1), (0, 9)]);
+        run(&mut view, &[Command::Delete]);
+        assert_eq!(texts(&view), ["fo bar fo", "foofoo foo"]);
+    }
+
+    /// select a block from the anchor to the caret
+    fn select_block(view: &mut View, anchor: Location, caret: Location) {
+        view.selection = Some(Selection { anchor, kind: SelectionKind::Block });
+        view.location = caret;
+    }
+
+    // region: block selection
+
+    #[test]
+    fn blocks_are_copied_by_columns() {
+        let mut view = view(&["abcd", "efgh", "ij"]);
+        select_block(&mut view, at(0, 1), at(2, 2));
+        let register = view.operate_selection(Operator::Yank).unwrap();
+        assert_eq!(register.text, "bc\nfg\nj");
+        assert!(register.kind == SelectionKind::Block);
+        assert_eq!(texts(&view), ["abcd", "efgh", "ij"]);
+        assert!(view.location() == at(0, 1));
+    }
+
+    #[test]
+    fn deleting_a_block_keeps_the_text_beside_it_in_its_columns() {
+        let mut view = view(&["x\tyz", "abcdefg"]);
+        select_block(&mut view, at(1, 2), at(0, 2));
+        run(&mut view, &[Command::Delete]);
+        // the tab sticking out of the block leaves a space for the column before it
+        assert_eq!(texts(&view), ["x z", "abfg"]);
+        assert!(view.undo());
+        assert_eq!(texts(&view), ["x\tyz", "abcdefg"]);
+
+        let mut view = super::tests::view(&["abcd", "a\u{4e2d}b"]);
+        select_block(&mut view, at(0, 2), at(1, 2));
+        let register = view.operate_selection(Operator::Delete).unwrap();
+        assert_eq!(register.text, "cd\n b");
+        assert_eq!(texts(&view), ["ab", "a "]);
+    }
+
+    #[test]
+    fn typing_replaces_the_block_on_every_line() {
+        let mut view = view(&["abc", "def"]);
+        select_block(&mut view, at(0, 1), at(1, 1));
+        run(&mut view, &[Command::Insert('X'), Command::Insert('Y')]);
+        assert_eq!(texts(&view), ["aXYc", "dXYf"]);
+        assert_eq!(carets(&view), [(0, 3), (1, 3)]);
+        assert!(view.undo());
+        assert_eq!(texts(&view), ["abc", "def"]);
+    }
+
+    #[test]
+    fn inserting_before_a_block_skips_short_lines() {
+        let mut view = view(&["abc", "", "abc"]);
+        select_block(&mut view, at(0, 1), at(2, 1));
+        view.command_handler(Command::BlockInsert { append: false });
+        assert_eq!(carets(&view), [(0, 1), (2, 1)]);
+        run(&mut view, &[Command::Insert('X')]);
+        assert_eq!(texts(&view), ["aXbc", "", "aXbc"]);
+    }
+
+    #[test]
+    fn appending_after_a_block_pads_short_lines() {
+        let mut view = view(&["abc", "a", "abc"]);
+        select_block(&mut view, at(0, 1), at(2, 1));
+        view.command_handler(Command::BlockInsert { append: true });
+        assert_eq!(carets(&view), [(0, 2), (1, 2), (2, 2)]);
+        run(&mut view, &[Command::Insert('X')]);
+        assert_eq!(texts(&view), ["abXc", "a X", "abXc"]);
+    }
+
+    #[test]
+    fn pasted_blocks_go_on_the_lines_below() {
+        let mut view = view(&["abc", "a"]);
+        let register = Register { text: "12\n34\n56".to_string(), kind: SelectionKind::Block };
+        view.goto(at(0, 0));
+        view.paste(&register, false);
+        assert_eq!(texts(&view), ["a12bc", "a34", " 56"]);
+    }
+
+    // region: auto pairs
+
+    #[test]
+    fn openers_are_closed_and_closers_stepped_over() {
+        let mut view = view(&[""]);
+        run(&mut view, &[Command::Insert('('), Command::Insert('x')]);
+        assert_eq!(texts(&view), ["(x)"]);
+        assert!(view.location() == at(0, 2));
+        run(&mut view, &[Command::Insert(')')]);
+        assert_eq!(texts(&view), ["(x)"]);
+        assert!(view.location() == at(0, 3));
+        run(&mut view, &[Command::Insert('"'), Command::Insert('"')]);
+        assert_eq!(texts(&view), ["(x)\"\""]);
+        assert!(view.location() == at(0, 5));
+    }
+
+    #[test]
+    fn pairs_are_closed_only_before_blanks_and_closers() {
+        let mut view = view(&["x", "]", "it"]);
+        run(&mut view, &[Command::Insert('(')]);
+        assert_eq!(texts(&view), ["(x", "]", "it"]);
+        view.goto(at(1, 0));
+        run(&mut view, &[Command::Insert('[')]);
+        assert_eq!(texts(&view), ["(x", "[]]", "it"]);
+        // right after a word, a quote is an apostrophe
+        view.goto(at(2, 2));
+        run(&mut view, &[Command::Insert('\''), Command::Insert(' '), Command::Insert('\'')]);
+        assert_eq!(texts(&view), ["(x", "[]]", "it' ''"]);
+    }
+
+    #[test]
+    fn backspace_deletes_an_empty_pair() {
+        let mut view = view(&[""]);
+        run(&mut view, &[Command::Insert('['), Command::Insert('{'), Command::Insert('x')]);
+        assert_eq!(texts(&view), ["[{x}]"]);
+        run(&mut view, &[Command::DeleteBackward]);
+        assert_eq!(texts(&view), ["[{}]"]);
+        run(&mut view, &[Command::DeleteBackward]);
+        assert_eq!(texts(&view), ["[]"]);
+        run(&mut view, &[Command::DeleteBackward]);
+        assert_eq!(texts(&view), [""]);
+    }
+
+    #[test]
+    fn pairs_follow_the_file_type() {
+        let mut view = view(&[""]);
+        view.set_settings(SettingsFile::default().for_file(Some("main.rs")));
+        // `'` starts lifetimes in rust
+        run(&mut view, &[Command::Insert('\''), Command::Insert('"')]);
+        assert_eq!(texts(&view), ["'\"\""]);
+
+        let mut view = super::tests::view(&[""]);
+        view.set_settings(SettingsFile::default().for_file(Some("main.c")));
+        run(&mut view, &[Command::Insert('\'')]);
+        assert_eq!(texts(&view), ["''"]);
+
+        let mut view = super::tests::view(&[""]);
+        view.set_settings(Settings { auto_pairs: String::new(), ..Settings::default() });
+        run(&mut view, &[Command::Insert('('), Command::Insert(')')]);
+        assert_eq!(texts(&view), ["()"]);
+        assert!(view.location() == at(0, 2));
+    }
+
+    // region: line operations
+
+    #[test]
+    fn duplicated_lines_take_the_caret_along() {
+        let mut view = view(&["a", "b", "c"]);
+        view.goto(at(1, 0));
+        run(&mut view, &[Command::DuplicateLines]);
+        assert_eq!(texts(&view), ["a", "b", "b", "c"]);
+        assert!(view.location() == at(2, 0));
+
+        run(&mut view, &[Command::Goto(at(0, 0)), Command::StartSelection(SelectionKind::Lines), Command::Move(Direction::Down)]);
+        run(&mut view, &[Command::DuplicateLines]);
+        assert_eq!(texts(&view), ["a", "b", "a", "b", "b", "c"]);
+        assert!(view.location().line_index == 3);
+        assert!(view.undo());
+        assert_eq!(texts(&view), ["a", "b", "b", "c"]);
+        assert!(view.undo());
+        assert_eq!(texts(&view), ["a", "b", "c"]);
+        assert!(!view.undo());
+    }
+
+    #[test]
+    fn moved_lines_take_the_caret_along() {
+        let mut view = view(&["a", "bb", "c"]);
+        view.goto(at(1, 1));
+        run(&mut view, &[Command::MoveLines(Direction::Down)]);
+        assert_eq!(texts(&view), ["a", "c", "bb"]);
+        assert!(view.location() == at(2, 1));
+        // at the bottom already, nothing to undo
+        run(&mut view, &[Command::MoveLines(Direction::Down)]);
+        run(&mut view, &[Command::MoveLines(Direction::Up)]);
+        run(&mut view, &[Command::MoveLines(Direction::Up)]);
+        assert_eq!(texts(&view), ["bb", "a", "c"]);
+        assert!(view.location() == at(0, 1));
+        assert!(view.undo());
+        assert_eq!(texts(&view), ["a", "bb", "c"]);
+        assert!(view.undo());
+        assert!(view.undo());
+        assert_eq!(texts(&view), ["a", "bb", "c"]);
+        assert!(!view.undo());
+    }
+
+    #[test]
+    fn deleted_lines_leave_the_caret_on_the_next_one() {
+        let mut view = view(&["a", "bb", "c"]);
+        view.goto(at(1, 1));
+        run(&mut view, &[Command::DeleteLines]);
+        assert_eq!(texts(&view), ["a", "c"]);
+        assert!(view.location() == at(1, 0));
+        run(&mut view, &[Command::DeleteLines]);
+        assert_eq!(texts(&view), ["a"]);
+        assert!(view.location() == at(0, 0));
+        assert!(view.undo());
+        assert_eq!(texts(&view), ["a", "c"]);
+        assert!(view.undo());
+        assert_eq!(texts(&view), ["a", "bb", "c"]);
+        assert!(view.location() == at(1, 1));
+    }
+
+    #[test]
+    fn joined_lines_leave_the_caret_where_they_met() {
+        let mut view = view(&["a  ", "  b", "c"]);
+        run(&mut view, &[Command::JoinLines]);
+        assert_eq!(texts(&view), ["a b", "c"]);
+        assert!(view.location() == at(0, 1));
+        run(&mut view, &[Command::JoinLines]);
+        assert_eq!(texts(&view), ["a b c"]);
+        assert!(view.location() == at(0, 3));
+        assert!(view.undo());
+        assert_eq!(texts(&view), ["a b", "c"]);
+        assert!(view.undo());
+        assert_eq!(texts(&view), ["a  ", "  b", "c"]);
+        assert!(!view.undo());
+    }
+
+    // region: diagnostics
+
+    fn problem(start: Location, end: Location, severity: Severity, message: &str) -> Diagnostic {
+        Diagnostic { start, end, severity, code: None, message: message.to_string(), source: "make".to_string() }
+    }
+
+    /// the start line and message of every problem, in order
+    fn problems(view: &View) -> Vec<(usize, String)> {
+        view.diagnostics().iter().map(|diagnostic| (diagnostic.start.line_index, diagnostic.message.clone())).collect()
+    }
+
+    /// the text and theme element of each segment showing something
+    fn elements(segments: &[(String, Option<Highlight>)]) -> Vec<(&str, &str)> {
+        segments
+            .iter()
+            .filter(|(text, _)| !text.is_empty())
+            .map(|(text, highlight)| (text.as_str(), highlight.map_or("", Highlight::element)))
+            .collect()
+    }
+
+    #[test]
+    fn problems_move_with_their_lines_and_go_with_edited_ones() {
+        let mut view = view(&["a", "b", "c"]);
+        view.set_diagnostics(
+            "make",
+            vec![problem(at(1, 0), at(1, 1), Severity::Error, "b"), problem(at(2, 0), at(2, 1), Severity::Warning, "c")],
+        );
+        run(&mut view, &[Command::InsertNewline]);
+        assert_eq!(texts(&view), ["", "a", "b", "c"]);
+        assert_eq!(problems(&view), [(2, "b".to_string()), (3, "c".to_string())]);
+        view.goto(at(2, 1));
+        run(&mut view, &[Command::Insert('!')]);
+        assert_eq!(problems(&view), [(3, "c".to_string())]);
+        // undoing moves the problems left back up, the dropped one stays dropped
+        assert!(view.undo());
+        assert!(view.undo());
+        assert_eq!(texts(&view), ["a", "b", "c"]);
+        assert_eq!(problems(&view), [(2, "c".to_string())]);
+        // reported again, only the new ones count
+        view.set_diagnostics("make", vec![problem(at(0, 0), at(0, 1), Severity::Hint, "a")]);
+        assert_eq!(problems(&view), [(0, "a".to_string())]);
+    }
+
+    #[test]
+    fn the_sign_column_marks_the_worst_problem_on_the_first_row_of_its_line() {
+        let mut view = view(&["a", "b"]);
+        assert!(view.sign(0, true).is_empty());
+        view.set_diagnostics(
+            "make",
+            vec![problem(at(0, 0), at(0, 1), Severity::Warning, "w"), problem(at(0, 0), at(0, 0), Severity::Error, "e")],
+        );
+        assert_eq!(elements(&view.sign(0, true)), [("E ", "error")]);
+        assert_eq!(elements(&view.sign(0, false)), [("  ", "gutter")]);
+        assert_eq!(elements(&view.sign(1, true)), [("  ", "gutter")]);
+    }
+
+    #[test]
+    fn problems_underline_what_they_cover() {
+        let mut view = view(&["let x = 1", "y"]);
+        view.settings.virtual_text = false;
+        view.set_diagnostics(
+            "make",
+            vec![
+                problem(at(0, 4), at(0, 5), Severity::Warning, "unused"),
+                // a spot between graphemes underlines the one after it
+                problem(at(0, 8), at(0, 8), Severity::Error, "wrong"),
+                // after the last grapheme, like a missing `;`
+                problem(at(1, 1), at(1, 1), Severity::Error, "missing"),
+            ],
+        );
+        assert_eq!(
+            elements(&view.text_row(0, 0..20, &[])),
+            [("let ", ""), ("x", "warning_underline"), (" = ", ""), ("1", "error_underline")]
+        );
+        assert_eq!(elements(&view.text_row(1, 0..20, &[])), [("y", ""), (" ", "error_underline")]);
+    }
+
+    #[test]
+    fn messages_follow_their_lines_or_pop_up_under_the_caret() {
+        let mut view = view(&["ab"]);
+        view.set_diagnostics("make", vec![problem(at(0, 0), at(0, 1), Severity::Error, "bad\nmore")]);
+        assert_eq!(
+            elements(&view.text_row(0, 0..10, &[])),
+            [("a", "error_underline"), ("b", ""), ("  bad", "error")]
+        );
+        // clipped to the row
+        assert_eq!(elements(&view.text_row(0, 0..5, &[])), [("a", "error_underline"), ("b", ""), ("  b", "error")]);
+        assert!(view.popup_diagnostic().is_none());
+
+        view.settings.virtual_text = false;
+        assert_eq!(elements(&view.text_row(0, 0..10, &[])), [("a", "error_underline"), ("b", "")]);
+        assert_eq!(view.popup_diagnostic().map(|(index, _)| index), Some(0));
+        view.goto(at(0, 1));
+        assert!(view.popup_diagnostic().is_none());
+    }
+
+    // region: welcome message
+
+    #[test]
+    fn the_welcome_message_is_centred_by_its_width_on_screen() {
+        assert_eq!(View::welcome_message("hi", 10), "~    hi");
+        // two columns each, though three bytes
+        assert_eq!(View::welcome_message("日本", 10), "~   日本");
+        assert_eq!(View::welcome_message("日本語", 6), "~");
     }
 }
@@ -1,5 +1,4 @@
 #[warn(missing_docs)]
-
 mod editor;
 
 use editor::Editor;